@@ -1,50 +1,135 @@
+use crate::config::embedded::{CONFIG_FILE_NAME, LOCAL_CONFIG_FILE_NAME, find_config};
+use crate::config::env_rules::{env_rules_path, env_rules_value};
+use crate::config::mutes::MutedRules;
 use crate::config::parser::parse_config_file;
-use crate::config::types::{LoadedConfig, MergedConfig, RuleWithSource};
+use crate::config::templates::resolve_templates;
+use crate::config::types::{
+	CascadeEnd, Config, ConfigDiscovery, EnvRulesDecision, LoadedConfig, MergedConfig, RuleGroup,
+	RuleWithSource, SearchedLocation, SkipReason, SkippedRule, UserConfigDecision, VarWithSource,
+};
 use crate::error::{Result, TrampError};
+use jiff::civil::DateTime;
 use std::path::{Path, PathBuf};
 
 /// Discover and load all config files in the cascade.
 ///
 /// The cascade order is:
-/// 1. Start from `start_dir` and look for `.tramp.toml`
+/// 1. Start from `start_dir` and look for `.tramp.toml`, or else a config
+///    embedded in `Cargo.toml` or `package.json` (see
+///    [`EMBEDDED_SOURCES`](crate::config::embedded::EMBEDDED_SOURCES))
+///    A `.tramp.local.toml` next to it is loaded first, taking priority,
+///    unless the config sets `no-external-lookup = true`
 /// 2. If found and `root = true`, skip to user config only
 /// 3. Otherwise, continue up the directory tree
 /// 4. Finally, check ~/.tramp.toml (unless disabled)
 ///
-/// Returns configs in cascade order (most specific first).
-pub fn discover_configs(start_dir: &Path) -> Result<Vec<LoadedConfig>> {
+/// Rules in the `TRAMP_RULES` environment variable come before all of these,
+/// unless the user config sets `no-env-rules = true`.
+///
+/// Returns a report of every location searched and why the walk ended, along
+/// with the loaded configs in cascade order (most specific first). Rules that
+/// `use` templates come back with their templates applied.
+pub fn discover_configs(start_dir: &Path) -> Result<ConfigDiscovery> {
+	let mut discovery = discover_configs_with(start_dir, &mut parse_config_file)?;
+	resolve_templates(&mut discovery.configs)?;
+	Ok(discovery)
+}
+
+/// Walk the cascade like [`discover_configs`], loading each found file with `load`.
+/// Templates are left unresolved.
+///
+/// This lets callers such as `tramp config validate` collect problems from
+/// every file instead of stopping at the first error.
+pub fn discover_configs_with(
+	start_dir: &Path,
+	load: &mut dyn FnMut(&Path) -> Result<Config>,
+) -> Result<ConfigDiscovery> {
 	let mut configs = Vec::new();
+	let mut searched = Vec::new();
 	let mut current_dir = start_dir.to_path_buf();
-	let mut skip_cascade = false;
+	let mut cascade_end = CascadeEnd::FilesystemRoot;
 
 	// Walk up the directory tree
 	loop {
-		let config_path = current_dir.join(".tramp.toml");
+		let standalone = current_dir.join(CONFIG_FILE_NAME);
+		let chosen = find_config(&current_dir);
+
+		searched.push(SearchedLocation {
+			path: standalone.clone(),
+			found: chosen.as_ref() == Some(&standalone),
+			ignored_by: None,
+		});
+
+		if let Some(config_path) = chosen {
+			if config_path != standalone {
+				searched.push(SearchedLocation {
+					path: config_path.clone(),
+					found: true,
+					ignored_by: None,
+				});
+			}
 
-		if config_path.exists() {
-			let config = parse_config_file(&config_path)?;
+			let config = load(&config_path)?;
+			let local_path = current_dir.join(LOCAL_CONFIG_FILE_NAME);
+			let has_local = local_path.exists();
 
 			// Check if external lookup is disabled
 			if config.no_external_lookup {
-				// Only use this config, skip everything else
+				// Only use this config, skip everything else, personal overrides included
+				if has_local {
+					searched.push(SearchedLocation {
+						path: local_path,
+						found: false,
+						ignored_by: Some(config_path.clone()),
+					});
+				}
 				configs.push(LoadedConfig {
 					config,
-					path: config_path,
+					path: config_path.clone(),
+				});
+				let env_rules = match env_rules_value() {
+					Some(_) => EnvRulesDecision::SkippedByNoExternalLookup {
+						set_by: config_path.clone(),
+					},
+					None => EnvRulesDecision::NotSet,
+				};
+				return Ok(ConfigDiscovery {
+					start_dir: start_dir.to_path_buf(),
+					searched,
+					cascade_end: CascadeEnd::NoExternalLookup {
+						path: config_path.clone(),
+					},
+					user_config: UserConfigDecision::SkippedByNoExternalLookup {
+						set_by: config_path,
+					},
+					env_rules,
+					configs,
 				});
-				return Ok(configs);
 			}
 
-			// Check if we should skip cascade and jump to user config
-			if config.root {
-				skip_cascade = true;
+			// Personal overrides take priority over the repository config next to them
+			if has_local {
+				searched.push(SearchedLocation {
+					path: local_path.clone(),
+					found: true,
+					ignored_by: None,
+				});
+				configs.push(LoadedConfig {
+					config: load(&local_path)?,
+					path: local_path,
+				});
 			}
 
+			// Check if we should skip cascade and jump to user config
+			let is_root = config.root;
+
 			configs.push(LoadedConfig {
 				config,
-				path: config_path,
+				path: config_path.clone(),
 			});
 
-			if skip_cascade {
+			if is_root {
+				cascade_end = CascadeEnd::Root { path: config_path };
 				break;
 			}
 		}
@@ -58,84 +143,245 @@ pub fn discover_configs(start_dir: &Path) -> Result<Vec<LoadedConfig>> {
 	}
 
 	// Check user config unless disabled by env var
-	if let Some(user_config) = load_user_config(&configs)? {
-		configs.push(user_config);
+	let (user_config, loaded) = load_user_config(&configs, load)?;
+	if let Some(loaded) = loaded {
+		configs.push(loaded);
 	}
 
-	Ok(configs)
+	let env_rules = load_env_rules(&mut configs, &user_config, load)?;
+
+	Ok(ConfigDiscovery {
+		start_dir: start_dir.to_path_buf(),
+		searched,
+		cascade_end,
+		user_config,
+		env_rules,
+		configs,
+	})
 }
 
 /// Load the user's ~/.tramp.toml if it exists and isn't disabled.
-fn load_user_config(existing_configs: &[LoadedConfig]) -> Result<Option<LoadedConfig>> {
+fn load_user_config(
+	existing_configs: &[LoadedConfig],
+	load: &mut dyn FnMut(&Path) -> Result<Config>,
+) -> Result<(UserConfigDecision, Option<LoadedConfig>)> {
 	// Check if any config disables user config lookup via env var
 	for loaded in existing_configs {
 		if let Some(ref env_var) = loaded.config.root_config_lookup_disable_env_var
 			&& is_env_truthy(env_var)
 		{
-			return Ok(None);
+			let decision = UserConfigDecision::DisabledByEnvVar {
+				env_var: env_var.clone(),
+				set_by: loaded.path.clone(),
+			};
+			return Ok((decision, None));
 		}
 	}
 
-	let home_dir = dirs::home_dir().ok_or(TrampError::HomeDirectoryNotFound)?;
-	let user_config_path = home_dir.join(".tramp.toml");
+	let user_config_path = user_config_path()?;
 
 	if user_config_path.exists() {
-		let config = parse_config_file(&user_config_path)?;
-		Ok(Some(LoadedConfig {
-			config,
-			path: user_config_path,
-		}))
+		let config = load(&user_config_path)?;
+		Ok((
+			UserConfigDecision::Loaded {
+				path: user_config_path.clone(),
+			},
+			Some(LoadedConfig {
+				config,
+				path: user_config_path,
+			}),
+		))
 	} else {
-		Ok(None)
+		Ok((
+			UserConfigDecision::NotFound {
+				path: user_config_path,
+			},
+			None,
+		))
 	}
 }
 
-/// Check if an environment variable is set to a truthy value.
-fn is_env_truthy(var_name: &str) -> bool {
-	match std::env::var(var_name) {
-		Ok(value) => {
-			let lower = value.to_lowercase();
-			!value.is_empty() && lower != "0" && lower != "false" && lower != "no"
-		}
-		Err(_) => false,
+/// Load the rules in `TRAMP_RULES` as the first layer of the cascade, unless
+/// the user config turns them off.
+fn load_env_rules(
+	configs: &mut Vec<LoadedConfig>,
+	user_config: &UserConfigDecision,
+	load: &mut dyn FnMut(&Path) -> Result<Config>,
+) -> Result<EnvRulesDecision> {
+	if env_rules_value().is_none() {
+		return Ok(EnvRulesDecision::NotSet);
+	}
+
+	// The user config is always last in the cascade
+	if let UserConfigDecision::Loaded { ref path } = *user_config
+		&& configs
+			.last()
+			.is_some_and(|loaded| loaded.config.no_env_rules)
+	{
+		return Ok(EnvRulesDecision::DisabledByUserConfig { path: path.clone() });
 	}
+
+	let path = env_rules_path();
+	configs.insert(
+		0,
+		LoadedConfig {
+			config: load(&path)?,
+			path,
+		},
+	);
+	Ok(EnvRulesDecision::Loaded)
+}
+
+/// Check if an environment variable is set to a truthy value.
+pub(crate) fn is_env_truthy(var_name: &str) -> bool {
+	std::env::var(var_name).is_ok_and(|value| is_truthy(&value))
+}
+
+/// Whether a setting's value counts as true: anything but empty, `0`, `false`, or `no`.
+pub(crate) fn is_truthy(value: &str) -> bool {
+	let lower = value.to_lowercase();
+	!value.is_empty() && lower != "0" && lower != "false" && lower != "no"
 }
 
 /// Merge multiple configs into a single effective config.
 ///
-/// Rules are collected in cascade order (first match wins).
+/// Rules are collected in cascade order (first match wins). Within a config,
+/// top-level rules come first, then the rules of each group in turn. Disabled
+/// rules are left out.
+/// Rules that have expired, are muted on this machine, or are outside their
+/// active hours are moved to `skipped`, as of the current local time.
 /// The `no_external_lookup` flag is set if any config has it.
+/// Scalar settings like `max-depth` and each variable come from the most specific
+/// config that sets them.
 pub fn merge_configs(configs: &[LoadedConfig]) -> MergedConfig {
+	let now = jiff::Zoned::now();
+	let mut muted = MutedRules::load();
+	muted.prune(now.timestamp());
+	merge_configs_at(configs, Some(now.datetime()), &muted)
+}
+
+/// Merge configs like [`merge_configs`], as of the local time `now` and with
+/// the rules in `muted` muted, whenever their mutes end.
+///
+/// Without `now`, `expires` and `active_between` are ignored, so the result
+/// doesn't depend on when it is computed.
+pub fn merge_configs_at(
+	configs: &[LoadedConfig],
+	now: Option<DateTime>,
+	muted: &MutedRules,
+) -> MergedConfig {
 	let mut merged = MergedConfig::default();
 
 	for loaded in configs {
 		// Collect rules with their source
-		for rule in &loaded.config.rules {
-			merged.rules.push(RuleWithSource {
-				rule: rule.clone(),
+		for (group, source_index, rule) in loaded.config.all_rules() {
+			if !rule.is_enabled() {
+				continue;
+			}
+			let rule_with_source = RuleWithSource {
 				source: loaded.path.clone(),
-			});
+				source_index,
+				rule: rule.clone(),
+				group_matchers: group.map(RuleGroup::matchers),
+			};
+
+			let mute = rule.id.as_deref().and_then(|id| muted.get(id));
+			let skipped = match (rule.expires, mute, rule.active_between) {
+				(Some(expires), _, _) if now.is_some_and(|now| rule.is_expired(now.date())) => {
+					Some(SkipReason::Expired { expires })
+				}
+				(_, Some(mute), _) => Some(SkipReason::Muted { until: mute.until }),
+				(_, _, Some(active_between))
+					if now.is_some_and(|now| !rule.is_active_at(now.time())) =>
+				{
+					Some(SkipReason::Inactive { active_between })
+				}
+				_ => None,
+			};
+			match skipped {
+				Some(skipped) => merged.skipped.push(SkippedRule {
+					rule: rule_with_source,
+					skipped,
+				}),
+				None => merged.rules.push(rule_with_source),
+			}
 		}
 
 		// Track if any config disables external lookup
 		if loaded.config.no_external_lookup {
 			merged.no_external_lookup = true;
 		}
+
+		if merged.max_depth.is_none() {
+			merged.max_depth = loaded.config.max_depth;
+		}
+
+		for (name, var) in &loaded.config.vars {
+			merged
+				.vars
+				.entry(name.clone())
+				.or_insert_with(|| VarWithSource {
+					source: loaded.path.clone(),
+					var: var.clone(),
+				});
+		}
 	}
 
 	merged
 }
 
+/// Load one config file on its own, in place of the cascade, and merge it.
+///
+/// Used by `tramp run --config`: no other file, user config, or `TRAMP_RULES`
+/// is read.
+pub fn load_single_config(path: &Path) -> Result<MergedConfig> {
+	let mut configs = vec![LoadedConfig {
+		config: parse_config_file(path)?,
+		path: path.to_path_buf(),
+	}];
+	resolve_templates(&mut configs)?;
+	Ok(merge_configs(&configs))
+}
+
 /// Convenience function to discover, load, and merge configs from a directory.
 pub fn load_merged_config(start_dir: &Path) -> Result<MergedConfig> {
-	let configs = discover_configs(start_dir)?;
-	Ok(merge_configs(&configs))
+	let discovery = discover_configs(start_dir)?;
+	Ok(merge_configs(&discovery.configs))
 }
 
 /// Get the path to the user's config file.
+///
+/// `~/.tramp.toml` is used if it exists. Otherwise the XDG location,
+/// `$XDG_CONFIG_HOME/tramp/config.toml` (default `~/.config/tramp/config.toml`),
+/// is used if it exists or if `XDG_CONFIG_HOME` is set. Anything else falls
+/// back to `~/.tramp.toml`, which is where `tramp init --user` creates it.
 pub fn user_config_path() -> Result<PathBuf> {
 	let home_dir = dirs::home_dir().ok_or(TrampError::HomeDirectoryNotFound)?;
-	Ok(home_dir.join(".tramp.toml"))
+	let xdg_config_home = std::env::var_os("XDG_CONFIG_HOME")
+		.filter(|dir| !dir.is_empty())
+		.map(PathBuf::from);
+
+	Ok(choose_user_config_path(&home_dir, xdg_config_home))
+}
+
+/// Pick the user config path for a home directory and optional `XDG_CONFIG_HOME`.
+fn choose_user_config_path(home_dir: &Path, xdg_config_home: Option<PathBuf>) -> PathBuf {
+	let home_config = home_dir.join(".tramp.toml");
+	if home_config.exists() {
+		return home_config;
+	}
+
+	let xdg_set = xdg_config_home.is_some();
+	let xdg_config = xdg_config_home
+		.unwrap_or_else(|| home_dir.join(".config"))
+		.join("tramp")
+		.join("config.toml");
+
+	if xdg_config.exists() || xdg_set {
+		xdg_config
+	} else {
+		home_config
+	}
 }
 
 #[cfg(test)]
@@ -189,11 +435,357 @@ mod tests {
 		}
 	}
 
+	#[test]
+	fn test_merge_configs_max_depth_most_specific_wins() {
+		use crate::config::types::Config;
+
+		let configs = vec![
+			LoadedConfig {
+				config: Config::default(),
+				path: PathBuf::from("/repo/sub/.tramp.toml"),
+			},
+			LoadedConfig {
+				config: Config {
+					max_depth: Some(3),
+					..Default::default()
+				},
+				path: PathBuf::from("/repo/.tramp.toml"),
+			},
+			LoadedConfig {
+				config: Config {
+					max_depth: Some(10),
+					..Default::default()
+				},
+				path: PathBuf::from("/home/user/.tramp.toml"),
+			},
+		];
+
+		let merged = merge_configs(&configs);
+		assert_eq!(merged.max_depth, Some(3));
+	}
+
+	/// The instant a local date and time falls on.
+	fn local_timestamp(datetime: DateTime) -> jiff::Timestamp {
+		datetime
+			.to_zoned(jiff::tz::TimeZone::system())
+			.unwrap()
+			.timestamp()
+	}
+
+	#[test]
+	fn test_merge_configs_skips_expired_and_muted_rules() {
+		use crate::config::parse_config_str;
+		use jiff::civil::date;
+
+		let path = PathBuf::from("/repo/.tramp.toml");
+		let content = r#"
+[[rules]]
+id = "old"
+pre_hook = "a.sh"
+expires = 2026-10-01
+
+[[rules]]
+id = "noisy"
+pre_hook = "b.sh"
+
+[[rules]]
+id = "daytime"
+pre_hook = "c.sh"
+active_between = ["09:00", "18:00"]
+"#;
+		let configs = vec![LoadedConfig {
+			config: parse_config_str(content, &path).unwrap(),
+			path,
+		}];
+		let now = date(2026, 10, 18).at(20, 0, 0, 0);
+		let mute_end = date(2026, 10, 18).at(22, 0, 0, 0);
+		let mut muted = MutedRules::default();
+		muted.mute("noisy", Some(local_timestamp(mute_end)));
+
+		let merged = merge_configs_at(&configs, Some(now), &muted);
+		assert!(merged.rules.is_empty());
+		let reasons: Vec<_> = merged
+			.skipped
+			.iter()
+			.map(|s| s.skipped.to_string())
+			.collect();
+		assert_eq!(
+			reasons,
+			[
+				"expired on 2026-10-01",
+				"muted until 2026-10-18 22:00",
+				"outside active_between 09:00-18:00"
+			]
+		);
+
+		let morning = date(2026, 10, 19).at(10, 0, 0, 0);
+		muted.prune(local_timestamp(morning));
+		let merged = merge_configs_at(&configs, Some(morning), &muted);
+		let ids: Vec<_> = merged
+			.rules
+			.iter()
+			.map(|r| r.rule.id.as_deref().unwrap())
+			.collect();
+		assert_eq!(ids, ["noisy", "daytime"]);
+
+		// Without a time, only mutes skip rules
+		let merged = merge_configs_at(&configs, None, &MutedRules::default());
+		assert_eq!(merged.rules.len(), 3);
+		assert!(merged.skipped.is_empty());
+	}
+
+	#[test]
+	fn test_merge_configs_flattens_groups_after_rules() {
+		use crate::config::parse_config_str;
+
+		let path = PathBuf::from("/repo/.tramp.toml");
+		let content = r#"
+[[groups]]
+id = "cargo"
+binary_pattern = ".*/cargo$"
+
+[[groups.rules]]
+id = "grouped"
+pre_hook = "a.sh"
+
+[[groups.rules]]
+enabled = false
+pre_hook = "b.sh"
+
+[[rules]]
+id = "top"
+pre_hook = "c.sh"
+"#;
+		let configs = vec![LoadedConfig {
+			config: parse_config_str(content, &path).unwrap(),
+			path,
+		}];
+
+		let merged = merge_configs(&configs);
+		let ids: Vec<_> = merged
+			.rules
+			.iter()
+			.map(|r| r.rule.id.as_deref().unwrap())
+			.collect();
+		assert_eq!(ids, ["top", "grouped"]);
+
+		let grouped = &merged.rules[1];
+		assert_eq!(grouped.source_index, 0);
+		assert_eq!(
+			grouped.rule.position(grouped.source_index),
+			"group cargo, rule 1"
+		);
+		let group_matchers = grouped.group_matchers.as_ref().unwrap();
+		assert_eq!(group_matchers.binary_pattern.as_deref(), Some(".*/cargo$"));
+		assert!(merged.rules[0].group_matchers.is_none());
+	}
+
+	#[test]
+	fn test_local_config_takes_priority_unless_forbidden() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let repo = temp_dir.path();
+		std::fs::write(repo.join(".tramp.toml"), "root = true\n").unwrap();
+		std::fs::write(
+			repo.join(".tramp.local.toml"),
+			"[[rules]]\nid = \"mine\"\npre_hook = \"a.sh\"\n",
+		)
+		.unwrap();
+
+		let discovery = discover_configs(repo).unwrap();
+		assert_eq!(discovery.configs[0].path, repo.join(".tramp.local.toml"));
+		assert_eq!(discovery.configs[1].path, repo.join(".tramp.toml"));
+		let merged = merge_configs(&discovery.configs);
+		assert_eq!(merged.rules[0].rule.id.as_deref(), Some("mine"));
+
+		std::fs::write(repo.join(".tramp.toml"), "no-external-lookup = true\n").unwrap();
+		let discovery = discover_configs(repo).unwrap();
+		assert_eq!(discovery.configs.len(), 1);
+		assert_eq!(discovery.configs[0].path, repo.join(".tramp.toml"));
+
+		let skipped = discovery
+			.searched
+			.iter()
+			.find(|location| location.path == repo.join(".tramp.local.toml"))
+			.unwrap();
+		assert!(!skipped.found);
+		assert_eq!(skipped.ignored_by, Some(repo.join(".tramp.toml")));
+	}
+
+	#[test]
+	fn test_local_config_cannot_change_repository_settings() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let repo = temp_dir.path();
+		std::fs::write(repo.join(".tramp.toml"), "root = true\n").unwrap();
+		std::fs::write(repo.join(".tramp.local.toml"), "max-depth = 2\n").unwrap();
+
+		let err = discover_configs(repo).unwrap_err();
+		assert!(matches!(
+			err,
+			TrampError::NotAllowedInLocalConfig { ref key, .. } if key == "max-depth"
+		));
+	}
+
+	#[test]
+	fn test_local_config_without_repo_config_is_not_read() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path().join("dir");
+		std::fs::create_dir(&dir).unwrap();
+		std::fs::write(dir.join(".tramp.local.toml"), "invalid [[[").unwrap();
+
+		let discovery = discover_configs(&dir).unwrap();
+		assert!(
+			discovery
+				.configs
+				.iter()
+				.all(|c| c.path != dir.join(".tramp.local.toml"))
+		);
+	}
+
+	#[test]
+	fn test_discover_configs_reads_embedded_configs() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let repo = temp_dir.path().join("repo");
+		let app = repo.join("app");
+		std::fs::create_dir_all(&app).unwrap();
+		std::fs::write(
+			repo.join("Cargo.toml"),
+			"[workspace]\n\n[workspace.metadata.tramp]\nroot = true\n",
+		)
+		.unwrap();
+		std::fs::write(app.join("package.json"), r#"{"tramp": {"rules": []}}"#).unwrap();
+		// A standalone config wins over the manifest next to it
+		std::fs::write(app.join(".tramp.toml"), "").unwrap();
+
+		let discovery = discover_configs(&app).unwrap();
+		let paths: Vec<_> = discovery.configs.iter().map(|c| c.path.clone()).collect();
+		assert_eq!(
+			paths[..2],
+			[
+				app.join(".tramp.toml"),
+				repo.join("Cargo.toml#workspace.metadata.tramp")
+			]
+		);
+		assert_eq!(
+			discovery.cascade_end,
+			CascadeEnd::Root {
+				path: repo.join("Cargo.toml#workspace.metadata.tramp")
+			}
+		);
+	}
+
+	#[test]
+	fn test_discover_configs_reports_root_cut() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let repo = temp_dir.path().join("repo");
+		let sub = repo.join("sub");
+		std::fs::create_dir_all(&sub).unwrap();
+		std::fs::write(repo.join(".tramp.toml"), "root = true\n").unwrap();
+		// Above the root config; must never be loaded
+		std::fs::write(temp_dir.path().join(".tramp.toml"), "invalid [[[").unwrap();
+
+		let discovery = discover_configs(&sub).unwrap();
+
+		assert_eq!(discovery.searched.len(), 2);
+		assert_eq!(discovery.searched[0].path, sub.join(".tramp.toml"));
+		assert!(!discovery.searched[0].found);
+		assert!(discovery.searched[1].found);
+		assert_eq!(
+			discovery.cascade_end,
+			CascadeEnd::Root {
+				path: repo.join(".tramp.toml")
+			}
+		);
+		assert_eq!(discovery.configs[0].path, repo.join(".tramp.toml"));
+	}
+
+	#[test]
+	fn test_discover_configs_reports_no_external_lookup() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let config_path = temp_dir.path().join(".tramp.toml");
+		std::fs::write(&config_path, "no-external-lookup = true\n").unwrap();
+
+		let discovery = discover_configs(temp_dir.path()).unwrap();
+
+		assert_eq!(discovery.configs.len(), 1);
+		assert_eq!(
+			discovery.cascade_end,
+			CascadeEnd::NoExternalLookup {
+				path: config_path.clone()
+			}
+		);
+		assert_eq!(
+			discovery.user_config,
+			UserConfigDecision::SkippedByNoExternalLookup {
+				set_by: config_path
+			}
+		);
+	}
+
+	#[test]
+	fn test_discover_configs_reports_user_config_disabled() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let config_path = temp_dir.path().join(".tramp.toml");
+		std::fs::write(
+			&config_path,
+			"root = true\nroot-config-lookup-disable-env-var = \"TEST_TRAMP_DISCOVERY_CI\"\n",
+		)
+		.unwrap();
+
+		// SAFETY: This env var is only used by this test
+		unsafe {
+			std::env::set_var("TEST_TRAMP_DISCOVERY_CI", "1");
+		}
+		let discovery = discover_configs(temp_dir.path()).unwrap();
+		unsafe {
+			std::env::remove_var("TEST_TRAMP_DISCOVERY_CI");
+		}
+
+		assert_eq!(
+			discovery.user_config,
+			UserConfigDecision::DisabledByEnvVar {
+				env_var: "TEST_TRAMP_DISCOVERY_CI".to_string(),
+				set_by: config_path,
+			}
+		);
+	}
+
 	#[test]
 	fn test_user_config_path() {
 		let path = user_config_path();
 		assert!(path.is_ok());
 		let path = path.unwrap();
-		assert!(path.ends_with(".tramp.toml"));
+		assert!(path.ends_with(".tramp.toml") || path.ends_with("tramp/config.toml"));
+	}
+
+	#[test]
+	fn test_choose_user_config_path() {
+		let home = tempfile::tempdir().unwrap();
+		let home = home.path();
+		let xdg = home.join("xdg");
+
+		// Nothing exists: ~/.tramp.toml, unless XDG_CONFIG_HOME is set
+		assert_eq!(
+			choose_user_config_path(home, None),
+			home.join(".tramp.toml")
+		);
+		assert_eq!(
+			choose_user_config_path(home, Some(xdg.clone())),
+			xdg.join("tramp/config.toml")
+		);
+
+		// An existing ~/.config/tramp/config.toml is found without XDG_CONFIG_HOME
+		std::fs::create_dir_all(home.join(".config/tramp")).unwrap();
+		std::fs::write(home.join(".config/tramp/config.toml"), "").unwrap();
+		assert_eq!(
+			choose_user_config_path(home, None),
+			home.join(".config/tramp/config.toml")
+		);
+
+		// ~/.tramp.toml always wins
+		std::fs::write(home.join(".tramp.toml"), "").unwrap();
+		assert_eq!(
+			choose_user_config_path(home, Some(xdg)),
+			home.join(".tramp.toml")
+		);
 	}
 }
@@ -0,0 +1,302 @@
+//! Conditional config sections: `[when.'<condition>']` tables that only apply
+//! where their condition holds.
+//!
+//! A condition tests one fact about the machine tramp runs on:
+//!
+//! - `os = "linux"`, `arch = "aarch64"`, `family = "unix"`: the platform, as
+//!   named by Rust's `std::env::consts`
+//! - `env.CI`: the environment variable is set to a truthy value
+//! - `env.DEPLOY = "staging"`: the environment variable has exactly this value
+//!
+//! `!=` negates a comparison, and `!env.CI` negates a truthiness test.
+//! Conditions are evaluated once, when the config is loaded, and the sections
+//! that hold are applied in file order, so a later section wins.
+
+use crate::config::cascade::is_truthy;
+use crate::config::types::{ConditionOutcome, ConditionalConfig, Config};
+use crate::error::{Result, TrampError};
+use std::path::Path;
+
+/// The facts a condition can test, besides `env.NAME`.
+pub const CONDITION_KEYS: &[&str] = &["os", "arch", "family"];
+
+/// Look up a fact about the current process: `os`, `arch`, `family`, or
+/// `env.NAME`. Returns `None` for an unset environment variable.
+pub fn current_fact(key: &str) -> Option<String> {
+	match key {
+		"os" => Some(std::env::consts::OS.to_string()),
+		"arch" => Some(std::env::consts::ARCH.to_string()),
+		"family" => Some(std::env::consts::FAMILY.to_string()),
+		_ => key
+			.strip_prefix("env.")
+			.and_then(|name| std::env::var(name).ok()),
+	}
+}
+
+/// A parsed condition.
+#[derive(Debug, PartialEq, Eq)]
+enum Condition<'a> {
+	/// `key = "value"`, or `key != "value"` when not `equal`.
+	Compare {
+		key: &'a str,
+		value: &'a str,
+		equal: bool,
+	},
+
+	/// `env.NAME`, or `!env.NAME` when `negated`.
+	Truthy { key: &'a str, negated: bool },
+}
+
+/// Parse a condition, returning why it is invalid if it is.
+fn parse_condition(condition: &str) -> std::result::Result<Condition<'_>, String> {
+	let condition = condition.trim();
+
+	let comparison = condition.find('=').map(|eq| {
+		let (key, value) = (&condition[..eq], &condition[eq + 1..]);
+		match key.strip_suffix('!') {
+			Some(key) => (key, value, false),
+			None => (key, value, true),
+		}
+	});
+
+	let parsed = match comparison {
+		Some((key, value, equal)) => Condition::Compare {
+			key: key.trim(),
+			value: unquote(value.trim()),
+			equal,
+		},
+		None => match condition.strip_prefix('!') {
+			Some(key) => Condition::Truthy {
+				key: key.trim(),
+				negated: true,
+			},
+			None => Condition::Truthy {
+				key: condition,
+				negated: false,
+			},
+		},
+	};
+
+	let key = match parsed {
+		Condition::Compare { key, .. } | Condition::Truthy { key, .. } => key,
+	};
+	let is_env = key
+		.strip_prefix("env.")
+		.is_some_and(|name| !name.is_empty());
+	if !is_env && !CONDITION_KEYS.contains(&key) {
+		return Err(format!(
+			"unknown key `{}`: expected {} or env.NAME",
+			key,
+			CONDITION_KEYS.join(", ")
+		));
+	}
+	if !is_env && matches!(parsed, Condition::Truthy { .. }) {
+		return Err(format!("`{}` needs a value, e.g. `{} = \"...\"`", key, key));
+	}
+
+	Ok(parsed)
+}
+
+/// Strip one pair of matching quotes from a value.
+fn unquote(value: &str) -> &str {
+	['"', '\'']
+		.into_iter()
+		.find_map(|quote| {
+			value
+				.strip_prefix(quote)
+				.and_then(|inner| inner.strip_suffix(quote))
+		})
+		.unwrap_or(value)
+}
+
+/// Evaluate a condition against the facts from `fact`.
+///
+/// Returns why the condition is invalid if it is.
+pub fn evaluate_condition(
+	condition: &str,
+	fact: &dyn Fn(&str) -> Option<String>,
+) -> std::result::Result<bool, String> {
+	Ok(match parse_condition(condition)? {
+		Condition::Compare { key, value, equal } => (fact(key).as_deref() == Some(value)) == equal,
+		Condition::Truthy { key, negated } => fact(key).is_some_and(|v| is_truthy(&v)) != negated,
+	})
+}
+
+/// Apply the `when` sections of a config whose conditions hold, in order.
+///
+/// The sections are moved out of `config.when` and recorded in
+/// `config.conditions` along with whether each applied.
+pub fn apply_conditions(
+	config: &mut Config,
+	path: &Path,
+	fact: &dyn Fn(&str) -> Option<String>,
+) -> Result<()> {
+	for (condition, section) in std::mem::take(&mut config.when) {
+		let applied = evaluate_condition(&condition, fact).map_err(|reason| {
+			TrampError::InvalidCondition {
+				path: path.to_path_buf(),
+				condition: condition.clone(),
+				reason,
+			}
+		})?;
+
+		if applied {
+			apply_section(config, section);
+		}
+		config
+			.conditions
+			.push(ConditionOutcome { condition, applied });
+	}
+	Ok(())
+}
+
+/// Lay a conditional section over a config.
+fn apply_section(config: &mut Config, section: ConditionalConfig) {
+	if let Some(root) = section.root {
+		config.root = root;
+	}
+	if let Some(no_external_lookup) = section.no_external_lookup {
+		config.no_external_lookup = no_external_lookup;
+	}
+	if section.root_config_lookup_disable_env_var.is_some() {
+		config.root_config_lookup_disable_env_var = section.root_config_lookup_disable_env_var;
+	}
+	if let Some(no_env_rules) = section.no_env_rules {
+		config.no_env_rules = no_env_rules;
+	}
+	if section.max_depth.is_some() {
+		config.max_depth = section.max_depth;
+	}
+
+	config.templates.extend(section.templates);
+	config.vars.extend(section.vars);
+
+	for rule in section.rules {
+		let existing = rule
+			.id
+			.as_ref()
+			.and_then(|id| config.rules.iter_mut().find(|r| r.id.as_ref() == Some(id)));
+		match existing {
+			Some(existing) => *existing = rule,
+			None => config.rules.push(rule),
+		}
+	}
+
+	for group in section.groups {
+		let existing = group
+			.id
+			.as_ref()
+			.and_then(|id| config.groups.iter_mut().find(|g| g.id.as_ref() == Some(id)));
+		match existing {
+			Some(existing) => *existing = group,
+			None => config.groups.push(group),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::config::parser::parse_config_str_as_written;
+
+	fn linux_ci(key: &str) -> Option<String> {
+		match key {
+			"os" => Some("linux".to_string()),
+			"arch" => Some("x86_64".to_string()),
+			"family" => Some("unix".to_string()),
+			"env.CI" => Some("true".to_string()),
+			"env.DEPLOY" => Some("staging".to_string()),
+			"env.QUIET" => Some("0".to_string()),
+			_ => None,
+		}
+	}
+
+	#[test]
+	fn test_evaluate_condition() {
+		let holds = |condition| evaluate_condition(condition, &linux_ci).unwrap();
+
+		assert!(holds(r#"os = "linux""#));
+		assert!(holds("os=linux"));
+		assert!(!holds(r#"os = "macos""#));
+		assert!(holds(r#"family != "windows""#));
+		assert!(holds("env.CI"));
+		assert!(!holds("!env.CI"));
+		assert!(!holds("env.QUIET"));
+		assert!(!holds("env.MISSING"));
+		assert!(holds("env.DEPLOY = 'staging'"));
+		assert!(holds(r#"env.MISSING != "x""#));
+	}
+
+	#[test]
+	fn test_invalid_conditions() {
+		let reason = |condition| evaluate_condition(condition, &linux_ci).unwrap_err();
+
+		assert_eq!(
+			reason(r#"platform = "linux""#),
+			"unknown key `platform`: expected os, arch, family or env.NAME"
+		);
+		assert_eq!(reason("os"), "`os` needs a value, e.g. `os = \"...\"`");
+		assert!(reason("env. = 1").starts_with("unknown key `env.`"));
+	}
+
+	#[test]
+	fn test_apply_conditions_in_file_order() {
+		let content = r#"
+max-depth = 3
+
+[[rules]]
+id = "build"
+binary_pattern = ".*/cargo$"
+pre_hook = "/usr/local/bin/log"
+
+[when.'os = "linux"']
+max-depth = 5
+
+[[when.'os = "linux"'.rules]]
+id = "build"
+binary_pattern = ".*/cargo$"
+pre_hook = "/opt/hooks/log"
+
+[[when.'os = "linux"'.rules]]
+binary_pattern = ".*/make$"
+pre_hook = "/opt/hooks/log"
+
+[when.'env.CI']
+max-depth = 8
+
+[when.'os = "macos"']
+root = true
+"#;
+		let path = Path::new("/repo/.tramp.toml");
+		let mut config = parse_config_str_as_written(content, path).unwrap();
+		apply_conditions(&mut config, path, &linux_ci).unwrap();
+
+		// The later section wins
+		assert_eq!(config.max_depth, Some(8));
+		assert!(!config.root);
+
+		// A rule with a known id is replaced in place; others are added
+		assert_eq!(config.rules.len(), 2);
+		assert_eq!(
+			config.rules[0].pre_hook.as_deref(),
+			Some(Path::new("/opt/hooks/log"))
+		);
+		assert_eq!(config.rules[1].binary_pattern.as_deref(), Some(".*/make$"));
+		assert_eq!(config.rules[1].location.as_ref().unwrap().start.line, 17);
+
+		let outcomes: Vec<_> = config
+			.conditions
+			.iter()
+			.map(|c| (c.condition.as_str(), c.applied))
+			.collect();
+		assert_eq!(
+			outcomes,
+			[
+				(r#"os = "linux""#, true),
+				("env.CI", true),
+				(r#"os = "macos""#, false),
+			]
+		);
+		assert!(config.when.is_empty());
+	}
+}
@@ -0,0 +1,990 @@
+use crate::config::cascade::discover_configs_with;
+use crate::config::embedded::{
+	LOCAL_CONFIG_FILE_NAME, config_candidates, read_config_source, split_config_path,
+};
+use crate::config::location::SourceLocation;
+use crate::config::parser::parse_config_str_unvalidated;
+use crate::config::schedule::{EXPIRY_WARNING_DAYS, local_now};
+use crate::config::templates::resolve_rule;
+use crate::config::types::{Config, GroupRef, LoadedConfig, Rule, RuleGroup, SearchedLocation};
+use crate::error::{Result, TrampError, unknown_key_message};
+use crate::exec::{is_executable, resolve_command};
+use crate::rules::Substitution;
+use crate::vars::var_references;
+use jiff::civil::Date;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// How serious a diagnostic is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+	Error,
+	Warning,
+}
+
+impl Severity {
+	/// Get the string representation used in text output.
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Severity::Error => "error",
+			Severity::Warning => "warning",
+		}
+	}
+}
+
+/// A single problem found in a config file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+	/// How serious the problem is.
+	pub severity: Severity,
+
+	/// The config file the problem is in.
+	pub file: PathBuf,
+
+	/// The group the problem is in, if it is in a group or one of its rules.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub group: Option<GroupRef>,
+
+	/// Position of the offending rule within the file, or its group (0-based),
+	/// if rule-specific.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub rule_index: Option<usize>,
+
+	/// The offending field, if field-specific.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub field: Option<String>,
+
+	/// Line of the problem in the file (1-based), if known.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub line: Option<usize>,
+
+	/// Column of the problem in the line (1-based), if known.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub column: Option<usize>,
+
+	/// Human-readable description of the problem.
+	pub message: String,
+}
+
+/// A config file checked during validation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CheckedConfig {
+	/// The config file path.
+	pub path: PathBuf,
+
+	/// Number of rules in the file (0 if it failed to parse).
+	pub rules: usize,
+}
+
+/// The result of validating every config file in the cascade.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ValidationReport {
+	/// Config files that were checked, in cascade order.
+	pub configs: Vec<CheckedConfig>,
+
+	/// Problems found, in cascade order.
+	pub diagnostics: Vec<Diagnostic>,
+}
+
+impl ValidationReport {
+	/// Whether any diagnostic is an error.
+	pub fn has_errors(&self) -> bool {
+		self.diagnostics
+			.iter()
+			.any(|d| d.severity == Severity::Error)
+	}
+
+	/// Count diagnostics of the given severity.
+	pub fn count(&self, severity: Severity) -> usize {
+		self.diagnostics
+			.iter()
+			.filter(|d| d.severity == severity)
+			.count()
+	}
+}
+
+/// Validate every config file in the cascade starting at `start_dir`.
+///
+/// Unlike [`crate::config::discover_configs`], this does not stop at the first
+/// broken file: unreadable or unparseable files are reported and treated as
+/// empty so the rest of the cascade is still checked.
+///
+/// Beyond parsing, every regex and substitution is compiled, hook files and
+/// alternate commands are resolved (relative to `start_dir`, as at runtime),
+/// and rules that can never match or do nothing are flagged as warnings.
+pub fn validate_cascade(start_dir: &Path) -> Result<ValidationReport> {
+	let mut report = ValidationReport::default();
+	let mut failures = Vec::new();
+
+	let mut load = |path: &Path| -> Result<Config> {
+		let config = read_config_source(path)
+			.and_then(|content| parse_config_str_unvalidated(&content, path));
+
+		Ok(config.unwrap_or_else(|e| {
+			failures.push(file_diagnostic(path, &e));
+			Config::default()
+		}))
+	};
+
+	let mut discovery = discover_configs_with(start_dir, &mut load)?;
+	let today = local_now().date();
+
+	// A rule may use variables defined anywhere in the cascade
+	let vars: BTreeSet<String> = discovery
+		.configs
+		.iter()
+		.flat_map(|loaded| loaded.config.vars.keys().cloned())
+		.collect();
+
+	// Check rules with their templates applied, as they will run
+	let mut checks = Vec::new();
+	for (config_index, loaded) in discovery.configs.iter().enumerate() {
+		let mut diagnostics = Vec::new();
+		let mut rules = Vec::new();
+		for (_, index, rule) in loaded.config.all_rules() {
+			match resolve_rule(&discovery.configs, config_index, rule) {
+				Ok(rule) => rules.push(rule),
+				Err(e) => {
+					diagnostics.push(rule_diagnostic(&loaded.path, index, rule, &e));
+					rules.push(rule.clone());
+				}
+			}
+		}
+		checks.push((diagnostics, rules));
+	}
+
+	for (loaded, (template_diagnostics, rules)) in discovery.configs.iter_mut().zip(checks) {
+		for (rule, resolved) in loaded.config.all_rules_mut().zip(rules) {
+			*rule = resolved;
+		}
+
+		report.configs.push(CheckedConfig {
+			path: loaded.path.clone(),
+			rules: loaded.config.all_rules().count(),
+		});
+		report
+			.diagnostics
+			.extend(failures.iter().filter(|d| d.file == loaded.path).cloned());
+		report.diagnostics.extend(template_diagnostics);
+		report
+			.diagnostics
+			.extend(check_var_references(&loaded.config, &loaded.path, &vars));
+		report
+			.diagnostics
+			.extend(check_config(&loaded.config, &loaded.path, start_dir));
+		report
+			.diagnostics
+			.extend(check_expiry(&loaded.config, &loaded.path, today));
+	}
+
+	report
+		.diagnostics
+		.extend(check_ignored_configs(&discovery.searched));
+	report
+		.diagnostics
+		.extend(check_shadowed_rules(&discovery.configs));
+
+	Ok(report)
+}
+
+/// Check a parsed config for invalid rules.
+///
+/// Hook paths and commands are resolved relative to `cwd`, matching how they
+/// are run.
+pub fn check_config(config: &Config, path: &Path, cwd: &Path) -> Vec<Diagnostic> {
+	let mut diagnostics = Vec::new();
+
+	for (index, group) in config.groups.iter().enumerate() {
+		let matchers = group.matchers();
+		for (field, pattern) in matchers.matchers() {
+			if let Err(e) = Regex::new(pattern) {
+				let location = rule_location(&matchers, Some(field));
+				diagnostics.push(Diagnostic {
+					severity: Severity::Error,
+					file: path.to_path_buf(),
+					group: Some(GroupRef {
+						index,
+						id: group.id.clone(),
+					}),
+					rule_index: None,
+					field: Some(field.to_string()),
+					line: location.map(|l| l.line),
+					column: location.map(|l| l.column),
+					message: format!("Invalid regex pattern: {}", e),
+				});
+			}
+		}
+	}
+
+	for (_, index, rule) in config.all_rules() {
+		let mut push = |severity, field: Option<&str>, message: String| {
+			let location = rule_location(rule, field);
+			diagnostics.push(Diagnostic {
+				severity,
+				file: path.to_path_buf(),
+				group: rule.group.clone(),
+				rule_index: Some(index),
+				field: field.map(str::to_string),
+				line: location.map(|l| l.line),
+				column: location.map(|l| l.column),
+				message,
+			});
+		};
+
+		if let Err(e) = rule.validate() {
+			let field = match e {
+				TrampError::MutuallyExclusive { ref option2, .. } => Some(option2.as_str()),
+				_ => None,
+			};
+			push(Severity::Error, field, e.to_string());
+		}
+
+		for (field, pattern) in rule.matchers() {
+			if let Err(e) = Regex::new(pattern) {
+				push(
+					Severity::Error,
+					Some(field),
+					format!("Invalid regex pattern: {}", e),
+				);
+			}
+		}
+
+		let substitutions = [
+			("arg_rewrite", &rule.arg_rewrite),
+			("command_rewrite", &rule.command_rewrite),
+		];
+		for (field, substitution) in substitutions {
+			if let Some(substitution) = substitution
+				&& let Err(e) = Substitution::parse(substitution)
+			{
+				let detail = match e {
+					TrampError::InvalidRegex { ref source, .. } => source.to_string(),
+					ref other => other.to_string(),
+				};
+				push(
+					Severity::Error,
+					Some(field),
+					format!("Invalid substitution {:?}: {}", substitution, detail),
+				);
+			}
+		}
+
+		// Paths that use variables are only known once the variables are computed
+		let uses_vars = |text: &str| !var_references(text).is_empty();
+
+		for (field, hook) in rule.hooks() {
+			if uses_vars(&hook.to_string_lossy()) {
+				continue;
+			}
+			if let Some(message) = check_hook(hook, cwd) {
+				push(Severity::Error, Some(field), message);
+			}
+		}
+
+		if let Some(ref alternate) = rule.alternate_command
+			&& !uses_vars(alternate)
+			&& resolve_command(alternate).is_none()
+		{
+			push(
+				Severity::Error,
+				Some("alternate_command"),
+				format!("Alternate command not found: {}", alternate),
+			);
+		}
+
+		if !rule.has_action() {
+			push(
+				Severity::Warning,
+				None,
+				"Rule has no action: it sets no rewrite and no hooks".to_string(),
+			);
+		}
+	}
+
+	for (name, template) in &config.templates {
+		if let Err(e) = template.validate() {
+			diagnostics.push(Diagnostic {
+				severity: Severity::Error,
+				file: path.to_path_buf(),
+				group: None,
+				rule_index: None,
+				field: Some(format!("templates.{}", name)),
+				line: None,
+				column: None,
+				message: e.to_string(),
+			});
+		}
+	}
+
+	for (name, var) in &config.vars {
+		if let Err(reason) = var.validate() {
+			diagnostics.push(Diagnostic {
+				severity: Severity::Error,
+				file: path.to_path_buf(),
+				group: None,
+				rule_index: None,
+				field: Some(format!("vars.{}", name)),
+				line: None,
+				column: None,
+				message: format!("Invalid variable `{}`: {}", name, reason),
+			});
+		}
+	}
+
+	diagnostics
+}
+
+/// Warn about rules that have expired, or expire within
+/// [`EXPIRY_WARNING_DAYS`] of `today`.
+pub fn check_expiry(config: &Config, path: &Path, today: Date) -> Vec<Diagnostic> {
+	let mut diagnostics = Vec::new();
+	for (_, index, rule) in config.all_rules() {
+		let Some(expires) = rule.expires else {
+			continue;
+		};
+		let days = expires.days_from(today);
+		let message = if expires.has_passed(today) {
+			format!(
+				"Rule expired on {} and is skipped; `tramp rule prune` deletes it",
+				expires
+			)
+		} else if days <= EXPIRY_WARNING_DAYS {
+			format!("Rule expires on {}, in {} day(s)", expires, days)
+		} else {
+			continue;
+		};
+
+		let location = rule_location(rule, Some("expires"));
+		diagnostics.push(Diagnostic {
+			severity: Severity::Warning,
+			file: path.to_path_buf(),
+			group: rule.group.clone(),
+			rule_index: Some(index),
+			field: Some("expires".to_string()),
+			line: location.map(|l| l.line),
+			column: location.map(|l| l.column),
+			message,
+		});
+	}
+	diagnostics
+}
+
+/// Check that every variable a config's rules use is defined in the cascade.
+fn check_var_references(config: &Config, path: &Path, vars: &BTreeSet<String>) -> Vec<Diagnostic> {
+	let mut diagnostics = Vec::new();
+	for (_, index, rule) in config.all_rules() {
+		for (field, value) in rule.interpolated_fields() {
+			for name in var_references(&value) {
+				if vars.contains(name) {
+					continue;
+				}
+				let location = rule_location(rule, Some(field));
+				diagnostics.push(Diagnostic {
+					severity: Severity::Error,
+					file: path.to_path_buf(),
+					group: rule.group.clone(),
+					rule_index: Some(index),
+					field: Some(field.to_string()),
+					line: location.map(|l| l.line),
+					column: location.map(|l| l.column),
+					message: TrampError::UnknownVar {
+						name: name.to_string(),
+					}
+					.to_string(),
+				});
+			}
+		}
+	}
+	diagnostics
+}
+
+/// Check that a hook can be run, returning a message if it can't.
+///
+/// Hooks run through `sh -c`, so a hook may be a path to a script or a short
+/// command line; for the latter, the program it starts is checked.
+fn check_hook(hook: &Path, cwd: &Path) -> Option<String> {
+	let script = cwd.join(hook);
+	if script.exists() {
+		return (!is_executable(&script))
+			.then(|| format!("Hook is not executable: {}", script.display()));
+	}
+
+	let hook_str = hook.to_string_lossy();
+	let program = hook_str.split_whitespace().next()?;
+
+	if program.contains('/') {
+		let program_path = cwd.join(program);
+		if !program_path.exists() {
+			Some(format!("Hook not found: {}", program_path.display()))
+		} else if !is_executable(&program_path) {
+			Some(format!(
+				"Hook is not executable: {}",
+				program_path.display()
+			))
+		} else {
+			None
+		}
+	} else if resolve_command(program).is_none() {
+		Some(format!("Hook command not found on PATH: {}", program))
+	} else {
+		None
+	}
+}
+
+/// Flag configs that are present but unused because another config in the
+/// same directory takes precedence, e.g. `Cargo.toml` metadata next to a
+/// `.tramp.toml`.
+pub fn check_ignored_configs(searched: &[SearchedLocation]) -> Vec<Diagnostic> {
+	let mut diagnostics = Vec::new();
+
+	// Local overrides sit alongside the config they override rather than competing with it
+	for used in searched
+		.iter()
+		.filter(|location| location.found && !location.path.ends_with(LOCAL_CONFIG_FILE_NAME))
+	{
+		let (file, _) = split_config_path(&used.path);
+		let Some(dir) = file.parent() else {
+			continue;
+		};
+
+		for ignored in config_candidates(dir).into_iter().skip(1) {
+			diagnostics.push(Diagnostic {
+				severity: Severity::Warning,
+				file: ignored,
+				group: None,
+				rule_index: None,
+				field: None,
+				line: None,
+				column: None,
+				message: format!(
+					"Config is ignored: {} in the same directory takes precedence",
+					used.path.display()
+				),
+			});
+		}
+	}
+
+	diagnostics
+}
+
+/// Flag rules that can never match because an earlier rule in the cascade
+/// always wins first: either a catch-all with no matchers, or a rule with
+/// exactly the same matchers. A rule in a group is compared with its group's
+/// matchers included. Expired rules and rules limited by `active_between`
+/// don't always apply, so they shadow nothing.
+pub fn check_shadowed_rules(configs: &[LoadedConfig]) -> Vec<Diagnostic> {
+	let mut diagnostics = Vec::new();
+	let mut earlier: Vec<(&Path, String, MatcherKey)> = Vec::new();
+	let today = local_now().date();
+
+	for loaded in configs {
+		for (group, index, rule) in loaded.config.all_rules() {
+			if !rule.is_enabled() {
+				continue;
+			}
+			let key = MatcherKey::new(group, rule);
+			let shadowed_by = earlier
+				.iter()
+				.find(|(_, _, earlier_key)| earlier_key.is_catch_all() || *earlier_key == key);
+
+			if let Some((earlier_path, earlier_position, earlier_key)) = shadowed_by {
+				let kind = if earlier_key.is_catch_all() {
+					"catch-all rule"
+				} else {
+					"rule with identical matchers"
+				};
+				let location = rule_location(rule, None);
+				diagnostics.push(Diagnostic {
+					severity: Severity::Warning,
+					file: loaded.path.clone(),
+					group: rule.group.clone(),
+					rule_index: Some(index),
+					field: None,
+					line: location.map(|l| l.line),
+					column: location.map(|l| l.column),
+					message: format!(
+						"Rule can never match: shadowed by earlier {} ({} in {})",
+						kind,
+						earlier_position,
+						earlier_path.display()
+					),
+				});
+			}
+
+			if rule.active_between.is_none() && !rule.is_expired(today) {
+				earlier.push((&loaded.path, rule.position(index), key));
+			}
+		}
+	}
+
+	diagnostics
+}
+
+/// Everything that decides which commands a rule matches, for comparing rules.
+#[derive(PartialEq, Eq)]
+struct MatcherKey {
+	group: Vec<(&'static str, String)>,
+	group_depth: Option<usize>,
+	rule: Vec<(&'static str, String)>,
+	rule_depth: Option<usize>,
+}
+
+impl MatcherKey {
+	fn new(group: Option<&RuleGroup>, rule: &Rule) -> Self {
+		let owned = |rule: &Rule| {
+			rule.matchers()
+				.into_iter()
+				.map(|(field, pattern)| (field, pattern.to_string()))
+				.collect()
+		};
+		let group = group.map(RuleGroup::matchers).unwrap_or_default();
+
+		MatcherKey {
+			group: owned(&group),
+			group_depth: group.ancestor_depth,
+			rule: owned(rule),
+			rule_depth: rule.ancestor_depth,
+		}
+	}
+
+	/// Whether the rule matches every command.
+	fn is_catch_all(&self) -> bool {
+		self.group.is_empty() && self.rule.is_empty()
+	}
+}
+
+/// The location of a rule's field (or the rule itself), if the rule was parsed from a file.
+fn rule_location<'a>(rule: &'a Rule, field: Option<&str>) -> Option<&'a SourceLocation> {
+	let location = rule.location.as_ref()?;
+	Some(match field {
+		Some(field) => location.field(field),
+		None => &location.start,
+	})
+}
+
+/// Turn an error resolving a rule's templates into a diagnostic.
+fn rule_diagnostic(path: &Path, index: usize, rule: &Rule, error: &TrampError) -> Diagnostic {
+	let location = rule_location(rule, Some("use"));
+	Diagnostic {
+		severity: Severity::Error,
+		file: path.to_path_buf(),
+		group: rule.group.clone(),
+		rule_index: Some(index),
+		field: Some("use".to_string()),
+		line: location.map(|l| l.line),
+		column: location.map(|l| l.column),
+		message: error.to_string(),
+	}
+}
+
+/// Turn a file-level load error into a diagnostic.
+fn file_diagnostic(path: &Path, error: &TrampError) -> Diagnostic {
+	let (message, location) = match error {
+		TrampError::ConfigReadError { source, .. } => {
+			(format!("Failed to read config file: {}", source), None)
+		}
+		TrampError::ConfigParseError {
+			source, location, ..
+		} => (source.message().to_string(), location.as_deref()),
+		TrampError::ConfigJsonParseError {
+			source, location, ..
+		} => (source.to_string(), location.as_deref()),
+		TrampError::UnknownKey {
+			key,
+			suggestion,
+			location,
+			..
+		} => (
+			unknown_key_message(key, suggestion.as_deref()),
+			Some(location),
+		),
+		TrampError::InvalidCondition {
+			condition, reason, ..
+		} => (
+			format!("Invalid condition `{}`: {}", condition, reason),
+			None,
+		),
+		other => (other.to_string(), None),
+	};
+
+	Diagnostic {
+		severity: Severity::Error,
+		file: path.to_path_buf(),
+		group: None,
+		rule_index: None,
+		field: None,
+		line: location.map(|l| l.line),
+		column: location.map(|l| l.column),
+		message,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_check_config_reports_rule_index_and_field() {
+		let content = r#"
+[[rules]]
+binary_pattern = ".*/cargo$"
+arg_rewrite = "s/^build$/build --release/"
+
+[[rules]]
+arg_rewrite = "s/foo/bar/"
+alternate_command = "sh"
+"#;
+		let path = Path::new("/repo/.tramp.toml");
+		let config = parse_config_str_unvalidated(content, path).unwrap();
+
+		let diagnostics = check_config(&config, path, Path::new("/"));
+
+		assert_eq!(diagnostics.len(), 1);
+		assert_eq!(diagnostics[0].severity, Severity::Error);
+		assert_eq!(diagnostics[0].rule_index, Some(1));
+		assert_eq!(diagnostics[0].field, Some("alternate_command".to_string()));
+		assert!(diagnostics[0].message.contains("arg_rewrite"));
+	}
+
+	#[test]
+	fn test_validate_cascade_continues_past_broken_files() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let sub = temp_dir.path().join("sub");
+		std::fs::create_dir(&sub).unwrap();
+		std::fs::write(sub.join(".tramp.toml"), "invalid toml [[[").unwrap();
+		std::fs::write(
+			temp_dir.path().join(".tramp.toml"),
+			"no-external-lookup = true\n[[rules]]\narg_rewrite = \"s/a/b/\"\ncommand_rewrite = \"s/c/d/\"\n",
+		)
+		.unwrap();
+
+		let report = validate_cascade(&sub).unwrap();
+
+		assert!(report.has_errors());
+		assert_eq!(report.count(Severity::Error), 2);
+		assert_eq!(report.diagnostics[0].file, sub.join(".tramp.toml"));
+		assert!(report.diagnostics[0].rule_index.is_none());
+		assert_eq!(report.diagnostics[1].rule_index, Some(0));
+	}
+
+	fn rule_diagnostics(content: &str, cwd: &Path) -> Vec<Diagnostic> {
+		let path = Path::new("/repo/.tramp.toml");
+		let config = parse_config_str_unvalidated(content, path).unwrap();
+		check_config(&config, path, cwd)
+	}
+
+	#[test]
+	fn test_check_config_compiles_regexes_and_substitutions() {
+		let diagnostics = rule_diagnostics(
+			r#"
+[[rules]]
+binary_pattern = "[invalid"
+arg_rewrite = "s/(unclosed/x/"
+
+[[rules]]
+cwd_pattern = ".*"
+command_rewrite = "not-a-substitution"
+"#,
+			Path::new("/"),
+		);
+
+		let errors: Vec<_> = diagnostics
+			.iter()
+			.filter(|d| d.severity == Severity::Error)
+			.map(|d| (d.rule_index, d.field.as_deref()))
+			.collect();
+		assert_eq!(
+			errors,
+			vec![
+				(Some(0), Some("binary_pattern")),
+				(Some(0), Some("arg_rewrite")),
+				(Some(1), Some("command_rewrite")),
+			]
+		);
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn test_check_config_resolves_hooks_and_alternate_command() {
+		use std::os::unix::fs::PermissionsExt;
+
+		let temp_dir = tempfile::tempdir().unwrap();
+		let runnable = temp_dir.path().join("runnable.sh");
+		let not_executable = temp_dir.path().join("plain.sh");
+		std::fs::write(&runnable, "#!/bin/sh\n").unwrap();
+		std::fs::write(&not_executable, "#!/bin/sh\n").unwrap();
+		std::fs::set_permissions(&runnable, std::fs::Permissions::from_mode(0o755)).unwrap();
+		std::fs::set_permissions(&not_executable, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+		let diagnostics = rule_diagnostics(
+			r#"
+[[rules]]
+pre_hook = "runnable.sh"
+post_hook = "echo done"
+alternate_command = "sh"
+
+[[rules]]
+pre_hook = "plain.sh"
+intercept_hook = "./missing.sh --flag"
+post_hook = "nonexistent_command_12345 arg"
+alternate_command = "nonexistent_command_12345"
+"#,
+			temp_dir.path(),
+		);
+
+		let fields: Vec<_> = diagnostics
+			.iter()
+			.map(|d| (d.rule_index, d.field.as_deref()))
+			.collect();
+		assert_eq!(
+			fields,
+			vec![
+				(Some(1), Some("pre_hook")),
+				(Some(1), Some("intercept_hook")),
+				(Some(1), Some("post_hook")),
+				(Some(1), Some("alternate_command")),
+			]
+		);
+		assert!(diagnostics[0].message.contains("not executable"));
+		assert!(diagnostics[1].message.contains("Hook not found"));
+	}
+
+	#[test]
+	fn test_check_config_warns_about_rules_without_action() {
+		let diagnostics = rule_diagnostics(
+			r#"
+[[rules]]
+binary_pattern = ".*/cargo$"
+"#,
+			Path::new("/"),
+		);
+
+		assert_eq!(diagnostics.len(), 1);
+		assert_eq!(diagnostics[0].severity, Severity::Warning);
+		assert!(diagnostics[0].message.contains("no action"));
+	}
+
+	#[test]
+	fn test_check_shadowed_rules() {
+		let load = |content: &str, path: &str| LoadedConfig {
+			config: parse_config_str_unvalidated(content, Path::new(path)).unwrap(),
+			path: PathBuf::from(path),
+		};
+		let configs = vec![
+			load(
+				r#"
+[[rules]]
+binary_pattern = ".*/cargo$"
+pre_hook = "a.sh"
+
+[[rules]]
+binary_pattern = ".*/cargo$"
+pre_hook = "b.sh"
+
+[[rules]]
+binary_pattern = ".*/cargo$"
+cwd_pattern = "/repo"
+pre_hook = "c.sh"
+"#,
+				"/repo/.tramp.toml",
+			),
+			load(
+				r#"
+[[rules]]
+post_hook = "catch-all.sh"
+
+[[rules]]
+binary_pattern = ".*/npm$"
+post_hook = "npm.sh"
+"#,
+				"/home/user/.tramp.toml",
+			),
+		];
+
+		let diagnostics = check_shadowed_rules(&configs);
+
+		let shadowed: Vec<_> = diagnostics
+			.iter()
+			.map(|d| (d.file.as_path(), d.rule_index))
+			.collect();
+		assert_eq!(
+			shadowed,
+			vec![
+				(Path::new("/repo/.tramp.toml"), Some(1)),
+				(Path::new("/home/user/.tramp.toml"), Some(1)),
+			]
+		);
+		assert!(diagnostics[0].message.contains("identical matchers"));
+		assert!(diagnostics[1].message.contains("catch-all"));
+	}
+
+	#[test]
+	fn test_rules_that_do_not_always_apply_shadow_nothing() {
+		let path = PathBuf::from("/repo/.tramp.toml");
+		let content = r#"
+[[rules]]
+post_hook = "nightly.sh"
+active_between = ["22:00", "06:00"]
+
+[[rules]]
+binary_pattern = ".*/cargo$"
+pre_hook = "old.sh"
+expires = 2000-01-01
+
+[[rules]]
+binary_pattern = ".*/cargo$"
+pre_hook = "cargo.sh"
+"#;
+		let configs = vec![LoadedConfig {
+			config: parse_config_str_unvalidated(content, &path).unwrap(),
+			path,
+		}];
+
+		assert!(check_shadowed_rules(&configs).is_empty());
+	}
+
+	#[test]
+	fn test_group_rules_are_checked_with_their_group() {
+		let path = PathBuf::from("/repo/.tramp.toml");
+		let content = r#"
+[[groups]]
+id = "cargo"
+binary_pattern = ".*/cargo$"
+
+[[groups.rules]]
+pre_hook = "true"
+
+[[groups.rules]]
+cwd_pattern = "["
+pre_hook = "true"
+
+[[groups]]
+id = "broken"
+binary_pattern = "("
+
+[[groups.rules]]
+pre_hook = "true"
+"#;
+		let config = parse_config_str_unvalidated(content, &path).unwrap();
+
+		let diagnostics = check_config(&config, &path, Path::new("/"));
+		let found: Vec<_> = diagnostics
+			.iter()
+			.map(|d| {
+				(
+					d.group.as_ref().map(|g| g.to_string()),
+					d.rule_index,
+					d.line,
+				)
+			})
+			.collect();
+		assert_eq!(
+			found,
+			vec![
+				(Some("group broken".to_string()), None, Some(15)),
+				(Some("group cargo".to_string()), Some(1), Some(10)),
+			]
+		);
+
+		// The first rule of `cargo` only matches cargo, so it is not a catch-all
+		let configs = vec![
+			LoadedConfig { config, path },
+			LoadedConfig {
+				config: parse_config_str_unvalidated(
+					"[[rules]]\nbinary_pattern = \".*/npm$\"\npre_hook = \"true\"\n",
+					Path::new("/home/user/.tramp.toml"),
+				)
+				.unwrap(),
+				path: PathBuf::from("/home/user/.tramp.toml"),
+			},
+		];
+		assert!(check_shadowed_rules(&configs).is_empty());
+	}
+
+	#[test]
+	fn test_var_references_are_checked() {
+		let path = Path::new("/repo/.tramp.toml");
+		let content = r#"
+[vars.branch]
+cmd = ["git", "branch", "--show-current"]
+
+[vars.broken]
+cmd = []
+
+[[rules]]
+binary_pattern = ".*/deploy$"
+pre_hook = "/hooks/${vars.branch}.sh"
+alternate_command = "deploy-${vars.missing}"
+"#;
+		let config = parse_config_str_unvalidated(content, path).unwrap();
+		let vars = config.vars.keys().cloned().collect();
+
+		// Paths using variables aren't resolved until the variables are computed
+		let messages: Vec<_> = check_config(&config, path, Path::new("/"))
+			.into_iter()
+			.map(|d| (d.field, d.message))
+			.collect();
+		assert_eq!(
+			messages,
+			[(
+				Some("vars.broken".to_string()),
+				"Invalid variable `broken`: `cmd` is empty".to_string()
+			)]
+		);
+
+		let unknown = check_var_references(&config, path, &vars);
+		assert_eq!(unknown.len(), 1);
+		assert_eq!(unknown[0].field.as_deref(), Some("alternate_command"));
+		assert_eq!(unknown[0].line, Some(11));
+		assert_eq!(
+			unknown[0].message,
+			"Unknown variable `missing`: no config in the cascade defines it"
+		);
+	}
+
+	#[test]
+	fn test_check_expiry() {
+		let path = Path::new("/repo/.tramp.toml");
+		let content = r#"
+[[rules]]
+binary_pattern = ".*/make$"
+pre_hook = "true"
+expires = 2026-10-01
+
+[[rules]]
+binary_pattern = ".*/npm$"
+pre_hook = "true"
+expires = 2026-10-25
+
+[[rules]]
+binary_pattern = ".*/cargo$"
+pre_hook = "true"
+expires = 2027-01-01
+"#;
+		let config = parse_config_str_unvalidated(content, path).unwrap();
+
+		let found: Vec<_> = check_expiry(&config, path, jiff::civil::date(2026, 10, 18))
+			.into_iter()
+			.map(|d| (d.rule_index, d.line, d.message))
+			.collect();
+		assert_eq!(
+			found,
+			[
+				(
+					Some(0),
+					Some(5),
+					"Rule expired on 2026-10-01 and is skipped; `tramp rule prune` deletes it"
+						.to_string()
+				),
+				(
+					Some(1),
+					Some(10),
+					"Rule expires on 2026-10-25, in 7 day(s)".to_string()
+				),
+			]
+		);
+	}
+}
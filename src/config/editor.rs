@@ -0,0 +1,536 @@
+//! In-place editing of config files for `tramp rule` and `tramp config set`.
+//!
+//! Edits are made to the TOML document itself rather than re-serializing a
+//! parsed [`Config`](crate::config::Config), so comments and formatting are
+//! kept. Every edit is validated before it is written back.
+
+use crate::config::embedded::{is_embedded, split_config_path};
+use crate::config::parser::parse_config_str_as_written;
+use crate::config::types::{Config, Rule, RuleWithSource};
+use crate::error::{Result, TrampError};
+use crate::rules::{CompiledRule, Substitution};
+use jiff::civil::Date;
+use std::path::{Path, PathBuf};
+use toml_edit::{ArrayOfTables, DocumentMut, Item, Table, Value};
+
+/// A config file opened for editing.
+#[derive(Debug)]
+pub struct ConfigEditor {
+	path: PathBuf,
+	document: DocumentMut,
+}
+
+impl ConfigEditor {
+	/// Open a config file for editing. A missing file starts out empty.
+	///
+	/// Configs embedded in a manifest can't be edited.
+	pub fn open(path: &Path) -> Result<Self> {
+		if is_embedded(path) {
+			return Err(embedded_error(path));
+		}
+
+		let content = if path.exists() {
+			std::fs::read_to_string(path).map_err(|source| TrampError::ConfigReadError {
+				path: path.to_path_buf(),
+				source,
+			})?
+		} else {
+			String::new()
+		};
+
+		Self::from_str(&content, path)
+	}
+
+	/// Start editing config content that belongs to `path`.
+	pub fn from_str(content: &str, path: &Path) -> Result<Self> {
+		// Surface syntax errors with the usual located message
+		crate::config::parser::parse_config_str_unvalidated(content, path)?;
+
+		let document = content
+			.parse::<DocumentMut>()
+			.map_err(|e| edit_error(path, e.to_string()))?;
+
+		Ok(ConfigEditor {
+			path: path.to_path_buf(),
+			document,
+		})
+	}
+
+	/// The path of the file being edited.
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Find a rule by `id`, or else by 1-based position (as shown by `tramp config show`).
+	///
+	/// Returns the 0-based index of the rule.
+	pub fn find_rule(&self, selector: &str) -> Result<usize> {
+		let config = self.parse()?;
+
+		if let Some(index) = config
+			.rules
+			.iter()
+			.position(|rule| rule.id.as_deref() == Some(selector))
+		{
+			return Ok(index);
+		}
+
+		selector
+			.parse::<usize>()
+			.ok()
+			.filter(|n| (1..=config.rules.len()).contains(n))
+			.map(|n| n - 1)
+			.ok_or_else(|| TrampError::RuleNotFound {
+				path: self.path.clone(),
+				selector: selector.to_string(),
+			})
+	}
+
+	/// Insert a rule at `position` (0-based), or append it. Returns its 0-based index.
+	pub fn add_rule(&mut self, rule: &Rule, position: Option<usize>) -> Result<usize> {
+		let table = rule_table(rule, &self.path)?;
+		let len = self.rule_count();
+		let index = position.unwrap_or(len).min(len);
+
+		match self.document.get_mut("rules") {
+			None => {
+				let mut rules = ArrayOfTables::new();
+				rules.push(table);
+				self.document.insert("rules", Item::ArrayOfTables(rules));
+			}
+			Some(Item::ArrayOfTables(rules)) => {
+				// ArrayOfTables has no insert, so rebuild it around the new table
+				let mut tables: Vec<Table> = rules.iter().cloned().collect();
+				tables.insert(index, table);
+				rules.clear();
+				tables.into_iter().for_each(|t| rules.push(t));
+			}
+			Some(Item::Value(Value::Array(rules))) => {
+				rules.insert_formatted(index, Value::InlineTable(table.into_inline_table()));
+			}
+			Some(_) => return Err(edit_error(&self.path, "`rules` is not an array")),
+		}
+
+		Ok(index)
+	}
+
+	/// Remove the rule at `index` (0-based), returning it.
+	pub fn remove_rule(&mut self, index: usize) -> Result<Rule> {
+		let rule = self.parse()?.rules.remove(index);
+
+		if !remove_entry(self.document.get_mut("rules"), index) {
+			return Err(edit_error(&self.path, "`rules` is not an array"));
+		}
+
+		Ok(rule)
+	}
+
+	/// Delete every rule that has expired on `today`, including rules in groups.
+	///
+	/// Returns the deleted rules in file order. Rules in `when` sections are left alone.
+	pub fn prune_expired(&mut self, today: Date) -> Result<Vec<Rule>> {
+		let expired: Vec<(usize, Rule)> = self
+			.parse()?
+			.all_rules()
+			.filter(|(_, _, rule)| rule.is_expired(today))
+			.map(|(_, index, rule)| (index, rule.clone()))
+			.collect();
+
+		// Remove from the back, so the positions of the rest stay valid
+		for (index, rule) in expired.iter().rev() {
+			let rules = match rule.group {
+				None => self.document.get_mut("rules"),
+				Some(ref group) => group_mut(&mut self.document, group.index)
+					.and_then(|group| group.get_mut("rules")),
+			};
+			if !remove_entry(rules, *index) {
+				return Err(edit_error(&self.path, "`rules` is not an array"));
+			}
+		}
+
+		Ok(expired.into_iter().map(|(_, rule)| rule).collect())
+	}
+
+	/// Enable or disable the rule at `index` (0-based).
+	///
+	/// Enabling removes the `enabled` key rather than writing `enabled = true`.
+	pub fn set_rule_enabled(&mut self, index: usize, enabled: bool) -> Result<()> {
+		let path = self.path.clone();
+		let rule = self
+			.rule_mut(index)
+			.ok_or_else(|| edit_error(&path, "`rules` is not an array"))?;
+
+		if enabled {
+			rule.remove("enabled");
+		} else {
+			rule.insert("enabled", toml_edit::value(false));
+		}
+
+		Ok(())
+	}
+
+	/// Set a top-level key. `value` is parsed as a TOML value, falling back to
+	/// a plain string (so `CI` and `"CI"` both work).
+	pub fn set_key(&mut self, key: &str, value: &str) -> Result<()> {
+		let value = value
+			.parse::<Value>()
+			.unwrap_or_else(|_| Value::from(value));
+
+		match self.document.get_mut(key) {
+			Some(Item::Value(existing)) => {
+				// Keep the comments around the existing value
+				let decor = existing.decor().clone();
+				*existing = value;
+				*existing.decor_mut() = decor;
+			}
+			_ => {
+				self.document.insert(key, Item::Value(value));
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Remove a top-level key. Returns whether it was present.
+	pub fn unset_key(&mut self, key: &str) -> bool {
+		self.document.remove(key).is_some()
+	}
+
+	/// Validate the edited document, returning the config it now describes.
+	///
+	/// Beyond parsing, every rule's regexes and substitutions must compile, since
+	/// a broken rule would make every command fail.
+	pub fn validate(&self) -> Result<Config> {
+		let config = self.parse()?;
+
+		for (index, rule) in config.rules.iter().enumerate() {
+			let rws = RuleWithSource {
+				source: self.path.clone(),
+				source_index: index,
+				rule: rule.clone(),
+				group_matchers: None,
+			};
+			CompiledRule::from_rule_with_source(&rws)?;
+
+			for substitution in [&rule.arg_rewrite, &rule.command_rewrite]
+				.into_iter()
+				.flatten()
+			{
+				Substitution::parse(substitution)
+					.map_err(|e| rule.locate_error(&self.path, index, e))?;
+			}
+		}
+
+		Ok(config)
+	}
+
+	/// Validate the edited document and write it back to its file.
+	pub fn save(&self) -> Result<Config> {
+		let config = self.validate()?;
+
+		if let Some(parent) = self.path.parent()
+			&& !parent.as_os_str().is_empty()
+		{
+			std::fs::create_dir_all(parent).map_err(|e| {
+				edit_error(&self.path, format!("Failed to create directory: {}", e))
+			})?;
+		}
+
+		std::fs::write(&self.path, self.document.to_string())
+			.map_err(|e| edit_error(&self.path, format!("Failed to write file: {}", e)))?;
+
+		Ok(config)
+	}
+
+	/// Number of entries in the `rules` array.
+	fn rule_count(&self) -> usize {
+		match self.document.get("rules") {
+			Some(Item::ArrayOfTables(rules)) => rules.len(),
+			Some(Item::Value(Value::Array(rules))) => rules.len(),
+			_ => 0,
+		}
+	}
+
+	/// The rule at `index` as an editable table-like value.
+	fn rule_mut(&mut self, index: usize) -> Option<&mut dyn toml_edit::TableLike> {
+		match self.document.get_mut("rules")? {
+			Item::ArrayOfTables(rules) => rules
+				.get_mut(index)
+				.map(|t| t as &mut dyn toml_edit::TableLike),
+			Item::Value(Value::Array(rules)) => match rules.get_mut(index)? {
+				Value::InlineTable(t) => Some(t as &mut dyn toml_edit::TableLike),
+				_ => None,
+			},
+			_ => None,
+		}
+	}
+
+	fn parse(&self) -> Result<Config> {
+		parse_config_str_as_written(&self.document.to_string(), &self.path)
+	}
+}
+
+impl std::fmt::Display for ConfigEditor {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}", self.document)
+	}
+}
+
+/// The group at `index` as an editable table-like value.
+fn group_mut(document: &mut DocumentMut, index: usize) -> Option<&mut dyn toml_edit::TableLike> {
+	match document.get_mut("groups")? {
+		Item::ArrayOfTables(groups) => groups
+			.get_mut(index)
+			.map(|t| t as &mut dyn toml_edit::TableLike),
+		Item::Value(Value::Array(groups)) => match groups.get_mut(index)? {
+			Value::InlineTable(t) => Some(t as &mut dyn toml_edit::TableLike),
+			_ => None,
+		},
+		_ => None,
+	}
+}
+
+/// Remove the entry at `index` from an array of tables or an inline array.
+/// Returns whether there was one to remove.
+fn remove_entry(array: Option<&mut Item>, index: usize) -> bool {
+	match array {
+		Some(Item::ArrayOfTables(tables)) if index < tables.len() => {
+			tables.remove(index);
+			true
+		}
+		Some(Item::Value(Value::Array(values))) if index < values.len() => {
+			values.remove(index);
+			true
+		}
+		_ => false,
+	}
+}
+
+/// Serialize a rule into a TOML table, keeping the field order of [`Rule`].
+fn rule_table(rule: &Rule, path: &Path) -> Result<Table> {
+	let content = toml::to_string(rule).map_err(|e| edit_error(path, e.to_string()))?;
+	let document = content
+		.parse::<DocumentMut>()
+		.map_err(|e| edit_error(path, e.to_string()))?;
+
+	let mut table = document.as_table().clone();
+	table.set_implicit(false);
+	table.decor_mut().clear();
+	Ok(table)
+}
+
+/// The error for an attempt to rewrite a config embedded in a manifest, which
+/// tramp leaves to the manifest's own tools.
+pub(crate) fn embedded_error(path: &Path) -> TrampError {
+	let (file, _) = split_config_path(path);
+	edit_error(
+		path,
+		format!(
+			"configs embedded in {} can't be changed by tramp; edit it directly",
+			file.display()
+		),
+	)
+}
+
+fn edit_error(path: &Path, message: impl Into<String>) -> TrampError {
+	TrampError::ConfigEditError {
+		path: path.to_path_buf(),
+		message: message.into(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const CONTENT: &str = r#"# Team config
+root = true # stop here
+
+# Release builds
+[[rules]]
+id = "release"
+binary_pattern = ".*/cargo$"
+arg_rewrite = "s/^build$/build --release/"
+
+# Use pnpm
+[[rules]]
+binary_pattern = ".*/npm$"
+alternate_command = "pnpm"
+"#;
+
+	fn editor(content: &str) -> ConfigEditor {
+		ConfigEditor::from_str(content, Path::new("/repo/.tramp.toml")).unwrap()
+	}
+
+	#[test]
+	fn test_find_rule_by_id_or_position() {
+		let editor = editor(CONTENT);
+		assert_eq!(editor.find_rule("release").unwrap(), 0);
+		assert_eq!(editor.find_rule("2").unwrap(), 1);
+		assert!(matches!(
+			editor.find_rule("3"),
+			Err(TrampError::RuleNotFound { .. })
+		));
+		assert!(editor.find_rule("missing").is_err());
+	}
+
+	#[test]
+	fn test_add_rule_keeps_comments() {
+		let mut editor = editor(CONTENT);
+		let rule = Rule {
+			id: Some("log".to_string()),
+			binary_pattern: Some(".*/make$".to_string()),
+			pre_hook: Some(PathBuf::from("./log.sh")),
+			..Default::default()
+		};
+
+		assert_eq!(editor.add_rule(&rule, Some(1)).unwrap(), 1);
+		let output = editor.to_string();
+
+		assert!(output.contains("# Team config"));
+		assert!(output.contains("root = true # stop here"));
+		assert!(output.contains("# Use pnpm"));
+
+		let config = editor.validate().unwrap();
+		assert_eq!(config.rules.len(), 3);
+		assert_eq!(config.rules[1].id.as_deref(), Some("log"));
+		assert_eq!(config.rules[2].alternate_command.as_deref(), Some("pnpm"));
+	}
+
+	#[test]
+	fn test_add_rule_to_inline_array_and_empty_file() {
+		let mut inline = editor("rules = [\n  { binary_pattern = \"a\", pre_hook = \"x\" },\n]\n");
+		inline.add_rule(&Rule::default(), None).unwrap();
+		assert_eq!(inline.validate().unwrap().rules.len(), 2);
+
+		let mut empty = editor("");
+		empty
+			.add_rule(
+				&Rule {
+					binary_pattern: Some("b".to_string()),
+					..Default::default()
+				},
+				None,
+			)
+			.unwrap();
+		assert_eq!(empty.to_string(), "[[rules]]\nbinary_pattern = \"b\"\n");
+	}
+
+	#[test]
+	fn test_remove_and_toggle_rules() {
+		let mut editor = editor(CONTENT);
+
+		editor.set_rule_enabled(1, false).unwrap();
+		assert!(!editor.validate().unwrap().rules[1].is_enabled());
+		assert!(editor.to_string().contains("enabled = false"));
+
+		editor.set_rule_enabled(1, true).unwrap();
+		assert!(!editor.to_string().contains("enabled"));
+
+		let removed = editor.remove_rule(0).unwrap();
+		assert_eq!(removed.id.as_deref(), Some("release"));
+		let output = editor.to_string();
+		assert!(!output.contains("release"));
+		assert!(output.contains("# Use pnpm"));
+	}
+
+	#[test]
+	fn test_prune_expired_rules() {
+		let mut editor = editor(
+			r#"rules = [
+  { id = "old", binary_pattern = "make", pre_hook = "true", expires = 2026-01-01 },
+  { id = "kept", binary_pattern = "npm", pre_hook = "true", expires = 2027-01-01 },
+]
+
+[[groups]]
+binary_pattern = ".*/cargo$"
+
+# Temporary timing
+[[groups.rules]]
+id = "timing"
+pre_hook = "true"
+expires = 2026-06-30
+
+[[groups.rules]]
+id = "logging"
+pre_hook = "true"
+"#,
+		);
+
+		let pruned = editor.prune_expired(jiff::civil::date(2026, 7, 1)).unwrap();
+		let ids: Vec<_> = pruned.iter().map(|r| r.id.as_deref().unwrap()).collect();
+		assert_eq!(ids, ["old", "timing"]);
+
+		let config = editor.validate().unwrap();
+		assert_eq!(config.rules[0].id.as_deref(), Some("kept"));
+		assert_eq!(config.groups[0].rules.len(), 1);
+		assert_eq!(config.groups[0].rules[0].id.as_deref(), Some("logging"));
+		assert!(!editor.to_string().contains("Temporary timing"));
+	}
+
+	#[test]
+	fn test_set_and_unset_keys() {
+		let mut editor = editor(CONTENT);
+
+		editor.set_key("root", "false").unwrap();
+		editor
+			.set_key("root-config-lookup-disable-env-var", "CI")
+			.unwrap();
+		editor.set_key("max-depth", "4").unwrap();
+
+		let output = editor.to_string();
+		assert!(output.contains("root = false # stop here"));
+
+		let config = editor.validate().unwrap();
+		assert!(!config.root);
+		assert_eq!(
+			config.root_config_lookup_disable_env_var.as_deref(),
+			Some("CI")
+		);
+		assert_eq!(config.max_depth, Some(4));
+
+		assert!(editor.unset_key("max-depth"));
+		assert!(!editor.unset_key("max-depth"));
+	}
+
+	#[test]
+	fn test_invalid_edits_fail_validation() {
+		let mut editor = editor(CONTENT);
+		editor.set_key("max_depth", "4").unwrap();
+		assert!(matches!(
+			editor.validate(),
+			Err(TrampError::UnknownKey { .. })
+		));
+
+		let mut editor = self::editor(CONTENT);
+		editor
+			.add_rule(
+				&Rule {
+					arg_rewrite: Some("s/a/b/".to_string()),
+					alternate_command: Some("pnpm".to_string()),
+					..Default::default()
+				},
+				None,
+			)
+			.unwrap();
+		assert!(matches!(
+			editor.validate(),
+			Err(TrampError::InvalidRule { .. })
+		));
+
+		let mut editor = self::editor(CONTENT);
+		editor
+			.add_rule(
+				&Rule {
+					binary_pattern: Some("[unclosed".to_string()),
+					..Default::default()
+				},
+				None,
+			)
+			.unwrap();
+		assert!(matches!(
+			editor.validate(),
+			Err(TrampError::InvalidRule { .. })
+		));
+	}
+}
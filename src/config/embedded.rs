@@ -0,0 +1,368 @@
+//! Configs embedded in project manifests.
+//!
+//! Besides `.tramp.toml`, each directory of the cascade may carry its config
+//! inside a manifest it already has:
+//!
+//! - `[package.metadata.tramp]` in `Cargo.toml`
+//! - `[workspace.metadata.tramp]` in `Cargo.toml`
+//! - a `"tramp"` key in `package.json`
+//!
+//! An embedded config is identified by the manifest path with its key path
+//! as a suffix, e.g. `Cargo.toml#package.metadata.tramp`, so it can be told
+//! apart from the manifest itself wherever config paths are reported.
+
+use crate::config::env_rules::{RULES_ENV_VAR, env_rules_value, is_env_rules};
+use crate::config::location::config_table;
+use crate::error::{Result, TrampError};
+use serde::Deserializer;
+use serde::de::{DeserializeOwned, DeserializeSeed, IgnoredAny, MapAccess, Visitor};
+use std::fmt;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use toml::de::DeTable;
+
+/// File name of a standalone config.
+pub const CONFIG_FILE_NAME: &str = ".tramp.toml";
+
+/// File name of a developer's personal, uncommitted overrides, read next to
+/// a repository config.
+pub const LOCAL_CONFIG_FILE_NAME: &str = ".tramp.local.toml";
+
+/// Separates a manifest path from the key path of the config inside it.
+pub const KEY_PATH_SEPARATOR: char = '#';
+
+/// A manifest that can embed a config, and where within it the config lives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmbeddedSource {
+	/// The manifest's file name.
+	pub file_name: &'static str,
+
+	/// Dotted path of the table holding the config.
+	pub key_path: &'static str,
+}
+
+/// Every place a config may be embedded, in order of precedence.
+///
+/// In any one directory only the first config found is used: `.tramp.toml`,
+/// then these in order. The rest are ignored, and `tramp config validate`
+/// warns about them.
+pub const EMBEDDED_SOURCES: &[EmbeddedSource] = &[
+	EmbeddedSource {
+		file_name: "Cargo.toml",
+		key_path: "package.metadata.tramp",
+	},
+	EmbeddedSource {
+		file_name: "Cargo.toml",
+		key_path: "workspace.metadata.tramp",
+	},
+	EmbeddedSource {
+		file_name: "package.json",
+		key_path: "tramp",
+	},
+];
+
+/// The syntax a config is written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+	Toml,
+	Json,
+}
+
+impl ConfigFormat {
+	/// The format of the file a config path refers to.
+	pub fn of(path: &Path) -> Self {
+		let (file, _) = split_config_path(path);
+		if file.extension().is_some_and(|ext| ext == "json") {
+			ConfigFormat::Json
+		} else {
+			ConfigFormat::Toml
+		}
+	}
+}
+
+/// The config path for a config embedded at `key_path` within `file`.
+pub fn embedded_config_path(file: &Path, key_path: &str) -> PathBuf {
+	let mut path = file.as_os_str().to_owned();
+	path.push(format!("{}{}", KEY_PATH_SEPARATOR, key_path));
+	PathBuf::from(path)
+}
+
+/// Split a config path into the file to read and the key path within it, if any.
+pub fn split_config_path(path: &Path) -> (PathBuf, Option<String>) {
+	let text = path.to_string_lossy();
+	let embedded = path
+		.file_name()
+		.and_then(|name| name.to_str())
+		.and_then(|name| name.split_once(KEY_PATH_SEPARATOR))
+		.filter(|(file_name, _)| EMBEDDED_SOURCES.iter().any(|s| s.file_name == *file_name));
+
+	match embedded {
+		Some((_, key_path)) => {
+			let file = &text[..text.len() - key_path.len() - 1];
+			(PathBuf::from(file), Some(key_path.to_string()))
+		}
+		None => (path.to_path_buf(), None),
+	}
+}
+
+/// Whether a config path refers to a config embedded in a manifest.
+pub fn is_embedded(path: &Path) -> bool {
+	split_config_path(path).1.is_some()
+}
+
+/// Read the file holding the config at `path`, or the value of `TRAMP_RULES`
+/// for its rules.
+pub fn read_config_source(path: &Path) -> Result<String> {
+	if is_env_rules(path) {
+		return env_rules_value().ok_or_else(|| TrampError::ConfigReadError {
+			path: path.to_path_buf(),
+			source: std::io::Error::new(
+				std::io::ErrorKind::NotFound,
+				format!("${} is not set", RULES_ENV_VAR),
+			),
+		});
+	}
+
+	let (file, _) = split_config_path(path);
+	std::fs::read_to_string(&file).map_err(|source| TrampError::ConfigReadError {
+		path: path.to_path_buf(),
+		source,
+	})
+}
+
+/// The config used in `dir`: the first one present, in order of precedence.
+///
+/// Manifests are only read if no config with a higher precedence is present.
+pub fn find_config(dir: &Path) -> Option<PathBuf> {
+	candidates(dir).next()
+}
+
+/// Every config present in `dir`, in order of precedence.
+///
+/// Manifests that don't parse are treated as having no config, so a broken
+/// `package.json` is left for its own tools to report.
+pub fn config_candidates(dir: &Path) -> Vec<PathBuf> {
+	candidates(dir).collect()
+}
+
+/// The configs present in `dir`, in order of precedence, reading each
+/// manifest only when the iterator gets to it.
+fn candidates(dir: &Path) -> impl Iterator<Item = PathBuf> {
+	let standalone = dir.join(CONFIG_FILE_NAME);
+	let standalone = standalone.exists().then_some(standalone);
+	let dir = dir.to_path_buf();
+
+	// Sources in the same manifest are next to each other
+	let manifests = EMBEDDED_SOURCES.chunk_by(|a, b| a.file_name == b.file_name);
+	standalone.into_iter().chain(
+		manifests
+			.flat_map(move |sources| embedded_configs(&dir.join(sources[0].file_name), sources)),
+	)
+}
+
+/// The configs embedded in `file` at the key paths of `sources`, parsing it once.
+fn embedded_configs(file: &Path, sources: &[EmbeddedSource]) -> Vec<PathBuf> {
+	let Ok(content) = std::fs::read_to_string(file) else {
+		return Vec::new();
+	};
+
+	let present: Vec<bool> = match ConfigFormat::of(file) {
+		ConfigFormat::Toml => {
+			let Ok(document) = DeTable::parse(&content) else {
+				return Vec::new();
+			};
+			sources
+				.iter()
+				.map(|source| {
+					let keys = key_path_segments(source.key_path);
+					let (last, parents) = keys.split_last().expect("key paths are never empty");
+					config_table(document.get_ref(), parents)
+						.is_some_and(|table| table.contains_key(*last))
+				})
+				.collect()
+		}
+		ConfigFormat::Json => {
+			let Ok(document) = serde_json::from_str::<serde_json::Value>(&content) else {
+				return Vec::new();
+			};
+			sources
+				.iter()
+				.map(|source| {
+					key_path_segments(source.key_path)
+						.iter()
+						.try_fold(&document, |value, key| value.get(key))
+						.is_some()
+				})
+				.collect()
+		}
+	};
+
+	sources
+		.iter()
+		.zip(present)
+		.filter(|(_, present)| *present)
+		.map(|(source, _)| embedded_config_path(file, source.key_path))
+		.collect()
+}
+
+/// Split a dotted key path into its keys.
+pub fn key_path_segments(key_path: &str) -> Vec<&str> {
+	key_path.split('.').collect()
+}
+
+/// Deserializes the value at a key path within a document, or `None` if any
+/// key along the way is missing.
+///
+/// Other keys are skipped without being interpreted, and errors in the value
+/// keep their position within the whole document.
+pub(crate) struct AtKeyPath<'a, T> {
+	keys: &'a [&'a str],
+	marker: PhantomData<T>,
+}
+
+impl<'a, T> AtKeyPath<'a, T> {
+	pub(crate) fn new(keys: &'a [&'a str]) -> Self {
+		AtKeyPath {
+			keys,
+			marker: PhantomData,
+		}
+	}
+}
+
+impl<'de, T: DeserializeOwned> DeserializeSeed<'de> for AtKeyPath<'_, T> {
+	type Value = Option<T>;
+
+	fn deserialize<D: Deserializer<'de>>(
+		self,
+		deserializer: D,
+	) -> std::result::Result<Self::Value, D::Error> {
+		match self.keys.split_first() {
+			None => T::deserialize(deserializer).map(Some),
+			Some((key, rest)) => deserializer.deserialize_map(KeyVisitor {
+				key,
+				rest,
+				marker: PhantomData::<T>,
+			}),
+		}
+	}
+}
+
+struct KeyVisitor<'a, T> {
+	key: &'a str,
+	rest: &'a [&'a str],
+	marker: PhantomData<T>,
+}
+
+impl<'de, T: DeserializeOwned> Visitor<'de> for KeyVisitor<'_, T> {
+	type Value = Option<T>;
+
+	fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "a table containing `{}`", self.key)
+	}
+
+	fn visit_map<A: MapAccess<'de>>(
+		self,
+		mut map: A,
+	) -> std::result::Result<Self::Value, A::Error> {
+		let mut found = None;
+		while let Some(key) = map.next_key::<String>()? {
+			if key == self.key {
+				found = map.next_value_seed(AtKeyPath::<T>::new(self.rest))?;
+			} else {
+				map.next_value::<IgnoredAny>()?;
+			}
+		}
+		Ok(found)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_split_config_path() {
+		let path = embedded_config_path(Path::new("/repo/Cargo.toml"), "package.metadata.tramp");
+		assert_eq!(
+			path,
+			PathBuf::from("/repo/Cargo.toml#package.metadata.tramp")
+		);
+		assert_eq!(
+			split_config_path(&path),
+			(
+				PathBuf::from("/repo/Cargo.toml"),
+				Some("package.metadata.tramp".to_string())
+			)
+		);
+		assert!(is_embedded(&path));
+
+		let standalone = Path::new("/repo#1/.tramp.toml");
+		assert_eq!(
+			split_config_path(standalone),
+			(standalone.to_path_buf(), None)
+		);
+		assert!(!is_embedded(standalone));
+	}
+
+	#[test]
+	fn test_config_candidates_in_precedence_order() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		assert!(config_candidates(dir).is_empty());
+
+		std::fs::write(
+			dir.join("Cargo.toml"),
+			"[workspace]\nmembers = []\n\n[workspace.metadata.tramp]\nroot = true\n",
+		)
+		.unwrap();
+		std::fs::write(dir.join("package.json"), r#"{"name": "x", "tramp": {}}"#).unwrap();
+		assert_eq!(
+			config_candidates(dir),
+			vec![
+				dir.join("Cargo.toml#workspace.metadata.tramp"),
+				dir.join("package.json#tramp"),
+			]
+		);
+
+		std::fs::write(dir.join(".tramp.toml"), "").unwrap();
+		assert_eq!(config_candidates(dir)[0], dir.join(".tramp.toml"));
+		assert_eq!(find_config(dir), Some(dir.join(".tramp.toml")));
+	}
+
+	#[test]
+	fn test_both_cargo_keys_are_candidates() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		std::fs::write(
+			dir.join("Cargo.toml"),
+			"[package]\nname = \"x\"\n\n[package.metadata.tramp]\n\n[workspace.metadata.tramp]\nroot = true\n",
+		)
+		.unwrap();
+
+		assert_eq!(
+			config_candidates(dir),
+			vec![
+				dir.join("Cargo.toml#package.metadata.tramp"),
+				dir.join("Cargo.toml#workspace.metadata.tramp"),
+			]
+		);
+		assert_eq!(
+			find_config(dir),
+			Some(dir.join("Cargo.toml#package.metadata.tramp"))
+		);
+	}
+
+	#[test]
+	fn test_manifest_without_config_is_not_a_candidate() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path();
+		std::fs::write(
+			dir.join("Cargo.toml"),
+			"[package]\nname = \"x\"\n\n[package.metadata.docs]\nall = true\n",
+		)
+		.unwrap();
+		std::fs::write(dir.join("package.json"), r#"{"tramp": "#).unwrap();
+
+		assert!(config_candidates(dir).is_empty());
+	}
+}
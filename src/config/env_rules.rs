@@ -0,0 +1,117 @@
+//! Rules injected through the `TRAMP_RULES` environment variable.
+//!
+//! CI jobs and one-off debugging sessions can add rules without writing any
+//! file. The rules form the first layer of the cascade, ahead of every config
+//! file, and are reported as coming from `<env:TRAMP_RULES>`. The value is
+//! either a JSON array of rules or a TOML document with a `rules` array:
+//!
+//! ```sh
+//! TRAMP_RULES='[{"binary_pattern": ".*/cargo$", "pre_hook": "/tmp/log.sh"}]'
+//! TRAMP_RULES='rules = [{ binary_pattern = ".*/cargo$", pre_hook = "/tmp/log.sh" }]'
+//! ```
+//!
+//! A user config with `no-env-rules = true` turns the variable off.
+
+use crate::config::location::locate_rules;
+use crate::config::parser::{json_parse_error, parse_error};
+use crate::config::types::{Config, Rule};
+use crate::error::Result;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// The environment variable holding injected rules.
+pub const RULES_ENV_VAR: &str = "TRAMP_RULES";
+
+/// The config path reported for rules from `TRAMP_RULES`.
+pub const ENV_RULES_SOURCE: &str = "<env:TRAMP_RULES>";
+
+/// The config path of the rules from `TRAMP_RULES`.
+pub fn env_rules_path() -> PathBuf {
+	PathBuf::from(ENV_RULES_SOURCE)
+}
+
+/// Whether a config path refers to the rules from `TRAMP_RULES`.
+pub fn is_env_rules(path: &Path) -> bool {
+	path == Path::new(ENV_RULES_SOURCE)
+}
+
+/// The value of `TRAMP_RULES`, or `None` if it is unset or blank.
+pub fn env_rules_value() -> Option<String> {
+	std::env::var(RULES_ENV_VAR)
+		.ok()
+		.filter(|value| !value.trim().is_empty())
+}
+
+/// The TOML form of `TRAMP_RULES`: rules and nothing else.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnvRules {
+	#[serde(default)]
+	rules: Vec<Rule>,
+}
+
+/// Read the value of `TRAMP_RULES` as a config holding only its rules.
+pub(crate) fn read_env_rules(content: &str, path: &Path) -> Result<Config> {
+	let trimmed = content.trim_start();
+	let rules = if trimmed.starts_with('[') && !trimmed.starts_with("[[") {
+		serde_json::from_str(content).map_err(|source| json_parse_error(content, path, source))?
+	} else {
+		let env_rules: EnvRules =
+			toml::from_str(content).map_err(|source| parse_error(content, path, source))?;
+		let mut rules = env_rules.rules;
+		for (rule, location) in rules.iter_mut().zip(locate_rules(content, &[])) {
+			rule.location = Some(location);
+		}
+		rules
+	};
+
+	Ok(Config {
+		rules,
+		..Default::default()
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::config::parse_config_str;
+	use crate::error::TrampError;
+
+	#[test]
+	fn test_read_env_rules_as_json_or_toml() {
+		let path = env_rules_path();
+
+		let json = r#"[{"binary_pattern": ".*/cargo$", "pre_hook": "/tmp/log.sh"}]"#;
+		let config = parse_config_str(json, &path).unwrap();
+		assert_eq!(config.rules.len(), 1);
+		assert_eq!(config.rules[0].binary_pattern.as_deref(), Some(".*/cargo$"));
+
+		let toml = "[[rules]]\nbinary_pattern = \".*/npm$\"\npre_hook = \"/tmp/log.sh\"\n";
+		let config = parse_config_str(toml, &path).unwrap();
+		assert_eq!(config.rules[0].location.as_ref().unwrap().start.line, 1);
+
+		let inline = r#"rules = [{ binary_pattern = ".*/make$", pre_hook = "/tmp/log.sh" }]"#;
+		let config = parse_config_str(inline, &path).unwrap();
+		assert_eq!(config.rules[0].binary_pattern.as_deref(), Some(".*/make$"));
+	}
+
+	#[test]
+	fn test_env_rules_hold_only_rules() {
+		let path = env_rules_path();
+
+		let err = parse_config_str("root = true\n", &path).unwrap_err();
+		assert!(matches!(err, TrampError::UnknownKey { ref key, .. } if key == "root"));
+
+		let err = parse_config_str(r#"[{"binary_patern": "x"}]"#, &path).unwrap_err();
+		assert!(matches!(err, TrampError::UnknownKey { ref suggestion, .. }
+			if suggestion.as_deref() == Some("binary_pattern")));
+
+		// Rules are validated like any other
+		let err = parse_config_str(
+			r#"[{"arg_rewrite": "s/a/b/", "alternate_command": "true"}]"#,
+			&path,
+		)
+		.unwrap_err();
+		assert!(matches!(err, TrampError::MutuallyExclusive { .. }));
+	}
+}
@@ -0,0 +1,273 @@
+//! Source locations within config files.
+//!
+//! Spans from the TOML parser are kept alongside parsed rules so that errors
+//! about a rule can point at the file, line, and column it came from, with a
+//! snippet of the offending line.
+
+use serde::Serialize;
+use std::ops::Range;
+use toml::Spanned;
+use toml::de::{DeTable, DeValue};
+
+/// A position in a config file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SourceLocation {
+	/// Line number (1-based).
+	pub line: usize,
+
+	/// Column number in characters (1-based).
+	pub column: usize,
+
+	/// The full text of the line, for snippets.
+	#[serde(skip)]
+	pub line_text: String,
+
+	/// Number of characters to underline, starting at `column`.
+	#[serde(skip)]
+	pub width: usize,
+}
+
+impl SourceLocation {
+	/// Locate a byte span within `content`.
+	///
+	/// Spans covering several lines are clipped to the end of their first line.
+	pub fn from_span(content: &str, span: Range<usize>) -> Self {
+		let start = span.start.min(content.len());
+		let end = span.end.clamp(start, content.len());
+
+		let line_start = content[..start].rfind('\n').map_or(0, |i| i + 1);
+		let line_end = content[start..]
+			.find('\n')
+			.map_or(content.len(), |i| start + i);
+		let line_text = content[line_start..line_end].trim_end_matches('\r');
+
+		SourceLocation {
+			line: content[..start].matches('\n').count() + 1,
+			column: content[line_start..start].chars().count() + 1,
+			line_text: line_text.to_string(),
+			width: content[start..end.min(line_end)].chars().count().max(1),
+		}
+	}
+
+	/// Locate a 1-based line and column (in characters) within `content`.
+	pub fn from_line_column(content: &str, line: usize, column: usize) -> Self {
+		let line_start: usize = content
+			.split_inclusive('\n')
+			.take(line.saturating_sub(1))
+			.map(str::len)
+			.sum();
+		let start = content[line_start..]
+			.char_indices()
+			.nth(column.saturating_sub(1))
+			.map_or(content.len(), |(i, _)| line_start + i);
+
+		Self::from_span(content, start..start)
+	}
+
+	/// Render the offending line with the span underlined, compiler-style:
+	///
+	/// ```text
+	///   |
+	/// 3 | binary_pattern = "[unclosed"
+	///   |                  ^^^^^^^^^^^
+	/// ```
+	pub fn snippet(&self) -> String {
+		let number = self.line.to_string();
+		let gutter = " ".repeat(number.len());
+		format!(
+			"{gutter} |\n{number} | {}\n{gutter} | {}{}",
+			self.line_text,
+			" ".repeat(self.column - 1),
+			"^".repeat(self.width),
+		)
+	}
+}
+
+/// Where a rule and each of its fields were defined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleLocation {
+	/// The start of the rule (its `[[rules]]` header or inline table).
+	pub start: SourceLocation,
+
+	/// Each field of the rule, as `(field, location of "key = value")` pairs.
+	pub fields: Vec<(String, SourceLocation)>,
+}
+
+impl RuleLocation {
+	/// The location of a field, falling back to the start of the rule.
+	pub fn field(&self, name: &str) -> &SourceLocation {
+		self.fields
+			.iter()
+			.find(|(field, _)| field == name)
+			.map_or(&self.start, |(_, location)| location)
+	}
+}
+
+/// Find the location of every entry in the `rules` array of a config file.
+///
+/// `keys` is the path of the table holding the config, for configs embedded
+/// in a manifest; it is empty for a standalone config. Returns an empty list
+/// if the document doesn't parse or has no rules.
+pub fn locate_rules(content: &str, keys: &[&str]) -> Vec<RuleLocation> {
+	let Ok(document) = DeTable::parse(content) else {
+		return Vec::new();
+	};
+
+	config_table(document.get_ref(), keys)
+		.map(|table| locate_tables(content, table, "rules"))
+		.unwrap_or_default()
+}
+
+/// Find the location of every entry in the `rules` array of a parsed config
+/// table. `content` is the document the table was parsed from.
+pub(crate) fn locate_rules_in(content: &str, table: &DeTable<'_>) -> Vec<RuleLocation> {
+	locate_tables(content, table, "rules")
+}
+
+/// Find the location of every entry in the `groups` array of a parsed config
+/// table, along with the locations of each group's rules.
+pub(crate) fn locate_groups_in(
+	content: &str,
+	table: &DeTable<'_>,
+) -> Vec<(RuleLocation, Vec<RuleLocation>)> {
+	let Some(DeValue::Array(groups)) = table.get("groups").map(|v| v.get_ref()) else {
+		return Vec::new();
+	};
+
+	groups
+		.iter()
+		.map(|group| {
+			let rules = match group.get_ref() {
+				DeValue::Table(group) => locate_tables(content, group, "rules"),
+				_ => Vec::new(),
+			};
+			(table_location(content, group), rules)
+		})
+		.collect()
+}
+
+/// The table holding the config: the document itself, or the table at `keys`.
+pub(crate) fn config_table<'a, 'i>(
+	document: &'a DeTable<'i>,
+	keys: &[&str],
+) -> Option<&'a DeTable<'i>> {
+	let mut table = document;
+	for key in keys {
+		match table.get(*key).map(|v| v.get_ref()) {
+			Some(DeValue::Table(inner)) => table = inner,
+			_ => return None,
+		}
+	}
+	Some(table)
+}
+
+/// Locate every entry of the array of tables at `key` within `table`.
+fn locate_tables(content: &str, table: &DeTable<'_>, key: &str) -> Vec<RuleLocation> {
+	match table.get(key).map(|v| v.get_ref()) {
+		Some(DeValue::Array(entries)) => entries
+			.iter()
+			.map(|entry| table_location(content, entry))
+			.collect(),
+		_ => Vec::new(),
+	}
+}
+
+/// Locate a table and each of its fields.
+fn table_location(content: &str, value: &Spanned<DeValue<'_>>) -> RuleLocation {
+	let fields = match value.get_ref() {
+		DeValue::Table(table) => table
+			.iter()
+			.map(|(key, value)| {
+				let span = key.span().start..value.span().end;
+				(
+					key.get_ref().to_string(),
+					SourceLocation::from_span(content, span),
+				)
+			})
+			.collect(),
+		_ => Vec::new(),
+	};
+
+	RuleLocation {
+		start: SourceLocation::from_span(content, value.span()),
+		fields,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_from_span_computes_line_and_column() {
+		let content = "root = true\n\n[[rules]]\nbinary_pattern = \"[x\"\n";
+		let start = content.find("\"[x\"").unwrap();
+		let location = SourceLocation::from_span(content, start..start + 4);
+
+		assert_eq!(location.line, 4);
+		assert_eq!(location.column, 18);
+		assert_eq!(location.line_text, "binary_pattern = \"[x\"");
+		assert_eq!(
+			location.snippet(),
+			"  |\n4 | binary_pattern = \"[x\"\n  |                  ^^^^"
+		);
+	}
+
+	#[test]
+	fn test_locate_rules_finds_fields() {
+		let content = r#"
+[[rules]]
+binary_pattern = ".*/cargo$"
+
+[[rules]]
+cwd_pattern = "^/repo"
+arg_rewrite = "s/a/b/"
+"#;
+		let rules = locate_rules(content, &[]);
+		assert_eq!(rules.len(), 2);
+
+		let location = rules[1].field("arg_rewrite");
+		assert_eq!(location.line, 7);
+		assert_eq!(location.column, 1);
+		assert_eq!(location.width, "arg_rewrite = \"s/a/b/\"".len());
+
+		// Unknown fields fall back to the start of the rule
+		assert_eq!(rules[0].field("pre_hook").line, rules[0].start.line);
+	}
+
+	#[test]
+	fn test_locate_groups_finds_group_rules() {
+		let content = r#"
+[[rules]]
+binary_pattern = "make"
+
+[[groups]]
+id = "cargo"
+binary_pattern = ".*/cargo$"
+
+[[groups.rules]]
+cwd_pattern = "^/repo"
+
+[[groups.rules]]
+arg_rewrite = "s/a/b/"
+"#;
+		let document = DeTable::parse(content).unwrap();
+		let groups = locate_groups_in(content, document.get_ref());
+		assert_eq!(groups.len(), 1);
+
+		let (group, rules) = &groups[0];
+		assert_eq!(group.field("binary_pattern").line, 7);
+		assert_eq!(rules.len(), 2);
+		assert_eq!(rules[1].field("arg_rewrite").line, 13);
+	}
+
+	#[test]
+	fn test_locate_rules_inline_tables() {
+		let content = "rules = [\n  { binary_pattern = \"a\", post_hook = \"b\" },\n]\n";
+		let rules = locate_rules(content, &[]);
+
+		let location = rules[0].field("post_hook");
+		assert_eq!(location.line, 2);
+		assert_eq!(location.column, 27);
+	}
+}
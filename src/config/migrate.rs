@@ -0,0 +1,277 @@
+//! Upgrading config files to the current format version for `tramp config migrate`.
+//!
+//! Old configs keep working without migrating: the parser reads every
+//! supported version. Migrating rewrites a file in the current format so it
+//! can use new features, editing the TOML document in place so comments and
+//! formatting are kept.
+
+use crate::config::editor::embedded_error;
+use crate::config::embedded::is_embedded;
+use crate::config::parser::{config_version, parse_config_str_unvalidated};
+use crate::error::{Result, TrampError};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use toml_edit::{DocumentMut, Item, Value};
+
+/// One step from a config version to the next.
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+	/// The version this step upgrades from; it produces `from + 1`.
+	pub from: u32,
+
+	/// Summary of the change, shown by `tramp config migrate`.
+	pub description: &'static str,
+
+	/// Rewrite the document. Returns a description of each edit made.
+	pub apply: fn(&mut DocumentMut) -> Vec<String>,
+}
+
+/// Every migration step, in version order.
+///
+/// Version 1 is the first versioned format, so there is nothing to upgrade yet.
+/// When the format changes, add a step here alongside the new version's arm
+/// in the parser.
+pub const MIGRATIONS: &[Migration] = &[];
+
+/// What migrating a config file changed (or would change).
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationReport {
+	/// The config file.
+	pub path: PathBuf,
+
+	/// The version the file was written for.
+	pub from: u32,
+
+	/// The version after migrating.
+	pub to: u32,
+
+	/// Each edit made to the file. Empty if it was already current.
+	pub changes: Vec<String>,
+}
+
+impl MigrationReport {
+	/// Whether the file needs rewriting.
+	pub fn changed(&self) -> bool {
+		!self.changes.is_empty()
+	}
+}
+
+/// Migrate config content belonging to `path`, returning the new content.
+///
+/// A config without a `version` key is read as version 1, so one that needs
+/// no migration step is up to date and is left alone.
+pub fn migrate_str(content: &str, path: &Path) -> Result<(String, MigrationReport)> {
+	let (migrated, report) = apply_migrations(content, path, MIGRATIONS)?;
+
+	// A migration must never produce a config the current parser rejects
+	if report.changed() {
+		parse_config_str_unvalidated(&migrated, path)?;
+	}
+
+	Ok((migrated, report))
+}
+
+/// Run the steps of `migrations` that apply to the config, then set its
+/// `version` to the one the last step produces.
+fn apply_migrations(
+	content: &str,
+	path: &Path,
+	migrations: &[Migration],
+) -> Result<(String, MigrationReport)> {
+	let from = config_version(content, path)?;
+	let mut document = content
+		.parse::<DocumentMut>()
+		.map_err(|e| migrate_error(path, e.to_string()))?;
+	let mut changes = Vec::new();
+	let mut to = from;
+
+	for migration in migrations.iter().filter(|m| m.from >= from) {
+		let edits = (migration.apply)(&mut document);
+		changes.push(format!(
+			"version {} -> {}: {}",
+			migration.from,
+			migration.from + 1,
+			migration.description
+		));
+		changes.extend(edits.into_iter().map(|edit| format!("  {}", edit)));
+		to = migration.from + 1;
+	}
+
+	let migrated = if changes.is_empty() {
+		content.to_string()
+	} else if let Some(value) = document.get("version").and_then(Item::as_value) {
+		let mut new_value = Value::from(i64::from(to));
+		*new_value.decor_mut() = value.decor().clone();
+		document["version"] = Item::Value(new_value);
+		changes.push(format!("set `version = {}`", to));
+		document.to_string()
+	} else {
+		changes.push(format!("add `version = {}`", to));
+		insert_version(&document.to_string(), to)
+	};
+
+	Ok((
+		migrated,
+		MigrationReport {
+			path: path.to_path_buf(),
+			from,
+			to,
+			changes,
+		},
+	))
+}
+
+/// Migrate a config file, writing it back unless `check` is set.
+///
+/// Configs embedded in a manifest can't be migrated.
+pub fn migrate_file(path: &Path, check: bool) -> Result<MigrationReport> {
+	if is_embedded(path) {
+		return Err(embedded_error(path));
+	}
+
+	let content = std::fs::read_to_string(path).map_err(|source| TrampError::ConfigReadError {
+		path: path.to_path_buf(),
+		source,
+	})?;
+	let (migrated, report) = migrate_str(&content, path)?;
+
+	if report.changed() && !check {
+		std::fs::write(path, migrated)
+			.map_err(|e| migrate_error(path, format!("Failed to write file: {}", e)))?;
+	}
+
+	Ok(report)
+}
+
+/// Add a `version` key after the file's leading comment block, so it sits
+/// at the top of the file instead of after the other top-level keys.
+fn insert_version(content: &str, version: u32) -> String {
+	let line = format!("version = {}\n", version);
+	let mut offset = 0;
+
+	for text in content.split_inclusive('\n') {
+		let trimmed = text.trim();
+		if !trimmed.is_empty() && !trimmed.starts_with('#') {
+			break;
+		}
+		offset += text.len();
+	}
+
+	let (head, tail) = content.split_at(offset);
+	let separator = if tail.is_empty() || tail.starts_with('\n') {
+		""
+	} else {
+		"\n"
+	};
+	let newline = if head.is_empty() || head.ends_with('\n') {
+		""
+	} else {
+		"\n"
+	};
+	format!("{}{}{}{}{}", head, newline, line, separator, tail)
+}
+
+fn migrate_error(path: &Path, message: impl Into<String>) -> TrampError {
+	TrampError::ConfigEditError {
+		path: path.to_path_buf(),
+		message: message.into(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::config::parser::CURRENT_CONFIG_VERSION;
+
+	/// A stand-in step from version 1, renaming `max-depth` to `depth`.
+	fn rename_max_depth(document: &mut DocumentMut) -> Vec<String> {
+		match document.remove("max-depth") {
+			Some(item) => {
+				document.insert("depth", item);
+				vec!["renamed `max-depth` to `depth`".to_string()]
+			}
+			None => Vec::new(),
+		}
+	}
+
+	const TEST_MIGRATIONS: &[Migration] = &[Migration {
+		from: 1,
+		description: "`max-depth` is now `depth`",
+		apply: rename_max_depth,
+	}];
+
+	#[test]
+	fn test_migration_steps_run_and_stamp_the_version() {
+		let content = "# Team config\n\nroot = true\nmax-depth = 3 # nested builds\n";
+		let (migrated, report) =
+			apply_migrations(content, Path::new("t.toml"), TEST_MIGRATIONS).unwrap();
+
+		assert_eq!(report.from, 1);
+		assert_eq!(report.to, 2);
+		assert_eq!(
+			report.changes,
+			[
+				"version 1 -> 2: `max-depth` is now `depth`",
+				"  renamed `max-depth` to `depth`",
+				"add `version = 2`",
+			]
+		);
+		assert_eq!(
+			migrated,
+			"# Team config\n\nversion = 2\n\nroot = true\ndepth = 3 # nested builds\n"
+		);
+
+		let content = "version = 1 # pinned\nmax-depth = 3\n";
+		let (migrated, report) =
+			apply_migrations(content, Path::new("t.toml"), TEST_MIGRATIONS).unwrap();
+		assert_eq!(report.changes.last().unwrap(), "set `version = 2`");
+		assert_eq!(migrated, "version = 2 # pinned\ndepth = 3\n");
+	}
+
+	#[test]
+	fn test_unversioned_current_config_is_up_to_date() {
+		let content = "# Team config\n\n[[rules]]\nbinary_pattern = \"cargo\" # build tool\nalternate_command = \"/usr/bin/true\"\n";
+		let (migrated, report) = migrate_str(content, Path::new("t.toml")).unwrap();
+
+		assert_eq!(report.from, 1);
+		assert_eq!(report.to, CURRENT_CONFIG_VERSION);
+		assert!(!report.changed());
+		assert_eq!(migrated, content);
+	}
+
+	#[test]
+	fn test_insert_version_without_header() {
+		assert_eq!(insert_version("", 1), "version = 1\n");
+		assert_eq!(
+			insert_version("root = true\n", 1),
+			"version = 1\n\nroot = true\n"
+		);
+		assert_eq!(
+			insert_version("# only a comment", 1),
+			"# only a comment\nversion = 1\n"
+		);
+	}
+
+	#[test]
+	fn test_current_config_is_unchanged() {
+		let content = "version = 1 # pinned\nroot = true\n";
+		let (migrated, report) = migrate_str(content, Path::new("t.toml")).unwrap();
+
+		assert!(!report.changed());
+		assert_eq!(migrated, content);
+	}
+
+	#[test]
+	fn test_newer_config_is_not_migrated() {
+		let err = migrate_str("version = 99\n", Path::new("t.toml")).unwrap_err();
+		assert!(matches!(err, TrampError::UnsupportedConfigVersion { .. }));
+	}
+
+	#[test]
+	fn test_migrations_are_in_version_order() {
+		for (i, migration) in MIGRATIONS.iter().enumerate() {
+			assert_eq!(migration.from, i as u32 + 1);
+		}
+		assert_eq!(MIGRATIONS.len() as u32 + 1, CURRENT_CONFIG_VERSION);
+	}
+}
@@ -3,12 +3,64 @@
 //! This module handles:
 //! - TOML config file parsing
 //! - Directory cascade discovery
+//! - Configs embedded in `Cargo.toml` and `package.json`
+//! - Rules injected through `TRAMP_RULES`
 //! - Config merging
+//! - Conditional `when` sections
+//! - Rule templates and groups
+//! - Expiring and time-boxed rules
+//! - Rules muted on this machine
+//! - Computed `vars` definitions
+//! - Structured validation diagnostics
+//! - Format versions and migration
+//! - JSON Schema export
 
 pub mod cascade;
+pub mod conditions;
+pub mod diagnostics;
+pub mod editor;
+pub mod embedded;
+pub mod env_rules;
+pub mod location;
+pub mod migrate;
+pub mod mutes;
 pub mod parser;
+pub mod presets;
+pub mod schedule;
+pub mod schema;
+pub mod templates;
 pub mod types;
 
-pub use cascade::{discover_configs, load_merged_config, merge_configs, user_config_path};
-pub use parser::{parse_config_file, parse_config_str};
-pub use types::{Config, LoadedConfig, MergedConfig, Rule, RuleWithSource};
+pub use cascade::{
+	discover_configs, discover_configs_with, load_merged_config, load_single_config, merge_configs,
+	merge_configs_at, user_config_path,
+};
+pub use conditions::{CONDITION_KEYS, apply_conditions, current_fact, evaluate_condition};
+pub use diagnostics::{CheckedConfig, Diagnostic, Severity, ValidationReport, validate_cascade};
+pub use editor::ConfigEditor;
+pub use embedded::{
+	CONFIG_FILE_NAME, ConfigFormat, EMBEDDED_SOURCES, EmbeddedSource, LOCAL_CONFIG_FILE_NAME,
+	config_candidates, find_config, is_embedded, read_config_source, split_config_path,
+};
+pub use env_rules::{
+	ENV_RULES_SOURCE, RULES_ENV_VAR, env_rules_path, env_rules_value, is_env_rules,
+};
+pub use location::{RuleLocation, SourceLocation};
+pub use migrate::{MIGRATIONS, Migration, MigrationReport, migrate_file, migrate_str};
+pub use mutes::{Mute, MutedRules, describe_mute_end, muted_rules_path};
+pub use parser::{
+	CURRENT_CONFIG_VERSION, parse_config_file, parse_config_str, parse_config_str_as_written,
+	parse_config_str_unvalidated,
+};
+pub use presets::{DEFAULT_PRESET, PRESETS, Preset, find_preset};
+pub use schedule::{
+	EXPIRY_WARNING_DAYS, ExpiryDate, TimeWindow, local_now, unwarned_expired_rules,
+};
+pub use schema::{config_schema, config_schema_json};
+pub use templates::{resolve_rule, resolve_templates};
+pub use types::{
+	CascadeEnd, ComputedVar, ConditionOutcome, ConditionalConfig, Config, ConfigDiscovery,
+	EnvRulesDecision, GroupRef, LoadedConfig, MergedConfig, Rule, RuleGroup, RuleTest,
+	RuleWithSource, SearchedLocation, SkipReason, SkippedRule, UserConfigDecision, VarWithSource,
+	rule_position,
+};
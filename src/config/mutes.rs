@@ -0,0 +1,188 @@
+//! Rules muted on this machine with `tramp disable`.
+//!
+//! Silencing a noisy rule shouldn't mean editing a shared config. `tramp disable
+//! <id>` records the rule's id in `tramp/muted.json` under the user data
+//! directory, optionally with an end time, and `tramp enable <id>` removes it.
+//! A muted rule is skipped wherever its id appears in the cascade.
+
+use crate::error::{Result, TrampError};
+use jiff::Timestamp;
+use jiff::tz::TimeZone;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Where muted rules are recorded: `tramp/muted.json` in the user data directory.
+pub fn muted_rules_path() -> Option<PathBuf> {
+	dirs::data_dir().map(|dir| dir.join("tramp").join("muted.json"))
+}
+
+/// A rule muted on this machine.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mute {
+	/// When the mute ends. `None` mutes until `tramp enable`.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub until: Option<Timestamp>,
+}
+
+impl Mute {
+	/// Whether the mute still holds at `now`.
+	pub fn is_active(&self, now: Timestamp) -> bool {
+		self.until.is_none_or(|until| now < until)
+	}
+}
+
+/// Describe when a mute ends in local time: `until 2026-10-18 16:00`, or
+/// `until `tramp enable``.
+pub fn describe_mute_end(until: Option<Timestamp>) -> String {
+	match until {
+		Some(until) => format!(
+			"until {}",
+			until
+				.to_zoned(TimeZone::system())
+				.strftime("%Y-%m-%d %H:%M")
+		),
+		None => "until `tramp enable`".to_string(),
+	}
+}
+
+/// The rules muted on this machine, by rule id.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MutedRules(BTreeMap<String, Mute>);
+
+impl MutedRules {
+	/// Read the muted rules recorded on this machine.
+	///
+	/// A missing or unreadable file mutes nothing, so a broken state file never
+	/// stops commands from running.
+	pub fn load() -> Self {
+		muted_rules_path()
+			.and_then(|path| Self::read(&path).ok())
+			.unwrap_or_default()
+	}
+
+	/// Read muted rules from `path`. A missing file mutes nothing.
+	pub fn read(path: &Path) -> Result<Self> {
+		let content = match std::fs::read_to_string(path) {
+			Ok(content) => content,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+			Err(e) => return Err(state_error(path, e)),
+		};
+		serde_json::from_str(&content).map_err(|e| state_error(path, e))
+	}
+
+	/// Write the muted rules to `path`, creating its directory if needed.
+	pub fn write(&self, path: &Path) -> Result<()> {
+		if let Some(dir) = path.parent() {
+			std::fs::create_dir_all(dir).map_err(|e| state_error(path, e))?;
+		}
+		let content = serde_json::to_string_pretty(self).map_err(|e| state_error(path, e))?;
+
+		// Write then rename, so a concurrent invocation never reads half a file
+		let temp = path.with_extension(format!("json.{}", std::process::id()));
+		std::fs::write(&temp, content + "\n").map_err(|e| state_error(&temp, e))?;
+		std::fs::rename(&temp, path).map_err(|e| {
+			let _ = std::fs::remove_file(&temp);
+			state_error(path, e)
+		})
+	}
+
+	/// Mute the rules with `id`, replacing any earlier mute.
+	pub fn mute(&mut self, id: &str, until: Option<Timestamp>) {
+		self.0.insert(id.to_string(), Mute { until });
+	}
+
+	/// Unmute the rules with `id`. Returns whether they were muted.
+	pub fn unmute(&mut self, id: &str) -> bool {
+		self.0.remove(id).is_some()
+	}
+
+	/// The mute on the rules with `id`, whether or not it has ended.
+	pub fn get(&self, id: &str) -> Option<Mute> {
+		self.0.get(id).copied()
+	}
+
+	/// The mute on the rules with `id`, if it holds at `now`.
+	pub fn active(&self, id: &str, now: Timestamp) -> Option<Mute> {
+		self.get(id).filter(|mute| mute.is_active(now))
+	}
+
+	/// Forget mutes that have ended by `now`.
+	pub fn prune(&mut self, now: Timestamp) {
+		self.0.retain(|_, mute| mute.is_active(now));
+	}
+
+	/// The mutes, in id order.
+	pub fn iter(&self) -> impl Iterator<Item = (&str, &Mute)> {
+		self.0.iter().map(|(id, mute)| (id.as_str(), mute))
+	}
+
+	/// Whether nothing is muted.
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+}
+
+fn state_error(path: &Path, error: impl std::fmt::Display) -> TrampError {
+	TrampError::MutedRulesError {
+		path: path.to_path_buf(),
+		message: error.to_string(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use jiff::civil::{DateTime, date};
+
+	/// The instant a local date and time falls on.
+	fn local(datetime: DateTime) -> Timestamp {
+		datetime.to_zoned(TimeZone::system()).unwrap().timestamp()
+	}
+
+	#[test]
+	fn test_mutes_expire_and_round_trip() {
+		let now = local(date(2026, 10, 18).at(12, 0, 0, 0));
+		let mut muted = MutedRules::default();
+		muted.mute("npm", Some(local(date(2026, 10, 18).at(14, 0, 0, 0))));
+		muted.mute("cargo", None);
+		muted.mute("old", Some(local(date(2026, 10, 17).at(9, 0, 0, 0))));
+
+		assert!(muted.active("npm", now).is_some());
+		assert!(
+			muted
+				.active("npm", local(date(2026, 10, 18).at(14, 0, 0, 0)))
+				.is_none()
+		);
+		assert_eq!(muted.active("cargo", now), Some(Mute { until: None }));
+		assert!(muted.active("old", now).is_none());
+		assert!(muted.active("make", now).is_none());
+
+		muted.prune(now);
+		assert_eq!(muted.iter().count(), 2);
+		assert!(muted.unmute("cargo"));
+		assert!(!muted.unmute("cargo"));
+
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("tramp").join("muted.json");
+		assert!(MutedRules::read(&path).unwrap().is_empty());
+		muted.write(&path).unwrap();
+		assert_eq!(MutedRules::read(&path).unwrap(), muted);
+		let files = std::fs::read_dir(path.parent().unwrap()).unwrap().count();
+		assert_eq!(files, 1, "the temporary file is renamed into place");
+
+		std::fs::write(&path, "not json").unwrap();
+		let err = MutedRules::read(&path).unwrap_err();
+		assert!(matches!(err, TrampError::MutedRulesError { .. }));
+	}
+
+	#[test]
+	fn test_describe_mute_end() {
+		assert_eq!(
+			describe_mute_end(Some(local(date(2026, 10, 18).at(16, 0, 0, 0)))),
+			"until 2026-10-18 16:00"
+		);
+		assert_eq!(describe_mute_end(None), "until `tramp enable`");
+	}
+}
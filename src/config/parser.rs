@@ -1,31 +1,422 @@
-use crate::config::types::Config;
+use crate::config::conditions::{apply_conditions, current_fact};
+use crate::config::embedded::{
+	AtKeyPath, ConfigFormat, LOCAL_CONFIG_FILE_NAME, key_path_segments, read_config_source,
+	split_config_path,
+};
+use crate::config::env_rules::{is_env_rules, read_env_rules};
+use crate::config::location::{
+	RuleLocation, SourceLocation, config_table, locate_groups_in, locate_rules_in,
+};
+use crate::config::types::{Config, GroupRef, Rule, RuleGroup};
 use crate::error::{Result, TrampError};
+use serde::de::{DeserializeOwned, DeserializeSeed};
+use serde::{Deserialize, Deserializer};
 use std::path::Path;
+use toml::Spanned;
+use toml::de::{DeTable, DeValue, ValueDeserializer};
+
+/// The newest config format version this build understands.
+pub const CURRENT_CONFIG_VERSION: u32 = 1;
+
+/// The version a config is assumed to use when it has no `version` key.
+pub const UNVERSIONED_CONFIG_VERSION: u32 = 1;
+
+/// Just enough of a config to find out which format version it uses.
+#[derive(Deserialize)]
+struct VersionProbe {
+	version: Option<u32>,
+}
 
 /// Parse a config file from the given path.
+///
+/// The path may refer to a config embedded in a manifest, such as
+/// `Cargo.toml#package.metadata.tramp`.
 pub fn parse_config_file(path: &Path) -> Result<Config> {
-	let content = std::fs::read_to_string(path).map_err(|source| TrampError::ConfigReadError {
-		path: path.to_path_buf(),
-		source,
-	})?;
-
+	let content = read_config_source(path)?;
 	parse_config_str(&content, path)
 }
 
 /// Parse a config from a string (useful for testing).
+///
+/// For an embedded config, `content` is the whole manifest and `path` carries
+/// the key path, so errors point into the manifest.
 pub fn parse_config_str(content: &str, path: &Path) -> Result<Config> {
-	let config: Config =
-		toml::from_str(content).map_err(|source| TrampError::ConfigParseError {
+	let config = parse_config_str_unvalidated(content, path)?;
+	validate_config(&config, path)?;
+	Ok(config)
+}
+
+/// Parse a config from a string without applying its `when` sections.
+///
+/// Used when editing a config, where rule positions must match the file.
+pub fn parse_config_str_as_written(content: &str, path: &Path) -> Result<Config> {
+	let mut config = read_config(content, path)?;
+	assign_groups(&mut config);
+	validate_config(&config, path)?;
+	Ok(config)
+}
+
+/// Parse a config from a string without validating its rules.
+///
+/// The `when` sections whose conditions hold are applied. Used by
+/// diagnostics, which report every invalid rule rather than the first.
+pub fn parse_config_str_unvalidated(content: &str, path: &Path) -> Result<Config> {
+	let mut config = read_config(content, path)?;
+	apply_conditions(&mut config, path, &current_fact)?;
+	assign_groups(&mut config);
+	Ok(config)
+}
+
+/// Validate a parsed config, pointing errors at the offending rule.
+fn validate_config(config: &Config, path: &Path) -> Result<()> {
+	// A personal file mustn't loosen the repository's settings, only add rules
+	if path.file_name() == Some(LOCAL_CONFIG_FILE_NAME.as_ref()) {
+		let settings = [
+			("no-external-lookup", config.no_external_lookup),
+			(
+				"root-config-lookup-disable-env-var",
+				config.root_config_lookup_disable_env_var.is_some(),
+			),
+			("max-depth", config.max_depth.is_some()),
+		];
+		if let Some((key, _)) = settings.into_iter().find(|(_, set)| *set) {
+			return Err(TrampError::NotAllowedInLocalConfig {
+				path: path.to_path_buf(),
+				key: key.to_string(),
+			});
+		}
+	}
+	for (_, index, rule) in config.all_rules() {
+		rule.validate()
+			.map_err(|e| rule.locate_error(path, index, e))?;
+	}
+	for (name, template) in &config.templates {
+		template
+			.validate()
+			.map_err(|e| TrampError::InvalidTemplate {
+				path: path.to_path_buf(),
+				name: name.clone(),
+				error: Box::new(e),
+			})?;
+	}
+	for (name, var) in &config.vars {
+		var.validate().map_err(|reason| TrampError::InvalidVar {
 			path: path.to_path_buf(),
-			source,
+			name: name.clone(),
+			reason,
 		})?;
+	}
+	Ok(())
+}
+
+/// Deserialize a config as written, with the locations of its rules and groups.
+fn read_config(content: &str, path: &Path) -> Result<Config> {
+	if is_env_rules(path) {
+		return read_env_rules(content, path);
+	}
 
-	// Validate the parsed config
-	config.validate()?;
+	let (_, key_path) = split_config_path(path);
+	let keys = key_path
+		.as_deref()
+		.map(key_path_segments)
+		.unwrap_or_default();
+
+	match ConfigFormat::of(path) {
+		ConfigFormat::Toml => read_toml_config(content, path, &keys),
+		ConfigFormat::Json => {
+			let probe: Option<VersionProbe> = deserialize_json_at(content, path, &keys)?;
+			let version = check_version(probe.and_then(|probe| probe.version), path)?;
+			match version {
+				1 => Ok(deserialize_json_at(content, path, &keys)?.unwrap_or_default()),
+				_ => unreachable!("check_version rejects unsupported versions"),
+			}
+		}
+	}
+}
+
+/// Deserialize a TOML config, parsing the document once for its version,
+/// its contents, and the locations of its rules and groups.
+fn read_toml_config(content: &str, path: &Path, keys: &[&str]) -> Result<Config> {
+	let toml_error = |mut source: toml::de::Error| {
+		source.set_input(Some(content));
+		parse_error(content, path, source)
+	};
+
+	let document = DeTable::parse(content).map_err(toml_error)?;
+	let Some(table) = config_table(document.get_ref(), keys) else {
+		return Ok(Config::default());
+	};
+
+	let version = match table.get("version") {
+		Some(value) => {
+			Some(u32::deserialize(ValueDeserializer::from(value.clone())).map_err(toml_error)?)
+		}
+		None => None,
+	};
+	let version = check_version(version, path)?;
+
+	let locations = TableLocations::of(content, table);
+	let when_locations: Vec<(String, TableLocations)> = match table.get("when").map(|v| v.get_ref())
+	{
+		Some(DeValue::Table(when)) => when
+			.iter()
+			.filter_map(|(condition, section)| match section.get_ref() {
+				DeValue::Table(section) => Some((
+					condition.get_ref().to_string(),
+					TableLocations::of(content, section),
+				)),
+				_ => None,
+			})
+			.collect(),
+		_ => Vec::new(),
+	};
+
+	let Some(table) = into_config_table(document, keys) else {
+		return Ok(Config::default());
+	};
+	let mut config =
+		deserialize_version(version, toml::Deserializer::from(table)).map_err(toml_error)?;
+
+	locations.attach(&mut config.rules, &mut config.groups);
+	for (condition, locations) in when_locations {
+		if let Some(section) = config.when.get_mut(&condition) {
+			locations.attach(&mut section.rules, &mut section.groups);
+		}
+	}
 
 	Ok(config)
 }
 
+/// The table holding the config, taken out of the parsed document: the
+/// document itself, or the table at `keys`.
+fn into_config_table<'i>(
+	document: Spanned<DeTable<'i>>,
+	keys: &[&str],
+) -> Option<Spanned<DeTable<'i>>> {
+	let mut table = document;
+	for key in keys {
+		let value = table.into_inner().remove(*key)?;
+		let span = value.span();
+		match value.into_inner() {
+			DeValue::Table(inner) => table = Spanned::new(span, inner),
+			_ => return None,
+		}
+	}
+	Some(table)
+}
+
+/// Where the rules and groups of a config table are, found before the table
+/// is consumed by deserializing it.
+struct TableLocations {
+	rules: Vec<RuleLocation>,
+	groups: Vec<(RuleLocation, Vec<RuleLocation>)>,
+}
+
+impl TableLocations {
+	fn of(content: &str, table: &DeTable<'_>) -> Self {
+		TableLocations {
+			rules: locate_rules_in(content, table),
+			groups: locate_groups_in(content, table),
+		}
+	}
+
+	/// Attach the locations to the deserialized rules and groups of the table.
+	fn attach(self, rules: &mut [Rule], groups: &mut [RuleGroup]) {
+		for (rule, location) in rules.iter_mut().zip(self.rules) {
+			rule.location = Some(location);
+		}
+		for (group, (location, rules)) in groups.iter_mut().zip(self.groups) {
+			group.location = Some(location);
+			for (rule, location) in group.rules.iter_mut().zip(rules) {
+				rule.location = Some(location);
+			}
+		}
+	}
+}
+
+/// Record on each rule in a group which group it is in.
+fn assign_groups(config: &mut Config) {
+	for (index, group) in config.groups.iter_mut().enumerate() {
+		let group_ref = GroupRef {
+			index,
+			id: group.id.clone(),
+		};
+		for rule in &mut group.rules {
+			rule.group = Some(group_ref.clone());
+		}
+	}
+}
+
+/// Read the format version of a config, rejecting versions this build doesn't know.
+pub fn config_version(content: &str, path: &Path) -> Result<u32> {
+	let (_, key_path) = split_config_path(path);
+	let keys = key_path
+		.as_deref()
+		.map(key_path_segments)
+		.unwrap_or_default();
+
+	let probe: Option<VersionProbe> = match ConfigFormat::of(path) {
+		ConfigFormat::Toml => toml::Deserializer::parse(content)
+			.and_then(|deserializer| AtKeyPath::new(&keys).deserialize(deserializer))
+			.map_err(|source| parse_error(content, path, source))?,
+		ConfigFormat::Json => deserialize_json_at(content, path, &keys)?,
+	};
+	check_version(probe.and_then(|probe| probe.version), path)
+}
+
+/// The version of a config with the given `version` key, rejecting versions
+/// this build doesn't know.
+fn check_version(version: Option<u32>, path: &Path) -> Result<u32> {
+	let version = version.unwrap_or(UNVERSIONED_CONFIG_VERSION);
+	if !(1..=CURRENT_CONFIG_VERSION).contains(&version) {
+		return Err(TrampError::UnsupportedConfigVersion {
+			path: path.to_path_buf(),
+			version,
+			supported: CURRENT_CONFIG_VERSION,
+		});
+	}
+
+	Ok(version)
+}
+
+/// Deserialize a config written for the given format version.
+///
+/// When the format changes incompatibly, older versions get their own arm
+/// here, deserializing the old layout and converting it to the current one.
+fn deserialize_version<'de, D: Deserializer<'de>>(
+	version: u32,
+	deserializer: D,
+) -> std::result::Result<Config, D::Error> {
+	match version {
+		1 => Config::deserialize(deserializer),
+		_ => unreachable!("check_version rejects unsupported versions"),
+	}
+}
+
+/// Deserialize the config part of JSON `content`: the whole document, or the
+/// value at `keys` for an embedded config. Returns `None` if that value is
+/// missing.
+fn deserialize_json_at<T: DeserializeOwned>(
+	content: &str,
+	path: &Path,
+	keys: &[&str],
+) -> Result<Option<T>> {
+	AtKeyPath::<T>::new(keys)
+		.deserialize(&mut serde_json::Deserializer::from_str(content))
+		.map_err(|source| json_parse_error(content, path, source))
+}
+
+/// Convert a TOML error into a located error, recognising unknown keys.
+pub(crate) fn parse_error(content: &str, path: &Path, source: toml::de::Error) -> TrampError {
+	let location = source
+		.span()
+		.map(|span| SourceLocation::from_span(content, span));
+
+	if let Some((key, expected)) = parse_unknown_field(source.message())
+		&& let Some(location) = location.clone()
+	{
+		return TrampError::UnknownKey {
+			path: path.to_path_buf(),
+			suggestion: suggest_key(&key, &expected),
+			key,
+			location,
+		};
+	}
+
+	TrampError::ConfigParseError {
+		path: path.to_path_buf(),
+		location: location.map(Box::new),
+		source,
+	}
+}
+
+/// Convert a JSON error into a located error, recognising unknown keys.
+pub(crate) fn json_parse_error(
+	content: &str,
+	path: &Path,
+	source: serde_json::Error,
+) -> TrampError {
+	let location = (source.line() > 0)
+		.then(|| SourceLocation::from_line_column(content, source.line(), source.column()));
+
+	// serde_json appends the position to the message
+	let message = source.to_string();
+	let message = message
+		.rsplit_once(" at line ")
+		.map_or(message.as_str(), |(message, _)| message);
+
+	if let Some((key, expected)) = parse_unknown_field(message)
+		&& let Some(location) = location.clone()
+	{
+		return TrampError::UnknownKey {
+			path: path.to_path_buf(),
+			suggestion: suggest_key(&key, &expected),
+			key,
+			location,
+		};
+	}
+
+	TrampError::ConfigJsonParseError {
+		path: path.to_path_buf(),
+		location: location.map(Box::new),
+		source,
+	}
+}
+
+/// Extract the key and the expected keys from serde's unknown field message:
+/// ``unknown field `x`, expected one of `a`, `b` ``.
+fn parse_unknown_field(message: &str) -> Option<(String, Vec<String>)> {
+	let rest = message.strip_prefix("unknown field `")?;
+	let (key, expected) = rest.split_once('`')?;
+
+	let expected = expected
+		.split('`')
+		.skip(1)
+		.step_by(2)
+		.map(str::to_string)
+		.collect();
+
+	Some((key.to_string(), expected))
+}
+
+/// Pick the known key closest to an unknown one.
+///
+/// Keys differing only in case or `-`/`_` always match; otherwise the closest
+/// key within a small edit distance is suggested.
+fn suggest_key(key: &str, expected: &[String]) -> Option<String> {
+	let normalize = |k: &str| k.to_lowercase().replace('-', "_");
+	let normalized = normalize(key);
+
+	if let Some(exact) = expected.iter().find(|e| normalize(e) == normalized) {
+		return Some(exact.clone());
+	}
+
+	let max_distance = (normalized.chars().count() / 3).max(2);
+	expected
+		.iter()
+		.map(|e| (edit_distance(&normalized, &normalize(e)), e))
+		.filter(|(distance, _)| *distance <= max_distance)
+		.min_by_key(|(distance, _)| *distance)
+		.map(|(_, e)| e.clone())
+}
+
+/// Levenshtein distance between two strings, counted in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+	let b: Vec<char> = b.chars().collect();
+	let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+	for (i, ca) in a.chars().enumerate() {
+		let mut current = vec![i + 1];
+		for (j, cb) in b.iter().enumerate() {
+			let substitution = previous[j] + usize::from(ca != *cb);
+			current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+		}
+		previous = current;
+	}
+
+	previous[b.len()]
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -121,14 +512,280 @@ command_rewrite = "s/baz/qux/"
 
 		assert!(result.is_err());
 		match result.unwrap_err() {
-			TrampError::MutuallyExclusive { option1, option2 } => {
-				assert_eq!(option1, "arg_rewrite");
-				assert_eq!(option2, "command_rewrite");
+			TrampError::InvalidRule {
+				rule_index,
+				location,
+				error,
+				..
+			} => {
+				assert_eq!(rule_index, 0);
+				assert_eq!((location.line, location.column), (5, 1));
+				match *error {
+					TrampError::MutuallyExclusive { option1, option2 } => {
+						assert_eq!(option1, "arg_rewrite");
+						assert_eq!(option2, "command_rewrite");
+					}
+					_ => panic!("Expected MutuallyExclusive error"),
+				}
 			}
-			_ => panic!("Expected MutuallyExclusive error"),
+			_ => panic!("Expected InvalidRule error"),
 		}
 	}
 
+	#[test]
+	fn test_unknown_rule_key_suggests_closest() {
+		let content = r#"
+[[rules]]
+binary_patern = ".*/cargo$"
+"#;
+		let path = PathBuf::from("test.toml");
+		let err = parse_config_str(content, &path).unwrap_err();
+
+		match err {
+			TrampError::UnknownKey {
+				ref key,
+				ref suggestion,
+				ref location,
+				..
+			} => {
+				assert_eq!(key, "binary_patern");
+				assert_eq!(suggestion.as_deref(), Some("binary_pattern"));
+				assert_eq!((location.line, location.column), (3, 1));
+			}
+			_ => panic!("Expected UnknownKey error, got {:?}", err),
+		}
+
+		let message = err.to_string();
+		assert!(message.contains("did you mean `binary_pattern`?"));
+		assert!(message.contains("test.toml:3:1"));
+		assert!(message.contains("3 | binary_patern"));
+	}
+
+	#[test]
+	fn test_unknown_key_suggests_across_case_conventions() {
+		let path = PathBuf::from("test.toml");
+
+		let err = parse_config_str("[[rules]]\npre-hook = \"x\"\n", &path).unwrap_err();
+		assert!(err.to_string().contains("did you mean `pre_hook`?"));
+
+		let err = parse_config_str("no_external_lookup = true\n", &path).unwrap_err();
+		assert!(
+			err.to_string()
+				.contains("did you mean `no-external-lookup`?")
+		);
+
+		let err = parse_config_str("completely_unrelated = 1\n", &path).unwrap_err();
+		match err {
+			TrampError::UnknownKey { suggestion, .. } => assert!(suggestion.is_none()),
+			_ => panic!("Expected UnknownKey error"),
+		}
+	}
+
+	#[test]
+	fn test_parse_error_reports_location() {
+		let content = "root = true\nrules = [\n";
+		let path = PathBuf::from("test.toml");
+		let err = parse_config_str(content, &path).unwrap_err();
+
+		match err {
+			TrampError::ConfigParseError { ref location, .. } => {
+				assert!(location.is_some());
+				assert!(
+					err.to_string()
+						.starts_with("Failed to parse config file: test.toml:")
+				);
+			}
+			_ => panic!("Expected ConfigParseError"),
+		}
+	}
+
+	#[test]
+	fn test_parsed_rules_keep_locations() {
+		let content = r#"
+[[rules]]
+binary_pattern = ".*/cargo$"
+"#;
+		let config = parse_config_str(content, Path::new("test.toml")).unwrap();
+		let location = config.rules[0].location.as_ref().unwrap();
+		assert_eq!(location.field("binary_pattern").line, 3);
+
+		let content = r#"
+[when.'env.CI']
+max-depth = 8
+
+[[when.'env.CI'.rules]]
+binary_pattern = ".*/make$"
+"#;
+		let config = parse_config_str_as_written(content, Path::new("test.toml")).unwrap();
+		let location = config.when["env.CI"].rules[0].location.as_ref().unwrap();
+		assert_eq!(location.field("binary_pattern").line, 6);
+	}
+
+	#[test]
+	fn test_parse_groups() {
+		let content = r#"
+[[groups]]
+id = "cargo"
+binary_pattern = ".*/cargo$"
+
+[[groups.rules]]
+cwd_pattern = "/crates/"
+pre_hook = "a.sh"
+
+[[groups]]
+
+[[groups.rules]]
+arg_rewrite = "s/a/b/"
+alternate_command = "pnpm"
+"#;
+		let config = parse_config_str_unvalidated(content, Path::new("test.toml")).unwrap();
+		assert_eq!(config.groups.len(), 2);
+
+		let rule = &config.groups[0].rules[0];
+		assert_eq!(rule.position(0), "group cargo, rule 1");
+		assert_eq!(rule.location.as_ref().unwrap().field("cwd_pattern").line, 7);
+		assert_eq!(config.groups[1].rules[0].position(0), "group 2, rule 1");
+
+		let err = parse_config_str(content, Path::new("test.toml")).unwrap_err();
+		assert!(
+			err.to_string().starts_with("Invalid group 2, rule 1\n"),
+			"{}",
+			err
+		);
+	}
+
+	#[test]
+	fn test_config_version() {
+		let path = PathBuf::from("test.toml");
+
+		let config = parse_config_str("version = 1\n", &path).unwrap();
+		assert_eq!(config.version, Some(1));
+		assert_eq!(config_version("", &path).unwrap(), 1);
+
+		let err = parse_config_str("version = 99\nroot = true\n", &path).unwrap_err();
+		match err {
+			TrampError::UnsupportedConfigVersion {
+				version, supported, ..
+			} => {
+				assert_eq!(version, 99);
+				assert_eq!(supported, CURRENT_CONFIG_VERSION);
+			}
+			_ => panic!("Expected UnsupportedConfigVersion error, got {:?}", err),
+		}
+		assert!(
+			parse_config_str("version = 99\n", &path)
+				.unwrap_err()
+				.to_string()
+				.contains("Upgrade tramp")
+		);
+
+		assert!(parse_config_str("version = 0\n", &path).is_err());
+
+		let err = parse_config_str("root = true\nversion = \"1\"\n", &path).unwrap_err();
+		match err {
+			TrampError::ConfigParseError { location, .. } => {
+				assert_eq!(location.unwrap().line, 2);
+			}
+			_ => panic!("Expected ConfigParseError, got {:?}", err),
+		}
+	}
+
+	#[test]
+	fn test_newer_config_reports_version_before_unknown_keys() {
+		// A newer format may add keys this build doesn't know; the version is the real problem
+		let err =
+			parse_config_str("version = 2\nshiny_new_key = 1\n", Path::new("t.toml")).unwrap_err();
+		assert!(matches!(err, TrampError::UnsupportedConfigVersion { .. }));
+	}
+
+	#[test]
+	fn test_local_config_only_adds_rules() {
+		for content in [
+			"no-external-lookup = true\n",
+			"root-config-lookup-disable-env-var = \"NO_TRAMP\"\n",
+			"max-depth = 2\n",
+		] {
+			let key = content.split(" =").next().unwrap();
+			let err = parse_config_str(content, Path::new("repo/.tramp.local.toml")).unwrap_err();
+			assert!(
+				matches!(err, TrampError::NotAllowedInLocalConfig { key: ref k, .. } if k == key),
+				"{content}"
+			);
+			assert!(err.to_string().contains(&format!("`{key}` can't be set")));
+			parse_config_str(content, Path::new("repo/.tramp.toml")).unwrap();
+		}
+	}
+
+	#[test]
+	fn test_parse_config_embedded_in_cargo_toml() {
+		let content = r#"[package]
+name = "demo"
+
+[package.metadata.docs.rs]
+all-features = true
+
+[package.metadata.tramp]
+root = true
+
+[[package.metadata.tramp.rules]]
+binary_pattern = ".*/cargo$"
+arg_rewrite = "s/^build$/build --release/"
+"#;
+		let path = PathBuf::from("/repo/Cargo.toml#package.metadata.tramp");
+		let config = parse_config_str(content, &path).unwrap();
+
+		assert!(config.root);
+		assert_eq!(config.rules.len(), 1);
+		let location = config.rules[0].location.as_ref().unwrap();
+		assert_eq!(location.field("arg_rewrite").line, 12);
+
+		let err =
+			parse_config_str(&content.replace("root = true", "rot = true"), &path).unwrap_err();
+		match err {
+			TrampError::UnknownKey { key, location, .. } => {
+				assert_eq!(key, "rot");
+				assert_eq!(location.line, 8);
+			}
+			_ => panic!("Expected UnknownKey error, got {:?}", err),
+		}
+	}
+
+	#[test]
+	fn test_parse_config_embedded_in_package_json() {
+		let content = r#"{
+  "name": "demo",
+  "tramp": {
+    "max-depth": 3,
+    "rules": [{ "binary_pattern": ".*/npm$", "alternate_command": "pnpm" }]
+  }
+}"#;
+		let path = PathBuf::from("/repo/package.json#tramp");
+		let config = parse_config_str(content, &path).unwrap();
+
+		assert_eq!(config.max_depth, Some(3));
+		assert_eq!(config.rules[0].alternate_command.as_deref(), Some("pnpm"));
+
+		let err = parse_config_str(&content.replace("max-depth", "max_depth"), &path).unwrap_err();
+		match err {
+			TrampError::UnknownKey {
+				suggestion,
+				location,
+				..
+			} => {
+				assert_eq!(suggestion.as_deref(), Some("max-depth"));
+				assert_eq!(location.line, 4);
+			}
+			_ => panic!("Expected UnknownKey error, got {:?}", err),
+		}
+	}
+
+	#[test]
+	fn test_edit_distance() {
+		assert_eq!(edit_distance("kitten", "sitting"), 3);
+		assert_eq!(edit_distance("", "abc"), 3);
+		assert_eq!(edit_distance("same", "same"), 0);
+	}
+
 	#[test]
 	fn test_parse_intercept_hook() {
 		let content = r#"
@@ -167,4 +824,23 @@ post_hook = "/path/to/post.sh"
 		);
 		assert_eq!(rule2.post_hook, Some(PathBuf::from("/path/to/post.sh")));
 	}
+
+	#[test]
+	fn test_parse_process_patterns() {
+		let content = r#"
+[[rules]]
+binary_pattern = ".*/cargo$"
+parent_process_pattern = "/code$"
+ancestor_exclude_pattern = "/tramp$"
+ancestor_depth = 4
+"#;
+		let path = PathBuf::from("test.toml");
+		let config = parse_config_str(content, &path).unwrap();
+
+		let rule = &config.rules[0];
+		assert_eq!(rule.parent_process_pattern, Some("/code$".to_string()));
+		assert!(rule.ancestor_pattern.is_none());
+		assert_eq!(rule.ancestor_exclude_pattern, Some("/tramp$".to_string()));
+		assert_eq!(rule.ancestor_depth, Some(4));
+	}
 }
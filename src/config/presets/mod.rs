@@ -0,0 +1,118 @@
+//! Built-in starting configs for `tramp init --preset`.
+//!
+//! Each preset is a commented `.tramp.toml` that ships with `[[tests]]`
+//! describing what its rules do, so `tramp test` keeps working after a user
+//! starts editing it.
+
+use crate::exec::trampoline::generate_init_template;
+
+/// A built-in config template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Preset {
+	/// Name used with `--preset`.
+	pub name: &'static str,
+
+	/// One-line summary shown in `--help`.
+	pub description: &'static str,
+
+	/// The config file contents.
+	pub content: &'static str,
+}
+
+/// Name of the preset used when none is given.
+pub const DEFAULT_PRESET: &str = "default";
+
+/// Every built-in preset.
+pub const PRESETS: &[Preset] = &[
+	Preset {
+		name: DEFAULT_PRESET,
+		description: "Commented template showing every option",
+		content: generate_init_template(),
+	},
+	Preset {
+		name: "ci-safe",
+		description: "Reproducible repo: stops the cascade and skips personal config in CI",
+		content: include_str!("ci-safe.toml"),
+	},
+	Preset {
+		name: "monorepo",
+		description: "Worktree-aware monorepo: rules that work in every clone and worktree",
+		content: include_str!("monorepo.toml"),
+	},
+	Preset {
+		name: "build-logger",
+		description: "Build instrumentation: logs every build command with its exit code",
+		content: include_str!("build-logger.toml"),
+	},
+	Preset {
+		name: "guardrails",
+		description: "Command guardrails: softens or stops risky git and terraform commands",
+		content: include_str!("guardrails.toml"),
+	},
+];
+
+/// Look up a preset by name.
+pub fn find_preset(name: &str) -> Option<&'static Preset> {
+	PRESETS.iter().find(|preset| preset.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::config::diagnostics::{Severity, check_config};
+	use crate::config::parse_config_str;
+	use crate::config::types::LoadedConfig;
+	use crate::testing::check_test;
+	use std::path::{Path, PathBuf};
+
+	#[test]
+	fn test_presets_parse_without_errors() {
+		for preset in PRESETS {
+			let path = PathBuf::from(format!("/presets/{}/.tramp.toml", preset.name));
+			let config = parse_config_str(preset.content, &path)
+				.unwrap_or_else(|e| panic!("preset {} does not parse: {}", preset.name, e));
+
+			let errors: Vec<_> = check_config(&config, &path, Path::new("/"))
+				.into_iter()
+				.filter(|d| d.severity == Severity::Error)
+				.collect();
+			assert!(
+				errors.is_empty(),
+				"preset {} has errors: {:?}",
+				preset.name,
+				errors
+			);
+		}
+	}
+
+	#[test]
+	fn test_preset_tests_pass() {
+		for preset in PRESETS.iter().filter(|p| p.name != DEFAULT_PRESET) {
+			let path = PathBuf::from(format!("/presets/{}/.tramp.toml", preset.name));
+			let configs = vec![LoadedConfig {
+				config: parse_config_str(preset.content, &path).unwrap(),
+				path,
+			}];
+
+			let tests = &configs[0].config.tests;
+			assert!(!tests.is_empty(), "preset {} has no tests", preset.name);
+
+			for test in tests {
+				let failures = check_test(test, &configs, Path::new("/presets"));
+				assert!(
+					failures.is_empty(),
+					"preset {} test {:?} failed: {:?}",
+					preset.name,
+					test.display_name(),
+					failures
+				);
+			}
+		}
+	}
+
+	#[test]
+	fn test_find_preset() {
+		assert_eq!(find_preset("ci-safe").unwrap().name, "ci-safe");
+		assert!(find_preset("missing").is_none());
+	}
+}
@@ -0,0 +1,343 @@
+//! Time limits on rules: `expires` and `active_between`.
+//!
+//! Both are read in local time. A rule stops applying at the start of its
+//! `expires` date, so temporary instrumentation can't be forgotten. An
+//! `active_between` window that ends before it starts spans midnight:
+//! `["22:00", "06:00"]` covers the night.
+
+use crate::config::types::SkippedRule;
+use jiff::civil::{Date, DateTime, Time};
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
+use serde::de::{self, Deserializer, MapAccess, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::PathBuf;
+
+/// How many days ahead `tramp config validate` warns about rules expiring.
+pub const EXPIRY_WARNING_DAYS: i32 = 14;
+
+/// The current local date and time.
+pub fn local_now() -> DateTime {
+	jiff::Zoned::now().datetime()
+}
+
+/// The date a rule expires on, written as a TOML date (`2026-12-01`) or a string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ExpiryDate(pub Date);
+
+impl ExpiryDate {
+	/// Whether the rule has expired on `today`.
+	pub fn has_passed(&self, today: Date) -> bool {
+		today >= self.0
+	}
+
+	/// Days from `today` until the expiry date (negative once it has passed).
+	pub fn days_from(&self, today: Date) -> i32 {
+		(self.0 - today).get_days()
+	}
+}
+
+impl fmt::Display for ExpiryDate {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.0)
+	}
+}
+
+impl Serialize for ExpiryDate {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(self)
+	}
+}
+
+impl<'de> Deserialize<'de> for ExpiryDate {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let text = deserializer.deserialize_any(DateTimeText)?;
+		// A date and nothing else: jiff would also accept a date with a time
+		let date = (text.len() == "2026-12-01".len())
+			.then(|| text.parse::<Date>().ok())
+			.flatten();
+		date.map(ExpiryDate).ok_or_else(|| {
+			de::Error::custom(format!("invalid date `{}`, expected e.g. 2026-12-01", text))
+		})
+	}
+}
+
+impl JsonSchema for ExpiryDate {
+	fn schema_name() -> Cow<'static, str> {
+		"ExpiryDate".into()
+	}
+
+	fn json_schema(_: &mut SchemaGenerator) -> Schema {
+		json_schema!({
+			"description": "A date such as 2026-12-01.",
+			"type": "string",
+			"format": "date"
+		})
+	}
+}
+
+/// The local times of day between which a rule applies, e.g. `["09:00", "18:00"]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeWindow {
+	pub start: Time,
+	pub end: Time,
+}
+
+impl TimeWindow {
+	/// Whether `time` falls within the window. The start is included, the end is not.
+	pub fn contains(&self, time: Time) -> bool {
+		if self.start <= self.end {
+			self.start <= time && time < self.end
+		} else {
+			time >= self.start || time < self.end
+		}
+	}
+}
+
+/// Format a time of day as `09:00`, with seconds only when they're set.
+fn format_time(time: Time) -> String {
+	if time.second() == 0 && time.subsec_nanosecond() == 0 {
+		time.strftime("%H:%M").to_string()
+	} else {
+		time.to_string()
+	}
+}
+
+impl fmt::Display for TimeWindow {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}-{}", format_time(self.start), format_time(self.end))
+	}
+}
+
+impl Serialize for TimeWindow {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		[format_time(self.start), format_time(self.end)].serialize(serializer)
+	}
+}
+
+impl<'de> Deserialize<'de> for TimeWindow {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let [start, end] = <[TimeOfDay; 2]>::deserialize(deserializer)?;
+		if start.0 == end.0 {
+			return Err(de::Error::custom(
+				"the window is empty: it starts and ends at the same time",
+			));
+		}
+		Ok(TimeWindow {
+			start: start.0,
+			end: end.0,
+		})
+	}
+}
+
+impl JsonSchema for TimeWindow {
+	fn schema_name() -> Cow<'static, str> {
+		"TimeWindow".into()
+	}
+
+	fn json_schema(_: &mut SchemaGenerator) -> Schema {
+		json_schema!({
+			"description": "Start and end times of day, such as [\"09:00\", \"18:00\"].",
+			"type": "array",
+			"items": { "type": "string", "pattern": "^\\d{2}:\\d{2}(:\\d{2})?$" },
+			"minItems": 2,
+			"maxItems": 2
+		})
+	}
+}
+
+/// A local date and time written as `2026-10-18T20:00`, or a TOML local
+/// date-time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalDateTime(pub DateTime);
+
+impl fmt::Display for LocalDateTime {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.0)
+	}
+}
+
+impl Serialize for LocalDateTime {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(self)
+	}
+}
+
+impl<'de> Deserialize<'de> for LocalDateTime {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let text = deserializer.deserialize_any(DateTimeText)?;
+		// A date alone would be read as midnight, which is rarely what was meant
+		let datetime = text
+			.contains(['T', 't', ' '])
+			.then(|| text.parse::<DateTime>().ok())
+			.flatten();
+		datetime.map(LocalDateTime).ok_or_else(|| {
+			de::Error::custom(format!(
+				"invalid date and time `{}`, expected e.g. 2026-10-18T20:00",
+				text
+			))
+		})
+	}
+}
+
+impl JsonSchema for LocalDateTime {
+	fn schema_name() -> Cow<'static, str> {
+		"LocalDateTime".into()
+	}
+
+	fn json_schema(_: &mut SchemaGenerator) -> Schema {
+		json_schema!({
+			"description": "A local date and time such as 2026-10-18T20:00.",
+			"type": "string",
+			"pattern": "^\\d{4}-\\d{2}-\\d{2}[Tt ]\\d{2}:\\d{2}(:\\d{2}(\\.\\d+)?)?$"
+		})
+	}
+}
+
+/// A time of day written as `09:00`, `09:00:30`, or a TOML local time.
+struct TimeOfDay(Time);
+
+impl<'de> Deserialize<'de> for TimeOfDay {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let text = deserializer.deserialize_any(DateTimeText)?;
+		text.parse::<Time>()
+			.map(TimeOfDay)
+			.map_err(|_| de::Error::custom(format!("invalid time `{}`, expected e.g. 09:00", text)))
+	}
+}
+
+/// Reads a string, or a TOML date or time literal, as text.
+struct DateTimeText;
+
+impl<'de> Visitor<'de> for DateTimeText {
+	type Value = String;
+
+	fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("a date or time")
+	}
+
+	fn visit_str<E: de::Error>(self, value: &str) -> Result<String, E> {
+		Ok(value.to_string())
+	}
+
+	fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<String, A::Error> {
+		// TOML hands over its date and time literals as a special map
+		let datetime =
+			toml::value::Datetime::deserialize(de::value::MapAccessDeserializer::new(map))?;
+		Ok(datetime.to_string())
+	}
+}
+
+/// Where the expired rules already warned about are recorded:
+/// `tramp/expired-warnings.json` in the user cache directory.
+pub fn expiry_warnings_path() -> Option<PathBuf> {
+	dirs::cache_dir().map(|dir| dir.join("tramp").join("expired-warnings.json"))
+}
+
+/// Pick the expired rules that haven't been warned about yet, and record them
+/// as warned.
+///
+/// Failing to read or write the record is ignored, at worst repeating a warning.
+pub fn unwarned_expired_rules(skipped: &[SkippedRule]) -> Vec<&SkippedRule> {
+	let expired: Vec<_> = skipped
+		.iter()
+		.filter_map(|skipped| skipped.expiry_key().map(|key| (skipped, key)))
+		.collect();
+	let Some(path) = expiry_warnings_path().filter(|_| !expired.is_empty()) else {
+		return Vec::new();
+	};
+
+	let mut warned: BTreeSet<String> = std::fs::read_to_string(&path)
+		.ok()
+		.and_then(|content| serde_json::from_str(&content).ok())
+		.unwrap_or_default();
+
+	let unwarned: Vec<_> = expired
+		.into_iter()
+		.filter(|(_, key)| warned.insert(key.clone()))
+		.map(|(skipped, _)| skipped)
+		.collect();
+
+	if !unwarned.is_empty()
+		&& let Ok(content) = serde_json::to_string(&warned)
+	{
+		if let Some(dir) = path.parent() {
+			let _ = std::fs::create_dir_all(dir);
+		}
+		let _ = std::fs::write(&path, content);
+	}
+	unwarned
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::config::types::Rule;
+	use jiff::civil::{date, time};
+
+	#[test]
+	fn test_parse_expires_and_active_between() {
+		let rule: Rule = toml::from_str(
+			"expires = 2026-12-01\nactive_between = [\"09:00\", \"18:00\"]\npre_hook = \"true\"\n",
+		)
+		.unwrap();
+		assert_eq!(rule.expires, Some(ExpiryDate(date(2026, 12, 1))));
+		assert_eq!(rule.active_between.unwrap().to_string(), "09:00-18:00");
+
+		let rule: Rule = serde_json::from_str(
+			r#"{"expires": "2026-12-01", "active_between": ["22:00", "06:00:30"]}"#,
+		)
+		.unwrap();
+		assert_eq!(rule.expires, Some(ExpiryDate(date(2026, 12, 1))));
+		assert_eq!(rule.active_between.unwrap().end, time(6, 0, 30, 0));
+
+		// Round trip through the serialized form
+		let json = serde_json::to_string(&rule).unwrap();
+		assert!(json.contains(r#""expires":"2026-12-01""#));
+		assert!(json.contains(r#""active_between":["22:00","06:00:30"]"#));
+	}
+
+	#[test]
+	fn test_invalid_dates_and_windows() {
+		let err = |content: &str| toml::from_str::<Rule>(content).unwrap_err().to_string();
+
+		assert!(err("expires = \"next week\"").contains("invalid date `next week`"));
+		assert!(err("expires = 2026-12-01T10:00:00").contains("invalid date"));
+		assert!(err("active_between = [\"9am\", \"5pm\"]").contains("invalid time `9am`"));
+		assert!(err("active_between = [\"09:00\"]").contains("expected an array of length 2"));
+		assert!(err("active_between = [\"09:00\", \"09:00\"]").contains("the window is empty"));
+
+		let test_err = |content: &str| {
+			toml::from_str::<crate::config::types::RuleTest>(content)
+				.unwrap_err()
+				.to_string()
+		};
+		assert!(test_err("command = []\nat = 2026-10-18").contains("invalid date and time"));
+		assert!(test_err("command = []\nat = \"tonight\"").contains("invalid date and time"));
+	}
+
+	#[test]
+	fn test_expiry_and_windows() {
+		let expires = ExpiryDate(date(2026, 12, 1));
+		assert!(!expires.has_passed(date(2026, 11, 30)));
+		assert!(expires.has_passed(date(2026, 12, 1)));
+		assert_eq!(expires.days_from(date(2026, 11, 20)), 11);
+
+		let day = TimeWindow {
+			start: time(9, 0, 0, 0),
+			end: time(18, 0, 0, 0),
+		};
+		assert!(day.contains(time(9, 0, 0, 0)));
+		assert!(!day.contains(time(18, 0, 0, 0)));
+
+		let night = TimeWindow {
+			start: time(22, 0, 0, 0),
+			end: time(6, 0, 0, 0),
+		};
+		assert!(night.contains(time(23, 30, 0, 0)));
+		assert!(night.contains(time(5, 59, 0, 0)));
+		assert!(!night.contains(time(12, 0, 0, 0)));
+	}
+}
@@ -0,0 +1,121 @@
+//! JSON Schema for `.tramp.toml`, for editor completion and validation.
+//!
+//! The schema is generated from [`Config`] and its nested types, so the doc
+//! comments on their fields become the descriptions editors show on hover.
+//! A copy is checked in at `schema/tramp.schema.json`; a test keeps it in sync.
+
+use crate::config::types::Config;
+use schemars::generate::SchemaSettings;
+use serde_json::Value;
+
+/// Generate the JSON Schema describing a `.tramp.toml` file.
+pub fn config_schema() -> Value {
+	let generator = SchemaSettings::draft07().into_generator();
+	let mut schema = generator.into_root_schema_for::<Config>().to_value();
+
+	// TOML has no null: optional fields are omitted, never set to null
+	remove_null(&mut schema);
+
+	if let Some(object) = schema.as_object_mut() {
+		object.insert("title".into(), ".tramp.toml".into());
+	}
+	schema
+}
+
+/// Drop `null` from every `type` list in the schema, and the `{"type": "null"}`
+/// branch from every `anyOf` and `oneOf`.
+fn remove_null(schema: &mut Value) {
+	match schema {
+		Value::Object(object) => {
+			if let Some(Value::Array(types)) = object.get_mut("type") {
+				types.retain(|t| t != "null");
+				if let [single] = types.as_slice() {
+					let single = single.clone();
+					object.insert("type".into(), single);
+				}
+			}
+
+			for keyword in ["anyOf", "oneOf"] {
+				let Some(Value::Array(branches)) = object.get_mut(keyword) else {
+					continue;
+				};
+				branches.retain(|branch| branch.get("type").is_none_or(|t| t != "null"));
+				if let [single] = branches.as_slice() {
+					let single = single.clone();
+					object.remove(keyword);
+					match single {
+						// Keywords next to `$ref` are ignored in draft 7, so keep it apart
+						Value::Object(branch) if branch.contains_key("$ref") => {
+							object.insert("allOf".into(), Value::Array(vec![branch.into()]));
+						}
+						Value::Object(branch) => object.extend(branch),
+						_ => {}
+					}
+				}
+			}
+
+			object.values_mut().for_each(remove_null);
+		}
+		Value::Array(items) => items.iter_mut().for_each(remove_null),
+		_ => {}
+	}
+}
+
+/// The schema as pretty-printed JSON, as written by `tramp config schema`.
+pub fn config_schema_json() -> String {
+	let mut json =
+		serde_json::to_string_pretty(&config_schema()).expect("a JSON value always serializes");
+	json.push('\n');
+	json
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_checked_in_schema_is_current() {
+		assert!(
+			config_schema_json() == include_str!("../../schema/tramp.schema.json"),
+			"schema/tramp.schema.json is out of date; regenerate it with \
+			 `cargo run -- config schema > schema/tramp.schema.json`"
+		);
+	}
+
+	#[test]
+	fn test_schema_describes_fields() {
+		let schema = config_schema();
+		let properties = &schema["properties"];
+
+		assert_eq!(schema["additionalProperties"], false);
+		assert!(properties["max-depth"]["description"].is_string());
+		assert!(properties["rules"].is_object());
+		assert!(properties.get("max_depth").is_none());
+
+		let rule = &schema["definitions"]["Rule"];
+		assert!(
+			rule["properties"]["binary_pattern"]["description"]
+				.as_str()
+				.unwrap()
+				.contains("binary path")
+		);
+		assert!(rule["properties"].get("location").is_none());
+	}
+
+	#[test]
+	fn test_optional_fields_do_not_accept_null() {
+		let schema = config_schema();
+		let properties = &schema["definitions"]["Rule"]["properties"];
+
+		for (field, definition) in [
+			("expires", "#/definitions/ExpiryDate"),
+			("active_between", "#/definitions/TimeWindow"),
+		] {
+			let property = &properties[field];
+			assert!(property.get("anyOf").is_none(), "{}: {}", field, property);
+			assert_eq!(property["allOf"][0]["$ref"], definition);
+			assert!(property["description"].is_string());
+		}
+		assert!(!config_schema_json().contains(r#""null""#));
+	}
+}
@@ -0,0 +1,259 @@
+//! Rule templates: `[templates.<name>]` tables that rules build on with `use`.
+//!
+//! A rule starts from the templates it uses, in order, and its own fields
+//! are laid on top. Templates may use other templates. Names are looked up in
+//! the rule's own config first, then in every config of the cascade in order,
+//! so a repository rule can use a template from the user config.
+
+use crate::config::types::{LoadedConfig, Rule};
+use crate::error::{Result, TrampError};
+
+/// Resolve the templates of every rule in the cascade, in place.
+///
+/// Fails on the first rule that uses an unknown template or a template cycle.
+pub fn resolve_templates(configs: &mut [LoadedConfig]) -> Result<()> {
+	let mut resolved = Vec::with_capacity(configs.len());
+	for (config_index, loaded) in configs.iter().enumerate() {
+		let rules = loaded
+			.config
+			.all_rules()
+			.map(|(_, index, rule)| {
+				resolve_rule(configs, config_index, rule)
+					.map_err(|e| rule.locate_error(&loaded.path, index, e))
+			})
+			.collect::<Result<Vec<_>>>()?;
+		resolved.push(rules);
+	}
+
+	for (loaded, rules) in configs.iter_mut().zip(resolved) {
+		for (rule, resolved) in loaded.config.all_rules_mut().zip(rules) {
+			*rule = resolved;
+		}
+	}
+	Ok(())
+}
+
+/// Resolve the templates used by a rule from `configs[config_index]`.
+///
+/// Rules without `use` are returned unchanged.
+pub fn resolve_rule(configs: &[LoadedConfig], config_index: usize, rule: &Rule) -> Result<Rule> {
+	apply_templates(configs, config_index, rule, &mut Vec::new())
+}
+
+fn apply_templates(
+	configs: &[LoadedConfig],
+	config_index: usize,
+	rule: &Rule,
+	chain: &mut Vec<String>,
+) -> Result<Rule> {
+	let mut base = Rule::default();
+
+	for name in &rule.uses {
+		if chain.contains(name) {
+			let mut cycle = chain.clone();
+			cycle.push(name.clone());
+			return Err(TrampError::TemplateCycle { chain: cycle });
+		}
+
+		let (template_config, template) = find_template(configs, config_index, name)
+			.ok_or_else(|| TrampError::UnknownTemplate { name: name.clone() })?;
+
+		chain.push(name.clone());
+		let template = apply_templates(configs, template_config, template, chain)?;
+		chain.pop();
+
+		base = overlay(base, template);
+	}
+
+	Ok(overlay(base, rule.clone()))
+}
+
+/// Find a template by name, preferring the config at `config_index`.
+///
+/// Returns the index of the config defining it along with the template.
+fn find_template<'a>(
+	configs: &'a [LoadedConfig],
+	config_index: usize,
+	name: &str,
+) -> Option<(usize, &'a Rule)> {
+	let own = configs
+		.get(config_index)
+		.and_then(|loaded| loaded.config.templates.get(name))
+		.map(|template| (config_index, template));
+
+	own.or_else(|| {
+		configs
+			.iter()
+			.enumerate()
+			.find_map(|(index, loaded)| loaded.config.templates.get(name).map(|t| (index, t)))
+	})
+}
+
+/// Lay the fields set on `top` over `base`.
+///
+/// `id`, `enabled`, and `group` describe the rule itself and are never inherited. The
+/// three rewrites count as one field, since a rule may only have one: a
+/// rewrite on `top` replaces whichever rewrite `base` has.
+fn overlay(base: Rule, top: Rule) -> Rule {
+	let top_rewrites = top.arg_rewrite.is_some()
+		|| top.command_rewrite.is_some()
+		|| top.alternate_command.is_some();
+	let (arg_rewrite, command_rewrite, alternate_command) = if top_rewrites {
+		(top.arg_rewrite, top.command_rewrite, top.alternate_command)
+	} else {
+		(
+			base.arg_rewrite,
+			base.command_rewrite,
+			base.alternate_command,
+		)
+	};
+
+	Rule {
+		id: top.id,
+		enabled: top.enabled,
+		expires: top.expires.or(base.expires),
+		active_between: top.active_between.or(base.active_between),
+		uses: top.uses,
+		binary_pattern: top.binary_pattern.or(base.binary_pattern),
+		cwd_pattern: top.cwd_pattern.or(base.cwd_pattern),
+		parent_process_pattern: top.parent_process_pattern.or(base.parent_process_pattern),
+		ancestor_pattern: top.ancestor_pattern.or(base.ancestor_pattern),
+		ancestor_exclude_pattern: top
+			.ancestor_exclude_pattern
+			.or(base.ancestor_exclude_pattern),
+		ancestor_depth: top.ancestor_depth.or(base.ancestor_depth),
+		arg_rewrite,
+		command_rewrite,
+		alternate_command,
+		pre_hook: top.pre_hook.or(base.pre_hook),
+		post_hook: top.post_hook.or(base.post_hook),
+		intercept_hook: top.intercept_hook.or(base.intercept_hook),
+		group: top.group,
+		location: top.location,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::config::parse_config_str;
+	use std::path::{Path, PathBuf};
+
+	fn load(configs: &[(&str, &str)]) -> Vec<LoadedConfig> {
+		configs
+			.iter()
+			.map(|(path, content)| LoadedConfig {
+				config: parse_config_str(content, Path::new(path)).unwrap(),
+				path: PathBuf::from(path),
+			})
+			.collect()
+	}
+
+	#[test]
+	fn test_explicit_fields_win_over_templates() {
+		let mut configs = load(&[(
+			"/repo/.tramp.toml",
+			r#"
+[templates.logged]
+pre_hook = "/hooks/log-start.sh"
+post_hook = "/hooks/log-end.sh"
+arg_rewrite = "s/^build$/build --release/"
+
+[templates.quiet]
+post_hook = "/hooks/quiet-end.sh"
+
+[[rules]]
+use = ["logged", "quiet"]
+binary_pattern = ".*/cargo$"
+alternate_command = "/usr/bin/true"
+"#,
+		)]);
+
+		resolve_templates(&mut configs).unwrap();
+		let rule = &configs[0].config.rules[0];
+
+		assert_eq!(rule.binary_pattern.as_deref(), Some(".*/cargo$"));
+		assert_eq!(rule.pre_hook, Some(PathBuf::from("/hooks/log-start.sh")));
+		assert_eq!(rule.post_hook, Some(PathBuf::from("/hooks/quiet-end.sh")));
+		// The rule's own rewrite replaces the template's
+		assert_eq!(rule.alternate_command.as_deref(), Some("/usr/bin/true"));
+		assert!(rule.arg_rewrite.is_none());
+		assert!(rule.validate().is_ok());
+	}
+
+	#[test]
+	fn test_templates_resolve_across_the_cascade() {
+		let mut configs = load(&[
+			(
+				"/repo/.tramp.toml",
+				r#"
+[templates.build]
+use = ["timed"]
+binary_pattern = ".*/(cargo|make)$"
+
+[[rules]]
+use = ["build"]
+"#,
+			),
+			(
+				"/home/user/.tramp.toml",
+				r#"
+[templates.timed]
+pre_hook = "/home/user/start-timer.sh"
+
+[templates.build]
+binary_pattern = ".*/never$"
+"#,
+			),
+		]);
+
+		resolve_templates(&mut configs).unwrap();
+		let rule = &configs[0].config.rules[0];
+
+		// The repo's own `build` wins over the user config's
+		assert_eq!(rule.binary_pattern.as_deref(), Some(".*/(cargo|make)$"));
+		assert_eq!(
+			rule.pre_hook,
+			Some(PathBuf::from("/home/user/start-timer.sh"))
+		);
+	}
+
+	#[test]
+	fn test_unknown_template_is_located() {
+		let mut configs = load(&[(
+			"/repo/.tramp.toml",
+			"[[rules]]\nbinary_pattern = \"cargo\"\nuse = [\"missing\"]\n",
+		)]);
+
+		let err = resolve_templates(&mut configs).unwrap_err();
+		match err {
+			TrampError::InvalidRule {
+				location, error, ..
+			} => {
+				assert_eq!(location.line, 3);
+				assert!(matches!(*error, TrampError::UnknownTemplate { .. }));
+			}
+			_ => panic!("Expected InvalidRule error, got {:?}", err),
+		}
+	}
+
+	#[test]
+	fn test_template_cycle_is_detected() {
+		let configs = load(&[(
+			"/repo/.tramp.toml",
+			r#"
+[templates.a]
+use = ["b"]
+
+[templates.b]
+use = ["a"]
+
+[[rules]]
+use = ["a"]
+"#,
+		)]);
+
+		let err = resolve_rule(&configs, 0, &configs[0].config.rules[0]).unwrap_err();
+		assert_eq!(err.to_string(), "Template cycle: a -> b -> a");
+	}
+}
@@ -1,10 +1,25 @@
-use serde::Deserialize;
-use std::path::PathBuf;
+use crate::config::location::{RuleLocation, SourceLocation};
+use crate::config::mutes::describe_mute_end;
+use crate::config::schedule::{ExpiryDate, LocalDateTime, TimeWindow};
+use crate::error::TrampError;
+use indexmap::IndexMap;
+use jiff::Timestamp;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
 
 /// Top-level configuration from a `.tramp.toml` file.
-#[derive(Debug, Clone, Default, Deserialize)]
-#[serde(rename_all = "kebab-case")]
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct Config {
+	/// Version of the config format the file is written for (default: 1).
+	/// Older versions are read as-is and can be upgraded with `tramp config migrate`.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	#[schemars(range(min = 1))]
+	pub version: Option<u32>,
+
 	/// If true, stop directory cascade and jump directly to ~/.tramp.toml.
 	#[serde(default)]
 	pub root: bool,
@@ -15,48 +30,364 @@ pub struct Config {
 
 	/// Environment variable name that, if truthy, skips ~/.tramp.toml lookup.
 	/// Useful for CI environments.
-	#[serde(default)]
+	#[serde(default, skip_serializing_if = "Option::is_none")]
 	pub root_config_lookup_disable_env_var: Option<String>,
 
+	/// If true, ignore rules injected through the `TRAMP_RULES` environment
+	/// variable. Only read from the user config.
+	#[serde(default)]
+	pub no_env_rules: bool,
+
+	/// Maximum nesting depth of tramp invocations before refusing to run.
+	/// Guards against trampolines that end up invoking themselves.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub max_depth: Option<usize>,
+
+	/// Named sets of rule fields that rules can build on with `use`.
+	/// Rules anywhere in the cascade can use them.
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub templates: BTreeMap<String, Rule>,
+
+	/// Variables computed from a command or a file, used in rules as `${vars.NAME}`.
+	/// Rules anywhere in the cascade can use them.
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub vars: BTreeMap<String, ComputedVar>,
+
 	/// Rules for matching and transforming commands.
 	/// First matching rule wins.
 	#[serde(default)]
 	pub rules: Vec<Rule>,
+
+	/// Rules sharing a set of matchers. Tried after `rules`, in order.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub groups: Vec<RuleGroup>,
+
+	/// Expectations about how commands are handled, checked by `tramp test`.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub tests: Vec<RuleTest>,
+
+	/// Sections that only apply where a condition holds, e.g. `[when.'os = "linux"']`
+	/// or `[when.'env.CI']`. Applied in file order when the config is loaded.
+	#[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+	pub when: IndexMap<String, ConditionalConfig>,
+
+	/// The conditions of the `when` sections and whether each applied. Set when
+	/// the sections are applied; never read from a config.
+	#[serde(skip_deserializing, skip_serializing_if = "Vec::is_empty")]
+	#[schemars(skip)]
+	pub conditions: Vec<ConditionOutcome>,
+}
+
+/// Settings and rules that apply only where a `when` condition holds.
+///
+/// Settings override the config's own. Rules and groups with the `id` of an
+/// existing one replace it in place; the rest are added after the config's own.
+/// Templates override those with the same name.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ConditionalConfig {
+	/// Overrides `root`.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub root: Option<bool>,
+
+	/// Overrides `no-external-lookup`.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub no_external_lookup: Option<bool>,
+
+	/// Overrides `root-config-lookup-disable-env-var`.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub root_config_lookup_disable_env_var: Option<String>,
+
+	/// Overrides `no-env-rules`.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub no_env_rules: Option<bool>,
+
+	/// Overrides `max-depth`.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub max_depth: Option<usize>,
+
+	/// Templates to add or override.
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub templates: BTreeMap<String, Rule>,
+
+	/// Variables to add or override.
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub vars: BTreeMap<String, ComputedVar>,
+
+	/// Rules to add, or to replace the rule with the same `id`.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub rules: Vec<Rule>,
+
+	/// Groups to add, or to replace the group with the same `id`.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub groups: Vec<RuleGroup>,
+}
+
+/// A variable whose value is computed when a rule that uses it is applied.
+///
+/// Exactly one of `cmd` and `file` must be set.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ComputedVar {
+	/// Command whose output (trimmed) is the value, program first. Runs in the
+	/// working directory of the invocation.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub cmd: Option<Vec<String>>,
+
+	/// File whose contents (trimmed) are the value, relative to the config's directory.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub file: Option<PathBuf>,
+
+	/// Seconds to wait for `cmd` before giving up (default: 2).
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	#[schemars(range(min = 0))]
+	pub timeout: Option<f64>,
+
+	/// Value to use if the command fails or times out, or the file can't be read.
+	/// Without one, the rule fails to apply.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub default: Option<String>,
+
+	/// Seconds to cache the value on disk, per working directory (default: not cached).
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub cache_ttl: Option<u64>,
+}
+
+impl ComputedVar {
+	/// Check that the variable has exactly one source, returning why not if it doesn't.
+	pub fn validate(&self) -> Result<(), String> {
+		match (&self.cmd, &self.file) {
+			(Some(_), Some(_)) => Err("set only one of `cmd` and `file`".to_string()),
+			(None, None) => Err("set `cmd` or `file`".to_string()),
+			(Some(cmd), None) if cmd.is_empty() => Err("`cmd` is empty".to_string()),
+			_ if self.timeout.is_some_and(|t| !t.is_finite() || t < 0.0) => {
+				Err("`timeout` must be a non-negative number of seconds".to_string())
+			}
+			_ => Ok(()),
+		}
+	}
+}
+
+/// Whether the section of a `when` condition was applied.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConditionOutcome {
+	/// The condition, as written.
+	pub condition: String,
+
+	/// Whether it held, so its section was applied.
+	pub applied: bool,
 }
 
 /// A rule for matching and transforming commands.
-#[derive(Debug, Clone, Default, Deserialize)]
-#[serde(rename_all = "snake_case")]
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
 pub struct Rule {
+	/// Identifier used to refer to the rule from tests and commands.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub id: Option<String>,
+
+	/// Set to false to keep the rule in the file without applying it (default: true).
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub enabled: Option<bool>,
+
+	/// Local date from which the rule no longer applies, e.g. `2026-12-01`.
+	/// `tramp rule prune` deletes expired rules.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub expires: Option<ExpiryDate>,
+
+	/// Local times of day between which the rule applies, e.g. `["09:00", "18:00"]`.
+	/// A window ending before it starts spans midnight.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub active_between: Option<TimeWindow>,
+
+	/// Templates whose fields this rule starts from, in order; later templates
+	/// override earlier ones, and fields set on the rule override both.
+	#[serde(rename = "use", default, skip_serializing_if = "Vec::is_empty")]
+	pub uses: Vec<String>,
+
 	/// Regex pattern to match the command binary path.
+	#[serde(skip_serializing_if = "Option::is_none")]
 	pub binary_pattern: Option<String>,
 
 	/// Regex pattern to match the current working directory.
+	#[serde(skip_serializing_if = "Option::is_none")]
 	pub cwd_pattern: Option<String>,
 
+	/// Regex pattern to match the parent process (executable path or command line).
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub parent_process_pattern: Option<String>,
+
+	/// Regex pattern that must match at least one ancestor process within `ancestor_depth` levels.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub ancestor_pattern: Option<String>,
+
+	/// Regex pattern that must not match any ancestor process within `ancestor_depth` levels.
+	/// Useful to skip commands launched from another tramp-wrapped tool.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub ancestor_exclude_pattern: Option<String>,
+
+	/// How many levels of the process tree the ancestor patterns inspect (default: 8).
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub ancestor_depth: Option<usize>,
+
 	/// Regex substitution for arguments (mutually exclusive with command_rewrite and alternate_command).
 	/// Format: "s/pattern/replacement/" or "s/pattern/replacement/g" for global.
+	#[serde(skip_serializing_if = "Option::is_none")]
 	pub arg_rewrite: Option<String>,
 
 	/// Regex substitution for the entire command string (mutually exclusive with arg_rewrite and alternate_command).
 	/// Format: "s/pattern/replacement/" or "s/pattern/replacement/g" for global.
+	#[serde(skip_serializing_if = "Option::is_none")]
 	pub command_rewrite: Option<String>,
 
 	/// Replacement command to execute instead (mutually exclusive with arg_rewrite and command_rewrite).
+	#[serde(skip_serializing_if = "Option::is_none")]
 	pub alternate_command: Option<String>,
 
 	/// Path to pre-hook script. Runs before the command.
+	#[serde(skip_serializing_if = "Option::is_none")]
 	pub pre_hook: Option<PathBuf>,
 
 	/// Path to post-hook script. Runs after the command.
+	#[serde(skip_serializing_if = "Option::is_none")]
 	pub post_hook: Option<PathBuf>,
 
 	/// Path to intercept hook script. Replaces command execution entirely.
+	#[serde(skip_serializing_if = "Option::is_none")]
 	pub intercept_hook: Option<PathBuf>,
+
+	/// The group the rule was declared in, if any. Set when parsing; never read
+	/// from a config.
+	#[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+	#[schemars(skip)]
+	pub group: Option<GroupRef>,
+
+	/// Where the rule was defined, if it was parsed from a file.
+	#[serde(skip)]
+	pub location: Option<RuleLocation>,
+}
+
+/// Rules that share matchers. Each rule matches only if the group's matchers
+/// and its own all match.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct RuleGroup {
+	/// Identifier used to refer to the group in output.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub id: Option<String>,
+
+	/// Regex pattern to match the command binary path.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub binary_pattern: Option<String>,
+
+	/// Regex pattern to match the current working directory.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub cwd_pattern: Option<String>,
+
+	/// Regex pattern to match the parent process (executable path or command line).
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub parent_process_pattern: Option<String>,
+
+	/// Regex pattern that must match at least one ancestor process within `ancestor_depth` levels.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub ancestor_pattern: Option<String>,
+
+	/// Regex pattern that must not match any ancestor process within `ancestor_depth` levels.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub ancestor_exclude_pattern: Option<String>,
+
+	/// How many levels of the process tree the group's ancestor patterns inspect (default: 8).
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub ancestor_depth: Option<usize>,
+
+	/// The rules of the group, tried in order.
+	#[serde(default)]
+	pub rules: Vec<Rule>,
+
+	/// Where the group was defined, if it was parsed from a file.
+	#[serde(skip)]
+	pub location: Option<RuleLocation>,
+}
+
+/// Identifies the group a rule was declared in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GroupRef {
+	/// Position of the group within its config file (0-based).
+	pub index: usize,
+
+	/// The group's `id`, if it has one.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub id: Option<String>,
+}
+
+impl fmt::Display for GroupRef {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.id {
+			Some(ref id) => write!(f, "group {}", id),
+			None => write!(f, "group {}", self.index + 1),
+		}
+	}
+}
+
+/// How a rule refers to the variable `name`: `${vars.NAME}`.
+pub fn var_reference(name: &str) -> String {
+	format!("${{vars.{}}}", name)
+}
+
+/// Describe where a rule sits in its config file: `rule 3`, or
+/// `group cargo, rule 2` for a rule declared in a group.
+pub fn rule_position(group: Option<&GroupRef>, rule_index: usize) -> String {
+	match group {
+		Some(group) => format!("{}, rule {}", group, rule_index + 1),
+		None => format!("rule {}", rule_index + 1),
+	}
+}
+
+/// An expectation about how tramp handles a command, checked by `tramp test`
+/// without running anything.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct RuleTest {
+	/// Name shown in test output. Defaults to the command line.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub name: Option<String>,
+
+	/// The command to evaluate, program first. The program is resolved on PATH
+	/// unless it contains a `/`; one that isn't installed is taken to be in `/usr/bin`.
+	pub command: Vec<String>,
+
+	/// Working directory, relative to the config file's directory (default: that directory).
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub cwd: Option<PathBuf>,
+
+	/// The local time to evaluate the command at, for rules with `expires` or
+	/// `active_between`. Without it, those limits are ignored.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub at: Option<LocalDateTime>,
+
+	/// The `id` of the rule expected to be chosen, or `"none"` for no rule.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub expect_rule: Option<String>,
+
+	/// The expected final command, program first. The program may be given as
+	/// a full path or a bare name.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub expect_argv: Option<Vec<String>>,
+
+	/// The hooks expected to run, in order (`pre`, `intercept`, `post`).
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub expect_hooks: Option<Vec<String>>,
+}
+
+impl RuleTest {
+	/// The name shown in test output.
+	pub fn display_name(&self) -> String {
+		self.name.clone().unwrap_or_else(|| self.command.join(" "))
+	}
 }
 
 /// A loaded configuration with its source path for debugging/display.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct LoadedConfig {
 	/// The parsed configuration.
 	pub config: Config,
@@ -65,27 +396,370 @@ pub struct LoadedConfig {
 	pub path: PathBuf,
 }
 
+/// The result of walking the config cascade, including why each layer was
+/// loaded or skipped.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigDiscovery {
+	/// The directory the walk started from.
+	pub start_dir: PathBuf,
+
+	/// Every `.tramp.toml` location checked during the directory walk, in order.
+	pub searched: Vec<SearchedLocation>,
+
+	/// Why the directory walk stopped.
+	pub cascade_end: CascadeEnd,
+
+	/// What happened with the user config (`~/.tramp.toml`).
+	pub user_config: UserConfigDecision,
+
+	/// What happened with rules injected through `TRAMP_RULES`.
+	pub env_rules: EnvRulesDecision,
+
+	/// The loaded configs, in cascade order (most specific first).
+	pub configs: Vec<LoadedConfig>,
+}
+
+/// A config file location checked during the directory walk.
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchedLocation {
+	/// The config file path that was checked.
+	pub path: PathBuf,
+
+	/// Whether a config file existed (and was loaded) at this path.
+	pub found: bool,
+
+	/// For a `.tramp.local.toml` that exists but wasn't loaded, the config
+	/// whose `no-external-lookup = true` ruled it out.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub ignored_by: Option<PathBuf>,
+}
+
+/// Why the directory walk of the cascade stopped.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+pub enum CascadeEnd {
+	/// The walk reached the filesystem root.
+	FilesystemRoot,
+
+	/// A config with `root = true` cut the walk; only the user config follows.
+	Root { path: PathBuf },
+
+	/// A config with `no-external-lookup = true` ended discovery entirely.
+	NoExternalLookup { path: PathBuf },
+}
+
+/// What happened with the user config during discovery.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum UserConfigDecision {
+	/// The user config was loaded.
+	Loaded { path: PathBuf },
+
+	/// No user config exists.
+	NotFound { path: PathBuf },
+
+	/// Skipped because a config's `root-config-lookup-disable-env-var` is truthy.
+	DisabledByEnvVar { env_var: String, set_by: PathBuf },
+
+	/// Skipped because a config set `no-external-lookup = true`.
+	SkippedByNoExternalLookup { set_by: PathBuf },
+}
+
+/// What happened with the rules in `TRAMP_RULES` during discovery.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum EnvRulesDecision {
+	/// The rules were loaded ahead of every config.
+	Loaded,
+
+	/// `TRAMP_RULES` is unset or empty.
+	NotSet,
+
+	/// Ignored because the user config sets `no-env-rules = true`.
+	DisabledByUserConfig { path: PathBuf },
+
+	/// Ignored because a config set `no-external-lookup = true`.
+	SkippedByNoExternalLookup { set_by: PathBuf },
+}
+
 /// Merged configuration from multiple config files in the cascade.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct MergedConfig {
 	/// All rules from all configs, in cascade order (first match wins).
 	pub rules: Vec<RuleWithSource>,
 
 	/// Whether external lookup is disabled (from any config in cascade).
 	pub no_external_lookup: bool,
+
+	/// Maximum nesting depth (from the most specific config that sets it).
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub max_depth: Option<usize>,
+
+	/// Variables by name (from the most specific config that defines each).
+	#[serde(skip_serializing_if = "BTreeMap::is_empty")]
+	pub vars: BTreeMap<String, VarWithSource>,
+
+	/// Rules left out because they expired or are outside their active hours,
+	/// in cascade order.
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub skipped: Vec<SkippedRule>,
+}
+
+/// A variable definition with its source config path.
+#[derive(Debug, Clone, Serialize)]
+pub struct VarWithSource {
+	/// The config file the variable was defined in.
+	pub source: PathBuf,
+
+	/// The definition.
+	#[serde(flatten)]
+	pub var: ComputedVar,
 }
 
 /// A rule with its source config path for debugging/display.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct RuleWithSource {
+	/// The config file this rule came from.
+	pub source: PathBuf,
+
+	/// Position of the rule within its source config (0-based), or within its
+	/// group for a rule declared in one.
+	pub source_index: usize,
+
 	/// The rule itself.
 	pub rule: Rule,
 
-	/// The config file this rule came from.
-	pub source: PathBuf,
+	/// The matchers of the rule's group, which must match as well.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub group_matchers: Option<Rule>,
+}
+
+/// A rule left out of the merged config, and why.
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedRule {
+	/// The rule, with where it came from.
+	#[serde(flatten)]
+	pub rule: RuleWithSource,
+
+	/// Why the rule doesn't apply.
+	pub skipped: SkipReason,
+}
+
+impl SkippedRule {
+	/// Identifies an expired rule, so its expiry is only warned about once.
+	pub fn expiry_key(&self) -> Option<String> {
+		match self.skipped {
+			SkipReason::Expired { expires } => Some(format!(
+				"{}\n{}\n{}",
+				self.rule.source.display(),
+				rule_position(self.rule.rule.group.as_ref(), self.rule.source_index),
+				expires
+			)),
+			_ => None,
+		}
+	}
+}
+
+/// Why a rule was left out of the merged config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+pub enum SkipReason {
+	/// The rule's `expires` date has passed.
+	Expired { expires: ExpiryDate },
+
+	/// The rule was muted on this machine with `tramp disable`.
+	Muted { until: Option<Timestamp> },
+
+	/// The current time is outside the rule's `active_between` window.
+	Inactive { active_between: TimeWindow },
+}
+
+impl fmt::Display for SkipReason {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			SkipReason::Expired { expires } => write!(f, "expired on {}", expires),
+			SkipReason::Muted { until } => write!(f, "muted {}", describe_mute_end(*until)),
+			SkipReason::Inactive { active_between } => {
+				write!(f, "outside active_between {}", active_between)
+			}
+		}
+	}
 }
 
 impl Rule {
+	/// The matcher patterns that are set, as `(field, pattern)` pairs.
+	pub fn matchers(&self) -> Vec<(&'static str, &str)> {
+		[
+			("binary_pattern", &self.binary_pattern),
+			("cwd_pattern", &self.cwd_pattern),
+			("parent_process_pattern", &self.parent_process_pattern),
+			("ancestor_pattern", &self.ancestor_pattern),
+			("ancestor_exclude_pattern", &self.ancestor_exclude_pattern),
+		]
+		.into_iter()
+		.filter_map(|(field, pattern)| pattern.as_deref().map(|p| (field, p)))
+		.collect()
+	}
+
+	/// The hooks that are set, as `(field, path)` pairs.
+	pub fn hooks(&self) -> Vec<(&'static str, &Path)> {
+		[
+			("pre_hook", &self.pre_hook),
+			("intercept_hook", &self.intercept_hook),
+			("post_hook", &self.post_hook),
+		]
+		.into_iter()
+		.filter_map(|(field, hook)| hook.as_deref().map(|h| (field, h)))
+		.collect()
+	}
+
+	/// Where the rule sits in its config file, e.g. `group cargo, rule 2`.
+	pub fn position(&self, rule_index: usize) -> String {
+		rule_position(self.group.as_ref(), rule_index)
+	}
+
+	/// The fields that may use `${vars.NAME}`, as `(field, value)` pairs for those set.
+	pub fn interpolated_fields(&self) -> Vec<(&'static str, String)> {
+		let text = [
+			("arg_rewrite", self.arg_rewrite.clone()),
+			("command_rewrite", self.command_rewrite.clone()),
+			("alternate_command", self.alternate_command.clone()),
+		];
+		let hooks = self
+			.hooks()
+			.into_iter()
+			.map(|(field, hook)| (field, Some(hook.to_string_lossy().into_owned())));
+		text.into_iter()
+			.chain(hooks)
+			.filter_map(|(field, value)| value.map(|v| (field, v)))
+			.collect()
+	}
+
+	/// Whether the rule is applied at all.
+	pub fn is_enabled(&self) -> bool {
+		self.enabled.unwrap_or(true)
+	}
+
+	/// Whether the rule has expired on `today`.
+	pub fn is_expired(&self, today: jiff::civil::Date) -> bool {
+		self.expires
+			.is_some_and(|expires| expires.has_passed(today))
+	}
+
+	/// Whether `time` of day is within the rule's `active_between` window, if it has one.
+	pub fn is_active_at(&self, time: jiff::civil::Time) -> bool {
+		self.active_between
+			.is_none_or(|window| window.contains(time))
+	}
+
+	/// Whether the rule does anything when it matches (a rewrite or a hook).
+	pub fn has_action(&self) -> bool {
+		self.arg_rewrite.is_some()
+			|| self.command_rewrite.is_some()
+			|| self.alternate_command.is_some()
+			|| !self.hooks().is_empty()
+	}
+
+	/// Attach this rule's source location to an error about it.
+	///
+	/// Errors are returned unchanged if the rule has no known location or the
+	/// error is already located.
+	pub fn locate_error(&self, path: &Path, rule_index: usize, error: TrampError) -> TrampError {
+		match self.error_location(&error) {
+			Some(location) => TrampError::InvalidRule {
+				path: path.to_path_buf(),
+				group: self.group.clone(),
+				rule_index,
+				location,
+				error: Box::new(error),
+			},
+			None => error,
+		}
+	}
+
+	/// Attach a group's source location to an error about its matchers, where
+	/// this rule holds the matchers of `group` (see [`RuleGroup::matchers`]).
+	pub fn locate_group_error(
+		&self,
+		path: &Path,
+		group: &GroupRef,
+		error: TrampError,
+	) -> TrampError {
+		match self.error_location(&error) {
+			Some(location) => TrampError::InvalidGroup {
+				path: path.to_path_buf(),
+				group: group.clone(),
+				location,
+				error: Box::new(error),
+			},
+			None => error,
+		}
+	}
+
+	/// Where in the file an error about this rule points, unless the rule has
+	/// no known location or the error is already located.
+	fn error_location(&self, error: &TrampError) -> Option<SourceLocation> {
+		let location = self.location.as_ref()?;
+		if matches!(
+			error,
+			TrampError::InvalidRule { .. } | TrampError::InvalidGroup { .. }
+		) {
+			return None;
+		}
+
+		let location = match self.field_for_error(error) {
+			Some(field) => location.field(field),
+			None => &location.start,
+		};
+		Some(location.clone())
+	}
+
+	/// The field an error about this rule refers to, if it can be told.
+	fn field_for_error(&self, error: &TrampError) -> Option<&'static str> {
+		match error {
+			TrampError::MutuallyExclusive { option2, .. } => {
+				["arg_rewrite", "command_rewrite", "alternate_command"]
+					.into_iter()
+					.find(|field| field == option2)
+			}
+			TrampError::InvalidRegex { pattern, .. } => {
+				let rewrites = [
+					("arg_rewrite", self.arg_rewrite.as_deref()),
+					("command_rewrite", self.command_rewrite.as_deref()),
+				];
+				self.matchers()
+					.into_iter()
+					.find(|(_, value)| value == pattern)
+					.or_else(|| {
+						rewrites.into_iter().find_map(|(field, value)| {
+							value
+								.filter(|value| value.contains(pattern.as_str()))
+								.map(|value| (field, value))
+						})
+					})
+					.map(|(field, _)| field)
+			}
+			TrampError::UnknownTemplate { .. } | TrampError::TemplateCycle { .. } => Some("use"),
+			TrampError::UnknownVar { name } | TrampError::VarFailed { name, .. } => {
+				let reference = var_reference(name);
+				self.interpolated_fields()
+					.into_iter()
+					.find(|(_, value)| value.contains(&reference))
+					.map(|(field, _)| field)
+			}
+			TrampError::CommandNotFound { .. } => {
+				if self.alternate_command.is_some() {
+					Some("alternate_command")
+				} else if self.command_rewrite.is_some() {
+					Some("command_rewrite")
+				} else {
+					None
+				}
+			}
+			_ => None,
+		}
+	}
+
 	/// Validate that mutually exclusive fields are not both set.
 	pub fn validate(&self) -> Result<(), crate::error::TrampError> {
 		let rewrite_fields = [
@@ -111,10 +785,54 @@ impl Rule {
 	}
 }
 
+impl RuleGroup {
+	/// The group's matchers as a rule with no action, located at the group.
+	pub fn matchers(&self) -> Rule {
+		Rule {
+			binary_pattern: self.binary_pattern.clone(),
+			cwd_pattern: self.cwd_pattern.clone(),
+			parent_process_pattern: self.parent_process_pattern.clone(),
+			ancestor_pattern: self.ancestor_pattern.clone(),
+			ancestor_exclude_pattern: self.ancestor_exclude_pattern.clone(),
+			ancestor_depth: self.ancestor_depth,
+			location: self.location.clone(),
+			..Default::default()
+		}
+	}
+}
+
 impl Config {
+	/// Every rule with the group it was declared in and its position there,
+	/// in the order rules are tried: top-level rules first, then the rules of
+	/// each group in turn.
+	pub fn all_rules(&self) -> impl Iterator<Item = (Option<&RuleGroup>, usize, &Rule)> {
+		let top_level = self
+			.rules
+			.iter()
+			.enumerate()
+			.map(|(index, rule)| (None, index, rule));
+		let grouped = self.groups.iter().flat_map(|group| {
+			group
+				.rules
+				.iter()
+				.enumerate()
+				.map(move |(index, rule)| (Some(group), index, rule))
+		});
+		top_level.chain(grouped)
+	}
+
+	/// Every rule, mutably, in the same order as [`Config::all_rules`].
+	pub fn all_rules_mut(&mut self) -> impl Iterator<Item = &mut Rule> {
+		self.rules.iter_mut().chain(
+			self.groups
+				.iter_mut()
+				.flat_map(|group| group.rules.iter_mut()),
+		)
+	}
+
 	/// Validate all rules in this config.
 	pub fn validate(&self) -> Result<(), crate::error::TrampError> {
-		for rule in &self.rules {
+		for (_, _, rule) in self.all_rules() {
 			rule.validate()?;
 		}
 		Ok(())
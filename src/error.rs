@@ -1,4 +1,6 @@
-use std::path::PathBuf;
+use crate::config::location::SourceLocation;
+use crate::config::types::{GroupRef, rule_position};
+use std::path::{Path, PathBuf};
 
 /// Library-level structured errors for tramp.
 ///
@@ -16,13 +18,56 @@ pub enum TrampError {
 		source: std::io::Error,
 	},
 
-	#[error("Failed to parse config file: {path}")]
+	#[error("Failed to parse config file: {}", display_position(path, .location.as_deref()))]
 	ConfigParseError {
 		path: PathBuf,
+		location: Option<Box<SourceLocation>>,
 		#[source]
 		source: toml::de::Error,
 	},
 
+	#[error("Failed to parse config in {}", display_position(path, .location.as_deref()))]
+	ConfigJsonParseError {
+		path: PathBuf,
+		location: Option<Box<SourceLocation>>,
+		#[source]
+		source: serde_json::Error,
+	},
+
+	#[error(
+		"{}\n{}",
+		unknown_key_message(key, .suggestion.as_deref()),
+		display_snippet(path, location)
+	)]
+	UnknownKey {
+		path: PathBuf,
+		key: String,
+		suggestion: Option<String>,
+		location: SourceLocation,
+	},
+
+	#[error("{}", unsupported_version_message(path, *version, *supported))]
+	UnsupportedConfigVersion {
+		path: PathBuf,
+		version: u32,
+		supported: u32,
+	},
+
+	#[error("Failed to edit config file {path}: {message}")]
+	ConfigEditError { path: PathBuf, message: String },
+
+	#[error("Invalid ${var}: {reason}")]
+	InvalidEnvVar { var: String, reason: String },
+
+	#[error("Shim {path}: {message}")]
+	ShimError { path: PathBuf, message: String },
+
+	#[error("Failed to update muted rules in {path}: {message}")]
+	MutedRulesError { path: PathBuf, message: String },
+
+	#[error("No rule with id or number `{selector}` in {path}")]
+	RuleNotFound { path: PathBuf, selector: String },
+
 	#[error("Invalid regex pattern in rule: {pattern}")]
 	InvalidRegex {
 		pattern: String,
@@ -30,9 +75,69 @@ pub enum TrampError {
 		source: regex::Error,
 	},
 
+	#[error("Unknown template `{name}`: no config in the cascade defines it")]
+	UnknownTemplate { name: String },
+
+	#[error("Template cycle: {}", chain.join(" -> "))]
+	TemplateCycle { chain: Vec<String> },
+
+	#[error("Invalid condition `{condition}` in {path}: {reason}")]
+	InvalidCondition {
+		path: PathBuf,
+		condition: String,
+		reason: String,
+	},
+
+	#[error("Unknown variable `{name}`: no config in the cascade defines it")]
+	UnknownVar { name: String },
+
+	#[error("Failed to compute variable `{name}`: {reason}")]
+	VarFailed { name: String, reason: String },
+
+	#[error("Invalid variable `{name}` in {path}: {reason}")]
+	InvalidVar {
+		path: PathBuf,
+		name: String,
+		reason: String,
+	},
+
+	#[error("Invalid template `{name}` in {path}")]
+	InvalidTemplate {
+		path: PathBuf,
+		name: String,
+		#[source]
+		error: Box<TrampError>,
+	},
+
+	#[error("Invalid {group}\n{}", display_snippet(path, location))]
+	InvalidGroup {
+		path: PathBuf,
+		group: GroupRef,
+		location: SourceLocation,
+		#[source]
+		error: Box<TrampError>,
+	},
+
+	#[error(
+		"`{key}` can't be set in {path}: personal overrides only add rules, so set it in the repository config"
+	)]
+	NotAllowedInLocalConfig { path: PathBuf, key: String },
+
 	#[error("Mutually exclusive options: {option1} and {option2}")]
 	MutuallyExclusive { option1: String, option2: String },
 
+	#[error("Invalid {}\n{}", rule_position(group.as_ref(), *rule_index), display_snippet(path, location))]
+	InvalidRule {
+		path: PathBuf,
+		/// The group the rule was declared in, if any.
+		group: Option<GroupRef>,
+		/// Position of the rule within its config file, or its group (0-based).
+		rule_index: usize,
+		location: SourceLocation,
+		#[source]
+		error: Box<TrampError>,
+	},
+
 	#[error("Hook execution failed: {hook_path}")]
 	HookFailed {
 		hook_path: PathBuf,
@@ -53,9 +158,64 @@ pub enum TrampError {
 	#[error("Command not found: {command}")]
 	CommandNotFound { command: String },
 
+	#[error(
+		"Recursion limit reached: tramp is nested {depth} levels deep (max-depth = {max_depth}). \
+		 Invocation chain: {chain}"
+	)]
+	RecursionLimit {
+		depth: usize,
+		max_depth: usize,
+		chain: String,
+	},
+
 	#[error("Failed to resolve home directory")]
 	HomeDirectoryNotFound,
 }
 
+/// Describe an unknown config key, suggesting the closest known key.
+pub(crate) fn unknown_key_message(key: &str, suggestion: Option<&str>) -> String {
+	match suggestion {
+		Some(suggestion) => format!("Unknown key `{}`; did you mean `{}`?", key, suggestion),
+		None => format!("Unknown key `{}`", key),
+	}
+}
+
+/// Describe a config version this build can't read.
+fn unsupported_version_message(path: &Path, version: u32, supported: u32) -> String {
+	if version > supported {
+		format!(
+			"{} uses config version {}, but this tramp only supports versions up to {}. \
+			 Upgrade tramp to use this config.",
+			path.display(),
+			version,
+			supported
+		)
+	} else {
+		format!(
+			"{} has invalid config version {}: versions start at 1",
+			path.display(),
+			version
+		)
+	}
+}
+
+/// Format `path:line:column`, or just the path if the location is unknown.
+fn display_position(path: &Path, location: Option<&SourceLocation>) -> String {
+	match location {
+		Some(location) => format!("{}:{}:{}", path.display(), location.line, location.column),
+		None => path.display().to_string(),
+	}
+}
+
+/// Format a `--> path:line:column` pointer followed by a snippet of the line.
+fn display_snippet(path: &Path, location: &SourceLocation) -> String {
+	format!(
+		"{}--> {}\n{}",
+		" ".repeat(location.line.to_string().len()),
+		display_position(path, Some(location)),
+		location.snippet()
+	)
+}
+
 /// Result type alias using TrampError.
 pub type Result<T> = std::result::Result<T, TrampError>;
@@ -0,0 +1,129 @@
+//! Per-invocation escape hatches, read from the environment.
+//!
+//! - `TRAMP_BYPASS=1` (or `tramp --bypass`) runs the real command without
+//!   loading any config.
+//! - `TRAMP_SKIP_RULES=id1,id2` ignores the rules with those ids, so the next
+//!   matching rule applies instead.
+//! - `TRAMP_SKIP_HOOKS=post` (or `pre`, `intercept`, `all`, comma-separated)
+//!   leaves out those hooks of the matched rule.
+
+use crate::config::Rule;
+use crate::config::cascade::is_env_truthy;
+use crate::error::{Result, TrampError};
+use crate::hooks::HookType;
+
+/// Environment variable that makes tramp run commands without any rules.
+pub const TRAMP_BYPASS_ENV: &str = "TRAMP_BYPASS";
+
+/// Environment variable listing rule ids to ignore.
+pub const TRAMP_SKIP_RULES_ENV: &str = "TRAMP_SKIP_RULES";
+
+/// Environment variable listing hook types to leave out.
+pub const TRAMP_SKIP_HOOKS_ENV: &str = "TRAMP_SKIP_HOOKS";
+
+/// Whether `TRAMP_BYPASS` asks to run commands without any rules.
+pub fn bypass_requested() -> bool {
+	is_env_truthy(TRAMP_BYPASS_ENV)
+}
+
+/// The rules and hooks to leave out of this invocation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Skips {
+	/// Ids of the rules to ignore.
+	pub rules: Vec<String>,
+
+	/// Hook types to leave out.
+	pub hooks: Vec<HookType>,
+}
+
+impl Skips {
+	/// Read `TRAMP_SKIP_RULES` and `TRAMP_SKIP_HOOKS`.
+	pub fn from_env() -> Result<Self> {
+		Self::parse(
+			std::env::var(TRAMP_SKIP_RULES_ENV).ok().as_deref(),
+			std::env::var(TRAMP_SKIP_HOOKS_ENV).ok().as_deref(),
+		)
+	}
+
+	/// Parse comma-separated rule ids and hook types. Blank entries are ignored.
+	pub fn parse(rules: Option<&str>, hooks: Option<&str>) -> Result<Self> {
+		let rules = split_list(rules).map(str::to_string).collect();
+
+		let mut skipped_hooks = Vec::new();
+		for name in split_list(hooks) {
+			let types: &[HookType] = match name {
+				"pre" => &[HookType::Pre],
+				"post" => &[HookType::Post],
+				"intercept" => &[HookType::Intercept],
+				"all" => &[HookType::Pre, HookType::Intercept, HookType::Post],
+				_ => {
+					return Err(TrampError::InvalidEnvVar {
+						var: TRAMP_SKIP_HOOKS_ENV.to_string(),
+						reason: format!(
+							"unknown hook type `{}`, expected pre, post, intercept, or all",
+							name
+						),
+					});
+				}
+			};
+			for &hook_type in types {
+				if !skipped_hooks.contains(&hook_type) {
+					skipped_hooks.push(hook_type);
+				}
+			}
+		}
+
+		Ok(Skips {
+			rules,
+			hooks: skipped_hooks,
+		})
+	}
+
+	/// Whether the rule is one of those to ignore.
+	pub fn skips_rule(&self, rule: &Rule) -> bool {
+		rule.id.as_ref().is_some_and(|id| self.rules.contains(id))
+	}
+
+	/// Whether hooks of this type are left out.
+	pub fn skips_hook(&self, hook_type: HookType) -> bool {
+		self.hooks.contains(&hook_type)
+	}
+}
+
+fn split_list(list: Option<&str>) -> impl Iterator<Item = &str> {
+	list.unwrap_or_default()
+		.split(',')
+		.map(str::trim)
+		.filter(|entry| !entry.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_parse_skips() {
+		let skips = Skips::parse(Some("npm, cargo,,"), Some("post")).unwrap();
+		assert_eq!(skips.rules, ["npm", "cargo"]);
+		assert_eq!(skips.hooks, [HookType::Post]);
+		assert!(skips.skips_hook(HookType::Post));
+		assert!(!skips.skips_hook(HookType::Pre));
+
+		let rule = Rule {
+			id: Some("npm".to_string()),
+			..Default::default()
+		};
+		assert!(skips.skips_rule(&rule));
+		assert!(!skips.skips_rule(&Rule::default()));
+
+		let skips = Skips::parse(None, Some("pre,all")).unwrap();
+		assert_eq!(
+			skips.hooks,
+			[HookType::Pre, HookType::Intercept, HookType::Post]
+		);
+		assert_eq!(Skips::parse(None, None).unwrap(), Skips::default());
+
+		let err = Skips::parse(None, Some("post,during")).unwrap_err();
+		assert!(err.to_string().contains("unknown hook type `during`"));
+	}
+}
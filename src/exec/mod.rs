@@ -3,18 +3,41 @@
 //! This module handles:
 //! - Executing wrapped commands with proper stdio handling
 //! - Exit code propagation
+//! - Recursion guarding for nested tramp invocations
+//! - Bypassing rules and hooks for a single invocation
 //! - Trampoline script generation
+//! - Running as a multi-call binary through symlinks named after tools
+//! - The managed shim directory
 
+pub mod bypass;
+pub mod multicall;
+pub mod shims;
 pub mod trampoline;
 
 use crate::error::{Result, TrampError};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::{Command, ExitStatus, Stdio};
 
+/// Environment variable set to "1" in every process spawned by tramp.
+pub const TRAMP_ACTIVE_ENV: &str = "TRAMP_ACTIVE";
+
+/// Environment variable holding how many tramp invocations deep a process is.
+pub const TRAMP_DEPTH_ENV: &str = "TRAMP_DEPTH";
+
+/// Environment variable holding the binaries tramp has been asked to run so far.
+pub const TRAMP_CHAIN_ENV: &str = "TRAMP_CHAIN";
+
+/// Environment variable listing extra shim directories that PATH lookups skip.
+pub const TRAMP_SHIM_DIRS_ENV: &str = "TRAMP_SHIM_DIRS";
+
+/// Default maximum nesting depth before tramp refuses to run a command.
+pub const DEFAULT_MAX_DEPTH: usize = 8;
+
 /// Execute a command with proper stdio handling.
 ///
 /// This function:
 /// - Passes stdin, stdout, stderr through to the child process
+/// - Marks the child as running under tramp (see [`nested_env`])
 /// - Returns the exit status of the child process
 pub fn execute_command(binary: &Path, args: &[String], cwd: &Path) -> Result<ExitStatus> {
 	let mut cmd = Command::new(binary);
@@ -22,7 +45,8 @@ pub fn execute_command(binary: &Path, args: &[String], cwd: &Path) -> Result<Exi
 		.current_dir(cwd)
 		.stdin(Stdio::inherit())
 		.stdout(Stdio::inherit())
-		.stderr(Stdio::inherit());
+		.stderr(Stdio::inherit())
+		.envs(nested_env(binary));
 
 	let status = cmd.status().map_err(|source| {
 		if source.kind() == std::io::ErrorKind::NotFound {
@@ -40,11 +64,92 @@ pub fn execute_command(binary: &Path, args: &[String], cwd: &Path) -> Result<Exi
 	Ok(status)
 }
 
+/// The exit code to pass on for a finished command.
+///
+/// A command killed by a signal gets `128 + signal`, as shells report it.
+pub fn exit_code(status: &ExitStatus) -> u8 {
+	#[cfg(unix)]
+	{
+		use std::os::unix::process::ExitStatusExt;
+		if let Some(signal) = status.signal() {
+			return (128 + signal) as u8;
+		}
+	}
+	status.code().unwrap_or(1) as u8
+}
+
+/// The current tramp nesting depth, read from the environment.
+///
+/// A process not started by tramp has depth 0.
+pub fn current_depth() -> usize {
+	std::env::var(TRAMP_DEPTH_ENV)
+		.ok()
+		.and_then(|value| value.parse().ok())
+		.unwrap_or(0)
+}
+
+/// The binaries run by enclosing tramp invocations, outermost first.
+pub fn current_chain() -> Vec<PathBuf> {
+	std::env::var_os(TRAMP_CHAIN_ENV)
+		.map(|value| std::env::split_paths(&value).collect())
+		.unwrap_or_default()
+}
+
+/// Environment variables marking a child of tramp, so nested invocations can
+/// detect recursion.
+pub fn nested_env(binary: &Path) -> Vec<(String, String)> {
+	let mut chain = current_chain();
+	chain.push(binary.to_path_buf());
+
+	let mut env = vec![
+		(TRAMP_ACTIVE_ENV.to_string(), "1".to_string()),
+		(
+			TRAMP_DEPTH_ENV.to_string(),
+			(current_depth() + 1).to_string(),
+		),
+	];
+
+	// Paths containing the separator can't be represented; skip the chain then
+	if let Ok(joined) = std::env::join_paths(&chain) {
+		env.push((
+			TRAMP_CHAIN_ENV.to_string(),
+			joined.to_string_lossy().to_string(),
+		));
+	}
+
+	env
+}
+
+/// Refuse to run `binary` if tramp is already nested `max_depth` levels deep.
+///
+/// This usually means a trampoline ends up invoking itself, e.g. a `cargo`
+/// shim that resolves `cargo` back to the shim.
+pub fn check_recursion_depth(binary: &Path, max_depth: usize) -> Result<()> {
+	let depth = current_depth();
+	if depth < max_depth {
+		return Ok(());
+	}
+
+	let mut chain = current_chain();
+	chain.push(binary.to_path_buf());
+
+	Err(TrampError::RecursionLimit {
+		depth,
+		max_depth,
+		chain: chain
+			.iter()
+			.map(|p| p.display().to_string())
+			.collect::<Vec<_>>()
+			.join(" -> "),
+	})
+}
+
 /// Resolve a command name to its full path.
 ///
 /// If the command is already an absolute path, returns it as-is.
-/// Otherwise, searches PATH for the command.
-pub fn resolve_command(command: &str) -> Option<std::path::PathBuf> {
+/// Otherwise, searches PATH for an executable with that name, skipping
+/// tramp's own shims so a trampoline never resolves back to itself.
+pub fn resolve_command(command: &str) -> Option<PathBuf> {
 	let path = Path::new(command);
 
 	// If it's already an absolute path, return it if it exists
@@ -58,9 +163,14 @@ pub fn resolve_command(command: &str) -> Option<std::path::PathBuf> {
 
 	// Search PATH
 	if let Ok(path_var) = std::env::var("PATH") {
+		let shim_dirs = shim_dirs();
 		for dir in std::env::split_paths(&path_var) {
+			if shim_dirs.iter().any(|shim_dir| same_dir(shim_dir, &dir)) {
+				continue;
+			}
+
 			let full_path = dir.join(command);
-			if full_path.exists() {
+			if is_executable(&full_path) && !is_tramp_shim(&full_path) {
 				return Some(full_path);
 			}
 		}
@@ -69,6 +179,95 @@ pub fn resolve_command(command: &str) -> Option<std::path::PathBuf> {
 	None
 }
 
+/// Directories known to contain tramp shims, which PATH lookups skip: the
+/// managed shim directory and those in `TRAMP_SHIM_DIRS`.
+pub fn shim_dirs() -> Vec<PathBuf> {
+	let extra: Vec<PathBuf> = std::env::var_os(TRAMP_SHIM_DIRS_ENV)
+		.map(|value| {
+			std::env::split_paths(&value)
+				.filter(|p| !p.as_os_str().is_empty())
+				.collect()
+		})
+		.unwrap_or_default();
+	shims::managed_shim_dir().into_iter().chain(extra).collect()
+}
+
+/// Compare two directories, following symlinks where possible.
+pub(crate) fn same_dir(a: &Path, b: &Path) -> bool {
+	match (a.canonicalize(), b.canonicalize()) {
+		(Ok(a), Ok(b)) => a == b,
+		_ => a == b,
+	}
+}
+
+/// Check whether a path is a regular file the current user can execute.
+pub fn is_executable(path: &Path) -> bool {
+	let Ok(metadata) = path.metadata() else {
+		return false;
+	};
+
+	if !metadata.is_file() {
+		return false;
+	}
+
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::PermissionsExt;
+		metadata.permissions().mode() & 0o111 != 0
+	}
+
+	#[cfg(not(unix))]
+	{
+		true
+	}
+}
+
+/// Check whether a file is a tramp shim: a trampoline script generated by
+/// tramp, or a link to the tramp binary itself.
+pub fn is_tramp_shim(path: &Path) -> bool {
+	use std::io::Read;
+
+	if is_tramp_binary(path) {
+		return true;
+	}
+
+	let Ok(file) = std::fs::File::open(path) else {
+		return false;
+	};
+
+	// The marker is on the second line, so the first few hundred bytes suffice
+	let mut head = Vec::with_capacity(256);
+	if file.take(256).read_to_end(&mut head).is_err() {
+		return false;
+	}
+
+	String::from_utf8_lossy(&head).contains(trampoline::TRAMPOLINE_MARKER)
+}
+
+/// Check whether a path is the running tramp binary, through a symlink or a hard link.
+pub fn is_tramp_binary(path: &Path) -> bool {
+	let Ok(exe) = std::env::current_exe() else {
+		return false;
+	};
+
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::MetadataExt;
+		match (path.metadata(), exe.metadata()) {
+			(Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+			_ => false,
+		}
+	}
+
+	#[cfg(not(unix))]
+	{
+		match (path.canonicalize(), exe.canonicalize()) {
+			(Ok(a), Ok(b)) => a == b,
+			_ => false,
+		}
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -99,4 +298,64 @@ mod tests {
 			assert!(result.is_some());
 		}
 	}
+
+	#[cfg(unix)]
+	#[test]
+	fn test_exit_code() {
+		let run = |script: &str| {
+			Command::new("/bin/sh")
+				.args(["-c", script])
+				.status()
+				.unwrap()
+		};
+		assert_eq!(exit_code(&run("exit 0")), 0);
+		assert_eq!(exit_code(&run("exit 42")), 42);
+		// SIGTERM is 15
+		assert_eq!(exit_code(&run("kill -TERM $$")), 143);
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn test_is_executable() {
+		use std::os::unix::fs::PermissionsExt;
+
+		let temp_dir = tempfile::tempdir().unwrap();
+		let file = temp_dir.path().join("tool");
+		std::fs::write(&file, "#!/bin/sh\n").unwrap();
+
+		std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o644)).unwrap();
+		assert!(!is_executable(&file));
+
+		std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o755)).unwrap();
+		assert!(is_executable(&file));
+
+		// Directories are never executables
+		assert!(!is_executable(temp_dir.path()));
+	}
+
+	#[test]
+	fn test_is_tramp_shim() {
+		let temp_dir = tempfile::tempdir().unwrap();
+
+		let shim = temp_dir.path().join("cargo");
+		std::fs::write(
+			&shim,
+			trampoline::generate_trampoline_script(Path::new("cargo"), None),
+		)
+		.unwrap();
+		assert!(is_tramp_shim(&shim));
+
+		let other = temp_dir.path().join("other");
+		std::fs::write(&other, "#!/bin/sh\nexec cargo \"$@\"\n").unwrap();
+		assert!(!is_tramp_shim(&other));
+
+		// A link to the running binary is a multi-call shim
+		#[cfg(unix)]
+		{
+			let link = temp_dir.path().join("npm");
+			std::os::unix::fs::symlink(std::env::current_exe().unwrap(), &link).unwrap();
+			assert!(is_tramp_shim(&link));
+			assert!(!is_tramp_binary(&other));
+		}
+	}
 }
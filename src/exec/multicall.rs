@@ -0,0 +1,51 @@
+//! Running tramp as a multi-call binary, busybox-style.
+//!
+//! A symlink named `cargo` that points at the `tramp` binary acts as a shim
+//! without the shell hop of a trampoline script: tramp sees `cargo` as its
+//! program name, finds the real `cargo` further down `PATH` (links to tramp
+//! itself are skipped, see [`is_tramp_shim`](super::is_tramp_shim)), and runs
+//! it through the rules.
+
+use std::env::consts::EXE_SUFFIX;
+use std::ffi::OsStr;
+use std::path::Path;
+
+/// The tool tramp was invoked as, given its program name (`argv[0]`).
+///
+/// Returns `None` when tramp runs under its own name, i.e. the name of the
+/// binary itself (usually `tramp`).
+pub fn invoked_as(argv0: &OsStr) -> Option<String> {
+	let exe = std::env::current_exe().ok();
+	let exe_name = exe.as_deref().and_then(program_name);
+	let name = program_name(Path::new(argv0))?;
+	(name != "tramp" && Some(name) != exe_name).then(|| name.to_string())
+}
+
+/// The file name of a program, without the platform's executable suffix.
+fn program_name(path: &Path) -> Option<&str> {
+	let name = path.file_name()?.to_str()?;
+	Some(name.strip_suffix(EXE_SUFFIX).unwrap_or(name))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_invoked_as() {
+		let tool = |argv0: &str| invoked_as(OsStr::new(argv0));
+
+		assert_eq!(tool("cargo").as_deref(), Some("cargo"));
+		assert_eq!(
+			tool("/home/me/.local/share/tramp/shims/python3.12").as_deref(),
+			Some("python3.12")
+		);
+		assert_eq!(tool("tramp"), None);
+		assert_eq!(tool("/usr/local/bin/tramp"), None);
+		assert_eq!(tool(""), None);
+
+		// Under its own name, whatever the binary is called (here, the test binary)
+		let exe = std::env::current_exe().unwrap();
+		assert_eq!(invoked_as(exe.as_os_str()), None);
+	}
+}
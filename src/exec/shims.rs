@@ -0,0 +1,403 @@
+//! The managed shim directory, `tramp/shims` in the user data directory.
+//!
+//! Each shim is a symlink to the tramp binary named after the tool it wraps
+//! (see [`multicall`](super::multicall)). With the directory early on `PATH`,
+//! running `cargo` runs tramp, which finds the real `cargo` further down.
+//! PATH lookups always skip this directory, so a shim never wraps itself.
+
+use crate::error::{Result, TrampError};
+use crate::exec::{is_executable, is_tramp_binary, is_tramp_shim, resolve_command, same_dir};
+use crate::rules::CompiledRule;
+use regex::Regex;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// The managed shim directory: `tramp/shims` in the user data directory
+/// (e.g. `~/.local/share/tramp/shims`).
+pub fn managed_shim_dir() -> Option<PathBuf> {
+	dirs::data_dir().map(|dir| dir.join("tramp").join("shims"))
+}
+
+/// The entries of PATH, in order.
+pub fn path_dirs() -> Vec<PathBuf> {
+	std::env::var_os("PATH")
+		.map(|value| std::env::split_paths(&value).collect())
+		.unwrap_or_default()
+}
+
+/// Where `dir` comes in `path_dirs` (0-based), if it is there at all.
+pub fn path_position(dir: &Path, path_dirs: &[PathBuf]) -> Option<usize> {
+	path_dirs.iter().position(|entry| same_dir(entry, dir))
+}
+
+/// A shim in the managed shim directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shim {
+	/// The tool the shim wraps, i.e. its file name.
+	pub name: String,
+
+	/// The shim itself.
+	pub path: PathBuf,
+
+	/// Where the shim links to, if it is a symlink.
+	pub link: Option<PathBuf>,
+}
+
+/// Something wrong with a shim, found by [`Shim::problems`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShimProblem {
+	/// The file isn't a link to tramp, so tramp didn't install it.
+	NotALink,
+
+	/// The tramp binary the shim links to no longer exists.
+	TrampMissing { link: PathBuf },
+
+	/// There is no real tool by the shim's name on PATH.
+	TargetMissing,
+
+	/// PATH finds the real tool before the shim directory, so the shim never runs.
+	Shadowed { by: PathBuf },
+}
+
+impl ShimProblem {
+	/// Whether the shim is useless and can be removed.
+	pub fn is_stale(&self) -> bool {
+		matches!(
+			self,
+			ShimProblem::TrampMissing { .. } | ShimProblem::TargetMissing
+		)
+	}
+}
+
+impl fmt::Display for ShimProblem {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ShimProblem::NotALink => write!(f, "not a link to tramp"),
+			ShimProblem::TrampMissing { link } => {
+				write!(f, "links to {}, which no longer exists", link.display())
+			}
+			ShimProblem::TargetMissing => write!(f, "no real tool by this name on PATH"),
+			ShimProblem::Shadowed { by } => {
+				write!(f, "PATH finds {} before the shim directory", by.display())
+			}
+		}
+	}
+}
+
+impl Shim {
+	/// The real tool the shim runs, found further down PATH.
+	pub fn target(&self) -> Option<PathBuf> {
+		resolve_command(&self.name)
+	}
+
+	/// Whether the shim is a link to tramp, or to a tramp binary that no
+	/// longer exists. Anything else in the shim directory wasn't installed by
+	/// tramp.
+	pub fn is_tramp_link(&self) -> bool {
+		self.link.is_some() && (is_tramp_shim(&self.path) || is_dangling_tramp_link(&self.path))
+	}
+
+	/// Check that the shim links to an existing tramp, that its tool exists,
+	/// and that `path_dirs` (the entries of PATH) reach the shim before the tool.
+	/// Whether the shim directory is on PATH at all is left to the caller.
+	pub fn problems(&self, path_dirs: &[PathBuf]) -> Vec<ShimProblem> {
+		let mut problems = Vec::new();
+		match self.link {
+			_ if !self.is_tramp_link() => problems.push(ShimProblem::NotALink),
+			Some(ref link) if !is_executable(&self.path) => {
+				problems.push(ShimProblem::TrampMissing { link: link.clone() });
+			}
+			_ => {}
+		}
+
+		let Some(target) = self.target() else {
+			problems.push(ShimProblem::TargetMissing);
+			return problems;
+		};
+		let shim_position = path_position(self.path.parent().unwrap_or(Path::new("")), path_dirs);
+		let target_position = target
+			.parent()
+			.and_then(|target_dir| path_position(target_dir, path_dirs));
+		if let (Some(target_position), Some(shim_position)) = (target_position, shim_position)
+			&& target_position < shim_position
+		{
+			problems.push(ShimProblem::Shadowed { by: target });
+		}
+		problems
+	}
+}
+
+/// Install a shim for `name` in `dir`, creating the directory if needed.
+///
+/// Returns `false` if the shim was already installed. An existing file that
+/// isn't a shim is only replaced with `force`.
+pub fn install_shim(dir: &Path, name: &str, force: bool) -> Result<bool> {
+	let path = shim_path(dir, name)?;
+	if name == "tramp" {
+		return Err(shim_error(&path, "tramp can't wrap itself"));
+	}
+
+	if path.symlink_metadata().is_ok() {
+		if is_tramp_binary(&path) {
+			return Ok(false);
+		}
+		if !force {
+			return Err(shim_error(
+				&path,
+				"a different file already exists there; use --force to replace it",
+			));
+		}
+		std::fs::remove_file(&path).map_err(|e| shim_error(&path, e))?;
+	}
+
+	std::fs::create_dir_all(dir).map_err(|e| shim_error(dir, e))?;
+	let exe = std::env::current_exe().map_err(|e| shim_error(&path, e))?;
+	link_to(&exe, &path)?;
+	Ok(true)
+}
+
+#[cfg(unix)]
+fn link_to(exe: &Path, path: &Path) -> Result<()> {
+	std::os::unix::fs::symlink(exe, path).map_err(|e| shim_error(path, e))
+}
+
+#[cfg(not(unix))]
+fn link_to(exe: &Path, path: &Path) -> Result<()> {
+	std::fs::hard_link(exe, path).map_err(|e| shim_error(path, e))
+}
+
+/// Remove the shim for `name` from `dir`.
+///
+/// Anything that isn't a tramp shim, including a link to another program, is
+/// an error. A link to a tramp binary that no longer exists still counts, so
+/// stale shims can be removed.
+pub fn remove_shim(dir: &Path, name: &str) -> Result<()> {
+	let path = shim_path(dir, name)?;
+	if path.symlink_metadata().is_err() {
+		return Err(shim_error(&path, "no such shim"));
+	}
+	if !is_tramp_shim(&path) && !is_dangling_tramp_link(&path) {
+		return Err(shim_error(&path, "not a tramp shim; remove it by hand"));
+	}
+	std::fs::remove_file(&path).map_err(|e| shim_error(&path, e))
+}
+
+/// The path of the shim for `name` in `dir`, if `name` is a plain file name.
+fn shim_path(dir: &Path, name: &str) -> Result<PathBuf> {
+	let path = dir.join(name);
+	if name.is_empty() || name.contains(std::path::is_separator) || name == "." || name == ".." {
+		return Err(shim_error(&path, "not a valid command name"));
+	}
+	Ok(path)
+}
+
+/// Whether `path` is a link to a tramp binary that no longer exists, as left
+/// behind when tramp is moved or uninstalled.
+fn is_dangling_tramp_link(path: &Path) -> bool {
+	!path.exists()
+		&& std::fs::read_link(path)
+			.is_ok_and(|link| link.file_stem().is_some_and(|stem| stem == "tramp"))
+}
+
+/// The shims in `dir`, by name. A missing directory holds no shims.
+pub fn list_shims(dir: &Path) -> Result<Vec<Shim>> {
+	let entries = match std::fs::read_dir(dir) {
+		Ok(entries) => entries,
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+		Err(e) => return Err(shim_error(dir, e)),
+	};
+
+	let mut shims = Vec::new();
+	for entry in entries {
+		let entry = entry.map_err(|e| shim_error(dir, e))?;
+		let Ok(name) = entry.file_name().into_string() else {
+			continue;
+		};
+		let path = entry.path();
+		shims.push(Shim {
+			link: std::fs::read_link(&path).ok(),
+			name,
+			path,
+		});
+	}
+	shims.sort_by(|a, b| a.name.cmp(&b.name));
+	Ok(shims)
+}
+
+/// The names of the commands on PATH that the `binary_pattern` of some rule
+/// matches. Rules without a `binary_pattern` would match everything, so they
+/// are left out. Only the first command of each name on PATH counts, since
+/// that is the one a shim would run.
+pub fn binaries_matching_rules(rules: &[CompiledRule]) -> Vec<String> {
+	let patterns: Vec<Vec<&Regex>> = rules
+		.iter()
+		.map(|rule| {
+			rule.group_matchers
+				.iter()
+				.filter_map(|group| group.binary_regex.as_ref())
+				.chain(rule.binary_regex.as_ref())
+				.collect::<Vec<_>>()
+		})
+		.filter(|patterns| !patterns.is_empty())
+		.collect();
+	if patterns.is_empty() {
+		return Vec::new();
+	}
+
+	let mut seen = BTreeSet::new();
+	let mut matched = Vec::new();
+	let path_var = std::env::var_os("PATH").unwrap_or_default();
+	let shim_dirs = super::shim_dirs();
+	for dir in std::env::split_paths(&path_var) {
+		if shim_dirs.iter().any(|shim_dir| same_dir(shim_dir, &dir)) {
+			continue;
+		}
+		let Ok(entries) = std::fs::read_dir(&dir) else {
+			continue;
+		};
+		let mut names: Vec<_> = entries
+			.filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+			.collect();
+		names.sort();
+
+		for name in names {
+			let path = dir.join(&name);
+			if seen.contains(&name) || !is_executable(&path) {
+				continue;
+			}
+
+			let subject = path.to_string_lossy();
+			let is_match = patterns
+				.iter()
+				.any(|patterns| patterns.iter().all(|regex| regex.is_match(&subject)));
+			// Shims elsewhere on PATH are skipped by lookups, so they don't count
+			if is_match && is_tramp_shim(&path) {
+				continue;
+			}
+			seen.insert(name.clone());
+			if is_match {
+				matched.push(name);
+			}
+		}
+	}
+	matched
+}
+
+fn shim_error(path: &Path, error: impl fmt::Display) -> TrampError {
+	TrampError::ShimError {
+		path: path.to_path_buf(),
+		message: error.to_string(),
+	}
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+	use super::*;
+	use std::os::unix::fs::PermissionsExt;
+
+	#[test]
+	fn test_install_list_and_remove_shims() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path().join("shims");
+
+		assert!(list_shims(&dir).unwrap().is_empty());
+		assert!(install_shim(&dir, "cargo", false).unwrap());
+		assert!(!install_shim(&dir, "cargo", false).unwrap());
+		assert!(install_shim(&dir, "../escape", false).is_err());
+		assert!(install_shim(&dir, "tramp", false).is_err());
+
+		// Other files are only replaced with force, and never removed
+		std::fs::write(dir.join("npm"), "#!/bin/sh\n").unwrap();
+		assert!(install_shim(&dir, "npm", false).is_err());
+		assert!(remove_shim(&dir, "npm").is_err());
+		assert!(install_shim(&dir, "npm", true).unwrap());
+
+		let shims = list_shims(&dir).unwrap();
+		let names: Vec<_> = shims.iter().map(|shim| shim.name.as_str()).collect();
+		assert_eq!(names, ["cargo", "npm"]);
+		assert_eq!(
+			shims[0].link.as_deref(),
+			Some(std::env::current_exe().unwrap().as_path())
+		);
+
+		remove_shim(&dir, "cargo").unwrap();
+		assert!(remove_shim(&dir, "cargo").is_err());
+		assert_eq!(list_shims(&dir).unwrap().len(), 1);
+	}
+
+	#[test]
+	fn test_remove_shim_leaves_other_links_alone() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let dir = temp_dir.path().join("shims");
+		std::fs::create_dir_all(&dir).unwrap();
+
+		// Links to other programs, inside the shim directory or next to it
+		std::os::unix::fs::symlink("/bin/sh", dir.join("sh")).unwrap();
+		std::os::unix::fs::symlink("/bin/sh", temp_dir.path().join("outside")).unwrap();
+		assert!(remove_shim(&dir, "sh").is_err());
+		assert!(remove_shim(&dir, "../outside").is_err());
+		assert!(dir.join("sh").symlink_metadata().is_ok());
+		assert!(temp_dir.path().join("outside").symlink_metadata().is_ok());
+
+		// Links to a tramp that was moved away are stale shims
+		std::os::unix::fs::symlink("/nonexistent/bin/tramp", dir.join("cargo")).unwrap();
+		std::os::unix::fs::symlink("/nonexistent/bin/other", dir.join("npm")).unwrap();
+		remove_shim(&dir, "cargo").unwrap();
+		assert!(remove_shim(&dir, "npm").is_err());
+	}
+
+	#[test]
+	fn test_shim_problems() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let shim_dir = temp_dir.path().join("shims");
+		std::fs::create_dir_all(&shim_dir).unwrap();
+
+		// A tool that does exist on PATH, and whose directory is known
+		let sh = resolve_command("sh").unwrap();
+		let sh_dir = sh.parent().unwrap().to_path_buf();
+
+		std::os::unix::fs::symlink("/nonexistent/tramp", shim_dir.join("sh")).unwrap();
+		let shim = Shim {
+			name: "sh".to_string(),
+			path: shim_dir.join("sh"),
+			link: Some(PathBuf::from("/nonexistent/tramp")),
+		};
+		let problems = shim.problems(&[shim_dir.clone(), sh_dir.clone()]);
+		assert_eq!(
+			problems,
+			[ShimProblem::TrampMissing {
+				link: PathBuf::from("/nonexistent/tramp")
+			}]
+		);
+		assert!(problems[0].is_stale());
+
+		let problems = shim.problems(&[sh_dir.clone(), shim_dir.clone()]);
+		assert!(problems.contains(&ShimProblem::Shadowed { by: sh.clone() }));
+		assert!(!problems.last().unwrap().is_stale());
+
+		// A working link, but nothing to wrap
+		let exe = std::env::current_exe().unwrap();
+		std::os::unix::fs::symlink(&exe, shim_dir.join("no-such-tool-here")).unwrap();
+		let shim = Shim {
+			name: "no-such-tool-here".to_string(),
+			path: shim_dir.join("no-such-tool-here"),
+			link: Some(exe),
+		};
+		assert!(shim.is_tramp_link());
+		assert_eq!(
+			shim.problems(std::slice::from_ref(&shim_dir)),
+			[ShimProblem::TargetMissing]
+		);
+
+		// A link to another program wasn't installed by tramp
+		let tool = temp_dir.path().join("tool");
+		std::fs::write(&tool, "#!/bin/sh\n").unwrap();
+		std::fs::set_permissions(&tool, std::fs::Permissions::from_mode(0o755)).unwrap();
+		std::os::unix::fs::symlink(&tool, shim_dir.join("sh-link")).unwrap();
+		let shims = list_shims(&shim_dir).unwrap();
+		let foreign = shims.iter().find(|shim| shim.name == "sh-link").unwrap();
+		assert!(!foreign.is_tramp_link());
+		assert_eq!(foreign.problems(&[shim_dir])[0], ShimProblem::NotALink);
+	}
+}
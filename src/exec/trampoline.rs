@@ -4,6 +4,11 @@
 
 use std::path::Path;
 
+/// Marker line identifying scripts generated by [`generate_trampoline_script`].
+///
+/// PATH lookups use it to skip trampolines so they never resolve to themselves.
+pub const TRAMPOLINE_MARKER: &str = "# Trampoline script generated by tramp";
+
 /// Generate a trampoline shell script for a binary.
 ///
 /// The generated script:
@@ -17,21 +22,25 @@ pub fn generate_trampoline_script(binary_path: &Path, tramp_path: Option<&Path>)
 
 	format!(
 		r#"#!/bin/sh
-# Trampoline script generated by tramp
+{marker}
 # This script wraps {binary} through tramp's hook system
 
 exec "{tramp}" "{binary}" "$@"
 "#,
+		marker = TRAMPOLINE_MARKER,
 		binary = binary_str,
 		tramp = tramp_cmd,
 	)
 }
 
 /// Generate the default template for --init.
-pub fn generate_init_template() -> &'static str {
+pub const fn generate_init_template() -> &'static str {
 	r#"# .tramp.toml - Tramp configuration
 # See: https://github.com/fbartho/tramp
 
+# Config format version; `tramp config migrate` upgrades older files
+version = 1
+
 # Stop directory cascade here and jump to ~/.tramp.toml
 # (Recommended: improves performance by limiting config file searches)
 root = true
@@ -42,17 +51,27 @@ root = true
 # Skip ~/.tramp.toml lookup when this env var is truthy (useful for CI)
 # root-config-lookup-disable-env-var = "CI"
 
+# Refuse to run when tramp is nested this many levels deep (default: 8)
+# max-depth = 8
+
 # Rules: first matching rule wins
 # Supports both [[rules]] and rules = [...] syntax
 
 # Example: Rewrite arguments
 # [[rules]]
+# id = "release-builds"              # Optional: name the rule for tests and commands
 # binary_pattern = ".*/cargo$"       # Regex to match command path
 # cwd_pattern = ".*/my-project$"     # Optional: only match in specific directories
 # arg_rewrite = "s/^build$/build --release/"  # Regex substitution on args
 # pre_hook = "/path/to/pre-hook.sh"  # Run before command
 # post_hook = "/path/to/post-hook.sh"  # Run after command
 
+# Example: Only match when launched by a particular parent process
+# [[rules]]
+# binary_pattern = ".*/cargo$"
+# parent_process_pattern = "/code$"          # Regex on the parent's executable or command line
+# ancestor_exclude_pattern = "/tramp$"       # Skip when any ancestor matches (see ancestor_depth)
+
 # Example: Replace with alternate command
 # [[rules]]
 # binary_pattern = ".*/npm$"
@@ -70,6 +89,34 @@ root = true
 #   TRAMP_CWD             - working directory
 #   TRAMP_HOOK_TYPE       - "pre", "post", or "intercept"
 #   TRAMP_EXIT_CODE       - exit code (post-hooks only)
+#   TRAMP_PARENT_PID      - PID of the process that launched tramp
+#   TRAMP_PARENT_EXE      - parent executable path (also _CMDLINE and _NAME)
+
+# Tests: check how commands are handled with `tramp test` (nothing is run)
+# [[tests]]
+# command = ["cargo", "build"]
+# cwd = "."                                  # Relative to this file
+# expect_rule = "release-builds"             # Rule id, or "none"
+# expect_argv = ["cargo", "build", "--release"]
+# expect_hooks = ["pre", "post"]
+"#
+}
+
+/// Generate the template for `tramp init --local`.
+pub const fn generate_local_template() -> &'static str {
+	r#"# .tramp.local.toml - Personal overrides for this repository
+# See: https://github.com/fbartho/tramp
+#
+# Keep this file out of version control. Its rules are checked before the
+# .tramp.toml next to it, unless that config sets no-external-lookup = true.
+# Whether the directory walk continues is still decided by .tramp.toml.
+
+version = 1
+
+# Example: Log your own builds in this repository only
+# [[rules]]
+# binary_pattern = ".*/cargo$"
+# post_hook = "/path/to/my-build-log.sh"
 "#
 }
 
@@ -95,6 +142,17 @@ mod tests {
 		assert!(script.contains("exec \"/opt/tramp/bin/tramp\" \"/usr/local/bin/cargo\" \"$@\""));
 	}
 
+	#[test]
+	fn test_generate_local_template_parses() {
+		let config = crate::config::parse_config_str(
+			generate_local_template(),
+			Path::new(".tramp.local.toml"),
+		)
+		.unwrap();
+		assert!(!config.root);
+		assert!(config.rules.is_empty());
+	}
+
 	#[test]
 	fn test_generate_init_template() {
 		let template = generate_init_template();
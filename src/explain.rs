@@ -0,0 +1,331 @@
+//! Dry-run tracing of rule evaluation for `tramp explain`.
+//!
+//! This module evaluates every rule against a command without running
+//! anything, recording which matchers passed, which rule was chosen, the
+//! rewritten command, and the hooks that would run.
+
+use crate::config::types::{
+	GroupRef, LoadedConfig, MergedConfig, Rule, SkippedRule, rule_position,
+};
+use crate::error::Result;
+use crate::hooks::HookType;
+use crate::process::ProcessInfo;
+use crate::rules::{
+	MatchContext, MatcherOutcome, apply_rule, compile_rules, required_ancestry_depth,
+};
+use crate::vars::preview_rule;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// A full trace of how tramp would handle a command.
+#[derive(Debug, Clone, Serialize)]
+pub struct Explanation {
+	/// The resolved binary being explained.
+	pub binary: PathBuf,
+
+	/// The arguments passed to the command.
+	pub args: Vec<String>,
+
+	/// The working directory used for matching.
+	pub cwd: PathBuf,
+
+	/// Config files that were loaded, in cascade order.
+	pub configs: Vec<PathBuf>,
+
+	/// Every rule in cascade order with its matcher results.
+	pub rules: Vec<RuleTrace>,
+
+	/// Rules left out because they expired, are muted, or are outside their
+	/// active hours.
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub skipped: Vec<SkippedRule>,
+
+	/// Index into `rules` of the rule that would be applied (first match wins).
+	pub chosen_rule: Option<usize>,
+
+	/// The binary that would be executed after applying the chosen rule.
+	pub final_binary: PathBuf,
+
+	/// The arguments that would be passed after applying the chosen rule.
+	pub final_args: Vec<String>,
+
+	/// Why the chosen rule's rewrite failed, if it did.
+	pub rewrite_error: Option<String>,
+
+	/// Variables the chosen rule uses that weren't computed, since that would
+	/// run their commands. Their `${vars.NAME}` references are left in place.
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub not_computed: Vec<String>,
+
+	/// Hooks that would run, in execution order.
+	pub hooks: Vec<PlannedHook>,
+}
+
+/// The evaluation of a single rule.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleTrace {
+	/// Position of the rule in the merged cascade (0-based).
+	pub index: usize,
+
+	/// The config file the rule came from.
+	pub source: PathBuf,
+
+	/// Position of the rule within its config file, or its group (0-based).
+	pub source_index: usize,
+
+	/// The group the rule was declared in, if any.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub group: Option<GroupRef>,
+
+	/// The rule's `id`, if it has one.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub id: Option<String>,
+
+	/// Result of each configured matcher. Empty for catch-all rules.
+	pub matchers: Vec<MatcherOutcome>,
+
+	/// Whether all matchers passed.
+	pub matched: bool,
+}
+
+impl RuleTrace {
+	/// Where the rule sits in its config file, e.g. `group cargo, rule 2`.
+	pub fn position(&self) -> String {
+		rule_position(self.group.as_ref(), self.source_index)
+	}
+}
+
+/// A hook that would run for the chosen rule.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedHook {
+	/// The kind of hook.
+	pub hook_type: HookType,
+
+	/// The hook script path.
+	pub path: PathBuf,
+}
+
+/// Evaluate all rules of `merged`, merged from `configs`, against a command
+/// without executing anything.
+///
+/// `ancestry` reads the invocation chain, given how many levels the rules
+/// inspect.
+pub fn explain_command(
+	configs: &[LoadedConfig],
+	merged: MergedConfig,
+	binary_path: &Path,
+	args: &[String],
+	cwd: &Path,
+	ancestry: impl FnOnce(usize) -> Vec<ProcessInfo>,
+) -> Result<Explanation> {
+	let rules = compile_rules(&merged)?;
+	let ancestry = ancestry(required_ancestry_depth(&rules));
+
+	let ctx = MatchContext {
+		binary_path,
+		cwd,
+		args,
+		ancestry: &ancestry,
+	};
+
+	let mut traces = Vec::with_capacity(rules.len());
+	let mut chosen_rule = None;
+
+	for (index, rule) in rules.iter().enumerate() {
+		let matchers = rule.evaluate(&ctx);
+		let matched = matchers.iter().all(|outcome| outcome.passed);
+
+		if matched && chosen_rule.is_none() {
+			chosen_rule = Some(index);
+		}
+
+		traces.push(RuleTrace {
+			index,
+			source: rule.source.clone(),
+			source_index: rule.source_index,
+			group: rule.rule.group.clone(),
+			id: rule.rule.id.clone(),
+			matchers,
+			matched,
+		});
+	}
+
+	let mut final_binary = binary_path.to_path_buf();
+	let mut final_args = args.to_vec();
+	let mut rewrite_error = None;
+	let mut not_computed = Vec::new();
+	let mut hooks = Vec::new();
+
+	if let Some(index) = chosen_rule {
+		let chosen = &rules[index];
+
+		// Variables are filled in without running their commands
+		let rule = preview_rule(&chosen.rule, &merged.vars, cwd).and_then(|(rule, uncomputed)| {
+			not_computed = uncomputed;
+			apply_rule(binary_path, args, &rule).map(|applied| (rule, applied))
+		});
+		match rule {
+			Ok((rule, (binary, new_args))) => {
+				final_binary = binary;
+				final_args = new_args;
+				hooks = planned_hooks(&rule);
+			}
+			Err(e) => {
+				rewrite_error = Some(e.to_string());
+				hooks = planned_hooks(&chosen.rule);
+			}
+		}
+	}
+
+	Ok(Explanation {
+		binary: binary_path.to_path_buf(),
+		args: args.to_vec(),
+		cwd: cwd.to_path_buf(),
+		configs: configs.iter().map(|loaded| loaded.path.clone()).collect(),
+		rules: traces,
+		skipped: merged.skipped,
+		chosen_rule,
+		final_binary,
+		final_args,
+		rewrite_error,
+		not_computed,
+		hooks,
+	})
+}
+
+/// The hooks a rule would run, in order. An intercept hook replaces the
+/// command, so post-hooks never run alongside it.
+fn planned_hooks(rule: &Rule) -> Vec<PlannedHook> {
+	let mut hooks = Vec::new();
+
+	if let Some(ref path) = rule.pre_hook {
+		hooks.push(PlannedHook {
+			hook_type: HookType::Pre,
+			path: path.clone(),
+		});
+	}
+
+	if let Some(ref path) = rule.intercept_hook {
+		hooks.push(PlannedHook {
+			hook_type: HookType::Intercept,
+			path: path.clone(),
+		});
+	} else if let Some(ref path) = rule.post_hook {
+		hooks.push(PlannedHook {
+			hook_type: HookType::Post,
+			path: path.clone(),
+		});
+	}
+
+	hooks
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::config::cascade::merge_configs_at;
+	use crate::config::mutes::MutedRules;
+	use crate::config::parse_config_str;
+
+	fn load(content: &str, path: &str) -> LoadedConfig {
+		LoadedConfig {
+			config: parse_config_str(content, Path::new(path)).unwrap(),
+			path: PathBuf::from(path),
+		}
+	}
+
+	#[test]
+	fn test_explain_chooses_first_matching_rule() {
+		let configs = vec![
+			load(
+				r#"
+[[rules]]
+binary_pattern = ".*/npm$"
+alternate_command = "pnpm"
+
+[[rules]]
+binary_pattern = ".*/cargo$"
+arg_rewrite = "s/^build$/build --release/"
+pre_hook = "/hooks/pre.sh"
+post_hook = "/hooks/post.sh"
+"#,
+				"/repo/.tramp.toml",
+			),
+			load(
+				r#"
+[[rules]]
+binary_pattern = ".*/cargo$"
+"#,
+				"/home/user/.tramp.toml",
+			),
+		];
+
+		let explanation = explain_command(
+			&configs,
+			merge_configs_at(&configs, None, &MutedRules::default()),
+			Path::new("/usr/bin/cargo"),
+			&["build".to_string()],
+			Path::new("/repo"),
+			|_| Vec::new(),
+		)
+		.unwrap();
+
+		assert_eq!(explanation.rules.len(), 3);
+		assert!(!explanation.rules[0].matched);
+		assert!(explanation.rules[1].matched);
+		assert_eq!(explanation.rules[1].source_index, 1);
+		// Later rules are still evaluated, even though they can't win
+		assert!(explanation.rules[2].matched);
+		assert_eq!(explanation.rules[2].source_index, 0);
+
+		assert_eq!(explanation.chosen_rule, Some(1));
+		assert_eq!(explanation.final_args, vec!["build", "--release"]);
+		assert!(explanation.rewrite_error.is_none());
+
+		let hook_types: Vec<_> = explanation.hooks.iter().map(|h| h.hook_type).collect();
+		assert_eq!(hook_types, vec![HookType::Pre, HookType::Post]);
+	}
+
+	#[test]
+	fn test_explain_intercept_hook_skips_post_hook() {
+		let rule = Rule {
+			intercept_hook: Some(PathBuf::from("/hooks/intercept.sh")),
+			post_hook: Some(PathBuf::from("/hooks/post.sh")),
+			..Default::default()
+		};
+
+		let hooks = planned_hooks(&rule);
+		assert_eq!(hooks.len(), 1);
+		assert_eq!(hooks[0].hook_type, HookType::Intercept);
+	}
+
+	#[test]
+	fn test_explain_reports_rewrite_errors() {
+		let configs = vec![load(
+			r#"
+[[rules]]
+alternate_command = "nonexistent_command_12345"
+"#,
+			"/repo/.tramp.toml",
+		)];
+
+		let explanation = explain_command(
+			&configs,
+			merge_configs_at(&configs, None, &MutedRules::default()),
+			Path::new("/usr/bin/npm"),
+			&[],
+			Path::new("/repo"),
+			|_| Vec::new(),
+		)
+		.unwrap();
+
+		assert_eq!(explanation.chosen_rule, Some(0));
+		assert_eq!(explanation.final_binary, Path::new("/usr/bin/npm"));
+		assert!(
+			explanation
+				.rewrite_error
+				.unwrap()
+				.contains("nonexistent_command_12345")
+		);
+	}
+}
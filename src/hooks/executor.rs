@@ -1,10 +1,13 @@
 use crate::error::{Result, TrampError};
+use crate::process::ProcessInfo;
+use serde::Serialize;
 use std::collections::HashMap;
 use std::path::Path;
 use std::process::{Command, Stdio};
 
 /// Type of hook being executed.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum HookType {
 	Pre,
 	Post,
@@ -45,6 +48,9 @@ pub struct HookContext<'a> {
 
 	/// Exit code from the command (for post-hooks).
 	pub exit_code: Option<i32>,
+
+	/// The process that invoked tramp, if known.
+	pub parent: Option<&'a ProcessInfo>,
 }
 
 /// Build environment variables for hook execution.
@@ -79,6 +85,23 @@ pub fn build_hook_env(ctx: &HookContext) -> HashMap<String, String> {
 		ctx.hook_type.as_str().to_string(),
 	);
 
+	// Parent process, when it could be determined
+	if let Some(parent) = ctx.parent {
+		env.insert("TRAMP_PARENT_PID".to_string(), parent.pid.to_string());
+		if let Some(ref exe) = parent.exe {
+			env.insert(
+				"TRAMP_PARENT_EXE".to_string(),
+				exe.to_string_lossy().to_string(),
+			);
+		}
+		if !parent.cmdline.is_empty() {
+			env.insert("TRAMP_PARENT_CMDLINE".to_string(), parent.cmdline_string());
+		}
+		if let Some(name) = parent.name() {
+			env.insert("TRAMP_PARENT_NAME".to_string(), name);
+		}
+	}
+
 	// Post-hook only
 	if let Some(executed_binary) = ctx.executed_binary {
 		env.insert(
@@ -109,7 +132,8 @@ pub fn execute_hook(hook_path: &Path, ctx: &HookContext) -> Result<i32> {
 		.stdin(Stdio::inherit())
 		.stdout(Stdio::inherit())
 		.stderr(Stdio::inherit())
-		.envs(&env);
+		.envs(&env)
+		.envs(crate::exec::nested_env(hook_path));
 
 	let status = cmd.status().map_err(|source| TrampError::HookFailed {
 		hook_path: hook_path.to_path_buf(),
@@ -145,6 +169,7 @@ pub fn execute_intercept_hook(hook_path: &Path, ctx: &HookContext) -> Result<i32
 }
 
 #[cfg(test)]
+#[allow(clippy::unnecessary_get_then_check)]
 mod tests {
 	use super::*;
 
@@ -165,6 +190,7 @@ mod tests {
 			executed_binary: None,
 			executed_args: None,
 			exit_code: None,
+			parent: None,
 		};
 
 		let env = build_hook_env(&ctx);
@@ -184,6 +210,7 @@ mod tests {
 		assert!(env.get("TRAMP_EXECUTED_BINARY").is_none());
 		assert!(env.get("TRAMP_EXECUTED_ARGS").is_none());
 		assert!(env.get("TRAMP_EXIT_CODE").is_none());
+		assert!(env.get("TRAMP_PARENT_PID").is_none());
 	}
 
 	#[test]
@@ -201,6 +228,7 @@ mod tests {
 			executed_binary: Some(Path::new("/usr/local/bin/cargo")),
 			executed_args: Some(&executed_args),
 			exit_code: Some(0),
+			parent: None,
 		};
 
 		let env = build_hook_env(&ctx);
@@ -227,6 +255,7 @@ mod tests {
 			executed_binary: None,
 			executed_args: None,
 			exit_code: None,
+			parent: None,
 		};
 
 		let env = build_hook_env(&ctx);
@@ -249,6 +278,7 @@ mod tests {
 			executed_binary: Some(Path::new("/usr/local/bin/deploy")),
 			executed_args: Some(&executed_args),
 			exit_code: None, // No exit code yet - command hasn't run
+			parent: None,
 		};
 
 		let env = build_hook_env(&ctx);
@@ -283,4 +313,34 @@ mod tests {
 		// No exit code for intercept hooks (command doesn't run)
 		assert!(env.get("TRAMP_EXIT_CODE").is_none());
 	}
+
+	#[test]
+	fn test_build_hook_env_parent_process() {
+		let parent = ProcessInfo {
+			pid: 4242,
+			ppid: Some(1),
+			exe: Some(std::path::PathBuf::from("/usr/share/code/code")),
+			cmdline: vec!["/usr/share/code/code".to_string(), "--wait".to_string()],
+		};
+		let ctx = HookContext {
+			original_binary: Path::new("/usr/local/bin/cargo"),
+			original_args: &[],
+			cwd: Path::new("/home/user/project"),
+			hook_type: HookType::Pre,
+			executed_binary: None,
+			executed_args: None,
+			exit_code: None,
+			parent: Some(&parent),
+		};
+
+		let env = build_hook_env(&ctx);
+
+		assert_eq!(env.get("TRAMP_PARENT_PID").unwrap(), "4242");
+		assert_eq!(env.get("TRAMP_PARENT_EXE").unwrap(), "/usr/share/code/code");
+		assert_eq!(
+			env.get("TRAMP_PARENT_CMDLINE").unwrap(),
+			"/usr/share/code/code --wait"
+		);
+		assert_eq!(env.get("TRAMP_PARENT_NAME").unwrap(), "code");
+	}
 }
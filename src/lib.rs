@@ -4,6 +4,9 @@
 //! - Configuration file parsing and cascade discovery
 //! - Rule matching and command rewriting
 //! - Hook execution with environment variable context
+//! - Parent process and invocation chain inspection
+//! - Dry-run tracing of rule evaluation
+//! - Computed variables interpolated into rules
 //! - Command execution with proper stdio handling
 //!
 //! # Example
@@ -21,6 +24,7 @@
 //!     binary_path: Path::new("/usr/local/bin/cargo"),
 //!     cwd: &cwd,
 //!     args: &["build".to_string()],
+//!     ancestry: &[],
 //! };
 //!
 //! if let Some(rule) = find_matching_rule(&rules, &ctx) {
@@ -31,7 +35,11 @@
 pub mod config;
 pub mod error;
 pub mod exec;
+pub mod explain;
 pub mod hooks;
+pub mod process;
 pub mod rules;
+pub mod testing;
+pub mod vars;
 
 pub use error::{Result, TrampError};
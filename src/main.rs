@@ -1,17 +1,40 @@
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use clap::builder::{PossibleValue, PossibleValuesParser};
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use std::io::{IsTerminal, Write};
 use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 
-use tramp_cli::config::{load_merged_config, user_config_path};
-use tramp_cli::exec::trampoline::{generate_init_template, generate_trampoline_script};
-use tramp_cli::exec::{execute_command, resolve_command};
+use tramp_cli::config::{
+	CONFIG_FILE_NAME, CascadeEnd, ConfigDiscovery, ConfigEditor, DEFAULT_PRESET, Diagnostic,
+	ENV_RULES_SOURCE, EnvRulesDecision, LOCAL_CONFIG_FILE_NAME, MergedConfig, MutedRules, PRESETS,
+	RULES_ENV_VAR, Rule, Severity, SkippedRule, UserConfigDecision, config_schema_json,
+	describe_mute_end, discover_configs, find_config, find_preset, is_embedded, is_env_rules,
+	load_merged_config, load_single_config, local_now, merge_configs, migrate_file,
+	muted_rules_path, rule_position, unwarned_expired_rules, user_config_path, validate_cascade,
+};
+use tramp_cli::exec::bypass::{Skips, bypass_requested};
+use tramp_cli::exec::multicall::invoked_as;
+use tramp_cli::exec::shims::{
+	ShimProblem, binaries_matching_rules, install_shim, list_shims, managed_shim_dir, path_dirs,
+	path_position, remove_shim,
+};
+use tramp_cli::exec::trampoline::{generate_local_template, generate_trampoline_script};
+use tramp_cli::exec::{
+	DEFAULT_MAX_DEPTH, check_recursion_depth, execute_command, exit_code, resolve_command,
+};
+use tramp_cli::explain::{Explanation, explain_command};
 use tramp_cli::hooks::{
 	HookContext, HookType, execute_intercept_hook, execute_post_hook, execute_pre_hook,
 };
+use tramp_cli::process::process_ancestry;
 use tramp_cli::rules::{
-	MatchContext, Substitution, compile_rules, find_matching_rule, rewrite_args, rewrite_command,
+	MatchContext, apply_rule, compile_rules, find_matching_rule, required_ancestry_depth,
 };
+use tramp_cli::testing::{TestReport, run_config_tests};
+use tramp_cli::vars::{interpolate_rule, preview_rule};
 
 #[derive(Parser)]
 #[command(name = "tramp")]
@@ -37,11 +60,40 @@ struct Cli {
 	#[arg(long, requires = "init")]
 	force: bool,
 
+	#[command(flatten)]
+	run: RunOptions,
+
 	/// Command to run through tramp
 	#[arg(trailing_var_arg = true, allow_hyphen_values = true)]
 	args: Vec<String>,
 }
 
+/// Flags for running a command. They are only read before the command, so
+/// the command's own flags of the same name pass through untouched.
+#[derive(Args, Default)]
+struct RunOptions {
+	/// Print the rule that applies and the command that runs to stderr
+	#[arg(short, long, requires = "args")]
+	verbose: bool,
+
+	/// Show what would run, without running the command or any hook
+	#[arg(long, requires = "args")]
+	dry_run: bool,
+
+	/// Use only this config file, instead of discovering the cascade
+	#[arg(
+		long,
+		value_name = "PATH",
+		requires = "args",
+		conflicts_with = "bypass"
+	)]
+	config: Option<PathBuf>,
+
+	/// Run the command as is, without loading any config (same as TRAMP_BYPASS=1)
+	#[arg(long, requires = "args")]
+	bypass: bool,
+}
+
 #[derive(Subcommand)]
 enum Commands {
 	/// Configuration management commands
@@ -49,14 +101,384 @@ enum Commands {
 		#[command(subcommand)]
 		action: ConfigAction,
 	},
+	/// Mute a rule on this machine, without editing any config file
+	///
+	/// The rule is skipped wherever its id appears, until `tramp enable` or the
+	/// end of --for.
+	Disable {
+		/// Id of the rule to mute
+		rule: String,
+
+		/// Mute only for this long, e.g. 30m, 2h, or 1d
+		#[arg(long = "for", value_name = "DURATION")]
+		duration: Option<jiff::Span>,
+	},
+	/// Unmute a rule muted with `tramp disable`
+	Enable {
+		/// Id of the rule to unmute
+		rule: String,
+	},
+	/// Trace how rules would apply to a command, without running anything
+	Explain {
+		/// Output format
+		#[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+		format: OutputFormat,
+
+		/// Command to explain
+		#[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+		command: Vec<String>,
+	},
+	/// Create a .tramp.toml from a built-in preset
+	Init {
+		/// Which preset to start from
+		#[arg(long, default_value = DEFAULT_PRESET, value_parser = preset_parser())]
+		preset: String,
+
+		/// Create the user config (~/.tramp.toml, or the XDG location) instead
+		#[arg(long, conflicts_with = "local")]
+		user: bool,
+
+		/// Create .tramp.local.toml, for personal overrides that aren't committed
+		#[arg(long)]
+		local: bool,
+
+		/// With --local, add .tramp.local.toml to .gitignore without asking
+		#[arg(long, requires = "local", conflicts_with = "no_gitignore")]
+		gitignore: bool,
+
+		/// With --local, leave .gitignore alone without asking
+		#[arg(long, requires = "local")]
+		no_gitignore: bool,
+
+		/// Overwrite an existing config file
+		#[arg(long)]
+		force: bool,
+	},
+	/// Add, remove, enable, or disable rules in a config file
+	Rule {
+		#[command(subcommand)]
+		action: RuleAction,
+	},
+	/// Run a command through tramp, even one named like a tramp subcommand
+	///
+	/// Tramp's flags go before the command; everything after it is passed on.
+	#[command(visible_alias = "exec")]
+	Run {
+		#[command(flatten)]
+		options: RunOptions,
+
+		/// Command to run, optionally after `--`
+		#[arg(
+			required = true,
+			value_name = "COMMAND",
+			trailing_var_arg = true,
+			allow_hyphen_values = true
+		)]
+		args: Vec<String>,
+	},
+	/// Manage shims in the shim directory (~/.local/share/tramp/shims)
+	Shim {
+		#[command(subcommand)]
+		action: ShimAction,
+	},
+	/// Check the [[tests]] in config files against the rules, without running anything
+	///
+	/// Exits 1 if any test fails.
+	Test {
+		/// Output format
+		#[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+		format: OutputFormat,
+
+		/// Only run tests whose name contains this string
+		filter: Option<String>,
+	},
+}
+
+/// Accept only built-in preset names, listing them with descriptions in `--help`.
+fn preset_parser() -> PossibleValuesParser {
+	PossibleValuesParser::new(
+		PRESETS
+			.iter()
+			.map(|preset| PossibleValue::new(preset.name).help(preset.description)),
+	)
+}
+
+#[derive(Subcommand)]
+enum RuleAction {
+	/// Add a rule built from flags
+	Add {
+		#[command(flatten)]
+		target: TargetFile,
+
+		/// Insert at this position (1-based) instead of appending; earlier rules win
+		#[arg(long)]
+		position: Option<usize>,
+
+		#[command(flatten)]
+		rule: Box<RuleArgs>,
+	},
+	/// Remove a rule
+	Remove {
+		#[command(flatten)]
+		target: TargetFile,
+
+		/// Rule id, or its number as shown by `tramp config show`
+		rule: String,
+	},
+	/// Enable a disabled rule
+	Enable {
+		#[command(flatten)]
+		target: TargetFile,
+
+		/// Rule id, or its number as shown by `tramp config show`
+		rule: String,
+	},
+	/// Keep a rule in the file but stop applying it
+	Disable {
+		#[command(flatten)]
+		target: TargetFile,
+
+		/// Rule id, or its number as shown by `tramp config show`
+		rule: String,
+	},
+	/// Delete rules whose `expires` date has passed
+	Prune {
+		#[command(flatten)]
+		target: TargetFile,
+	},
+}
+
+#[derive(Subcommand)]
+enum ShimAction {
+	/// Install shims for the named commands, which must be on PATH
+	Install {
+		/// Commands to wrap
+		#[arg(required_unless_present = "from_config")]
+		names: Vec<String>,
+
+		/// Also wrap every command on PATH that a rule's `binary_pattern` matches
+		#[arg(long)]
+		from_config: bool,
+
+		/// Replace files in the shim directory that aren't shims
+		#[arg(long)]
+		force: bool,
+	},
+	/// List installed shims and the commands they run
+	List,
+	/// Remove shims
+	Remove {
+		/// Shims to remove
+		#[arg(required_unless_present = "stale")]
+		names: Vec<String>,
+
+		/// Remove shims whose tramp or command no longer exists
+		#[arg(long)]
+		stale: bool,
+	},
+	/// Check that the shim directory comes early enough on PATH and that every
+	/// shim points to an existing tramp and command
+	///
+	/// Exits 1 if any problem is found.
+	Doctor,
+}
+
+/// Which config file an editing command changes.
+#[derive(Args)]
+struct TargetFile {
+	/// Config file to edit (default: .tramp.toml in the current directory)
+	#[arg(long, value_name = "PATH", conflicts_with = "user")]
+	file: Option<PathBuf>,
+
+	/// Edit the user config (~/.tramp.toml)
+	#[arg(long)]
+	user: bool,
+
+	/// Edit your personal overrides (.tramp.local.toml in the current directory)
+	#[arg(long, conflicts_with_all = ["file", "user"])]
+	local: bool,
+}
+
+impl TargetFile {
+	fn path(&self) -> Result<PathBuf> {
+		if self.user {
+			Ok(user_config_path()?)
+		} else if self.local {
+			Ok(PathBuf::from(LOCAL_CONFIG_FILE_NAME))
+		} else {
+			Ok(self
+				.file
+				.clone()
+				.unwrap_or_else(|| PathBuf::from(CONFIG_FILE_NAME)))
+		}
+	}
+
+	fn open(&self) -> Result<ConfigEditor> {
+		let path = self.path()?;
+		ConfigEditor::open(&path).with_context(|| format!("Failed to open {}", path.display()))
+	}
+}
+
+/// Rule fields for `tramp rule add`.
+#[derive(Args)]
+struct RuleArgs {
+	/// Identifier for the rule
+	#[arg(long)]
+	id: Option<String>,
+
+	/// Template to build the rule on (repeatable; later templates win)
+	#[arg(long = "use", value_name = "TEMPLATE")]
+	uses: Vec<String>,
+
+	/// Regex to match the command binary path
+	#[arg(long)]
+	binary_pattern: Option<String>,
+
+	/// Regex to match the current working directory
+	#[arg(long)]
+	cwd_pattern: Option<String>,
+
+	/// Regex to match the parent process
+	#[arg(long)]
+	parent_process_pattern: Option<String>,
+
+	/// Regex that must match an ancestor process
+	#[arg(long)]
+	ancestor_pattern: Option<String>,
+
+	/// Regex that must not match any ancestor process
+	#[arg(long)]
+	ancestor_exclude_pattern: Option<String>,
+
+	/// How many ancestor levels the ancestor patterns inspect
+	#[arg(long)]
+	ancestor_depth: Option<usize>,
+
+	/// Substitution applied to the arguments, e.g. "s/^build$/build --release/"
+	#[arg(long)]
+	arg_rewrite: Option<String>,
+
+	/// Substitution applied to the whole command string
+	#[arg(long)]
+	command_rewrite: Option<String>,
+
+	/// Command to run instead
+	#[arg(long)]
+	alternate_command: Option<String>,
+
+	/// Hook to run before the command
+	#[arg(long)]
+	pre_hook: Option<PathBuf>,
+
+	/// Hook to run after the command
+	#[arg(long)]
+	post_hook: Option<PathBuf>,
+
+	/// Hook that replaces the command entirely
+	#[arg(long)]
+	intercept_hook: Option<PathBuf>,
+}
+
+impl From<RuleArgs> for Rule {
+	fn from(args: RuleArgs) -> Self {
+		Rule {
+			id: args.id,
+			uses: args.uses,
+			binary_pattern: args.binary_pattern,
+			cwd_pattern: args.cwd_pattern,
+			parent_process_pattern: args.parent_process_pattern,
+			ancestor_pattern: args.ancestor_pattern,
+			ancestor_exclude_pattern: args.ancestor_exclude_pattern,
+			ancestor_depth: args.ancestor_depth,
+			arg_rewrite: args.arg_rewrite,
+			command_rewrite: args.command_rewrite,
+			alternate_command: args.alternate_command,
+			pre_hook: args.pre_hook,
+			post_hook: args.post_hook,
+			intercept_hook: args.intercept_hook,
+			..Default::default()
+		}
+	}
+}
+
+/// Output format for inspection commands.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+	/// Human-readable text
+	Text,
+	/// JSON for tooling
+	Json,
+	/// TOML for tooling
+	Toml,
+}
+
+/// Stable schema for `tramp config show --format json|toml`.
+///
+/// Bump `schema_version` when making incompatible changes to this layout.
+#[derive(Serialize)]
+struct ConfigShowReport<'a> {
+	schema_version: u32,
+	discovery: &'a ConfigDiscovery,
+	effective: &'a MergedConfig,
 }
 
 #[derive(Subcommand)]
 enum ConfigAction {
 	/// Display merged effective configuration with source annotations
-	Show,
+	Show {
+		/// Output format
+		#[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+		format: OutputFormat,
+	},
 	/// Check all config files for errors without running anything
-	Validate,
+	///
+	/// Exits 1 if any error is found. With --strict, exits 2 if only warnings are found.
+	Validate {
+		/// Output format
+		#[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+		format: OutputFormat,
+
+		/// Treat warnings (shadowed rules, rules without action) as failures
+		#[arg(long)]
+		strict: bool,
+	},
+	/// Set a top-level key in a config file, e.g. `tramp config set max-depth 4`
+	Set {
+		#[command(flatten)]
+		target: TargetFile,
+
+		/// The key to set
+		key: String,
+
+		/// The value, as TOML (`true`, `4`, `"CI"`); bare words are taken as strings
+		value: String,
+	},
+	/// Remove a top-level key from a config file
+	Unset {
+		#[command(flatten)]
+		target: TargetFile,
+
+		/// The key to remove
+		key: String,
+	},
+	/// Rewrite config files in the current format version, keeping comments
+	///
+	/// Migrates every config in the cascade unless --file or --user is given.
+	Migrate {
+		/// Migrate only this config file
+		#[arg(long, value_name = "PATH", conflicts_with = "user")]
+		file: Option<PathBuf>,
+
+		/// Migrate only the user config
+		#[arg(long)]
+		user: bool,
+
+		/// Report what would change without writing; exits 1 if any file needs migrating
+		#[arg(long)]
+		check: bool,
+	},
+	/// Print the JSON Schema for .tramp.toml, for editor completion and validation
+	Schema,
 }
 
 fn main() -> ExitCode {
@@ -70,7 +492,32 @@ fn main() -> ExitCode {
 }
 
 fn run() -> Result<ExitCode> {
-	let cli = Cli::parse();
+	// Called through a symlink named after a tool, e.g. `cargo`: wrap that tool
+	if let Some(tool) = std::env::args_os()
+		.next()
+		.and_then(|argv0| invoked_as(&argv0))
+	{
+		let args = std::iter::once(Ok(tool))
+			.chain(std::env::args_os().skip(1).map(|arg| {
+				arg.into_string().map_err(|arg| {
+					anyhow::anyhow!("Argument is not valid UTF-8: {}", arg.to_string_lossy())
+				})
+			}))
+			.collect::<Result<Vec<_>>>()?;
+		return handle_command(&args, &RunOptions::default());
+	}
+
+	let cli = match Cli::try_parse() {
+		Ok(cli) => cli,
+		Err(e) => {
+			let hint = run_hint(&e);
+			let _ = e.print();
+			if let Some(hint) = hint {
+				eprintln!("\n{}", hint);
+			}
+			std::process::exit(e.exit_code());
+		}
+	};
 
 	// Handle --setup
 	if let Some(binary_path) = cli.setup {
@@ -79,141 +526,1118 @@ fn run() -> Result<ExitCode> {
 
 	// Handle --init
 	if cli.init {
-		return handle_init(cli.force);
+		return handle_init(DEFAULT_PRESET, InitTarget::Repo, cli.force);
 	}
 
 	// Handle subcommands
 	if let Some(command) = cli.command {
 		return match command {
 			Commands::Config { action } => match action {
-				ConfigAction::Show => handle_config_show(),
-				ConfigAction::Validate => handle_config_validate(),
+				ConfigAction::Show { format } => handle_config_show(format),
+				ConfigAction::Validate { format, strict } => handle_config_validate(format, strict),
+				ConfigAction::Set { target, key, value } => {
+					handle_config_set(&target, &key, &value)
+				}
+				ConfigAction::Unset { target, key } => handle_config_unset(&target, &key),
+				ConfigAction::Migrate { file, user, check } => {
+					handle_config_migrate(file, user, check)
+				}
+				ConfigAction::Schema => {
+					print!("{}", config_schema_json());
+					Ok(ExitCode::SUCCESS)
+				}
 			},
+			Commands::Init {
+				preset,
+				user,
+				local,
+				gitignore,
+				no_gitignore,
+				force,
+			} => {
+				let target = if user {
+					InitTarget::User
+				} else if local {
+					InitTarget::Local {
+						gitignore: (gitignore || no_gitignore).then_some(gitignore),
+					}
+				} else {
+					InitTarget::Repo
+				};
+				handle_init(&preset, target, force)
+			}
+			Commands::Disable { rule, duration } => handle_disable(&rule, duration),
+			Commands::Enable { rule } => handle_enable(&rule),
+			Commands::Rule { action } => handle_rule(action),
+			Commands::Explain { format, command } => handle_explain(&command, format),
+			Commands::Run { options, args } => handle_command(&args, &options),
+			Commands::Shim { action } => handle_shim(action),
+			Commands::Test { format, filter } => handle_test(filter.as_deref(), format),
 		};
 	}
 
 	// Handle command execution
 	if !cli.args.is_empty() {
-		return handle_command(&cli.args);
+		return handle_command(&cli.args, &cli.run);
 	}
 
 	// No command specified - this shouldn't happen due to arg_required_else_help
 	Ok(ExitCode::SUCCESS)
 }
 
+/// Point at `tramp run` when a subcommand is given arguments it doesn't know
+/// and a real command of the same name exists, e.g. `tramp test -f file`
+/// meant for `/usr/bin/test`.
+fn run_hint(error: &clap::Error) -> Option<String> {
+	if error.kind() != ErrorKind::UnknownArgument {
+		return None;
+	}
+
+	let args: Vec<String> = std::env::args().skip(1).collect();
+	let position = args.iter().position(|arg| !arg.starts_with('-'))?;
+	let name = &args[position];
+	Cli::command().find_subcommand(name)?;
+	let binary = resolve_command(name)?;
+
+	let flags: String = args[..position]
+		.iter()
+		.map(|flag| format!("{} ", flag))
+		.collect();
+	Some(format!(
+		"tip: to run {} instead of `tramp {}`, use `tramp run {}-- {}`",
+		binary.display(),
+		name,
+		flags,
+		args[position..].join(" ")
+	))
+}
+
 fn handle_setup(binary_path: &Path) -> Result<ExitCode> {
 	let script = generate_trampoline_script(binary_path, None);
 	println!("{}", script);
 	Ok(ExitCode::SUCCESS)
 }
 
-fn handle_init(force: bool) -> Result<ExitCode> {
-	let config_path = PathBuf::from(".tramp.toml");
+/// Which config file `tramp init` creates.
+enum InitTarget {
+	/// `.tramp.toml` in the current directory.
+	Repo,
+
+	/// `.tramp.local.toml` in the current directory. `gitignore` says whether
+	/// to add it to `.gitignore`; `None` means ask.
+	Local { gitignore: Option<bool> },
+
+	/// The user config.
+	User,
+}
+
+fn handle_init(preset_name: &str, target: InitTarget, force: bool) -> Result<ExitCode> {
+	let preset = find_preset(preset_name)
+		.ok_or_else(|| anyhow::anyhow!("Unknown preset: {}", preset_name))?;
+
+	let config_path = match target {
+		InitTarget::Repo => PathBuf::from(CONFIG_FILE_NAME),
+		InitTarget::Local { .. } => PathBuf::from(LOCAL_CONFIG_FILE_NAME),
+		InitTarget::User => user_config_path()?,
+	};
+	let content = match target {
+		InitTarget::Local { .. } if preset.name == DEFAULT_PRESET => generate_local_template(),
+		_ => preset.content,
+	};
 
 	if config_path.exists() && !force {
-		anyhow::bail!(".tramp.toml already exists. Use --force to overwrite.");
+		anyhow::bail!(
+			"{} already exists. Use --force to overwrite.",
+			config_path.display()
+		);
 	}
 
-	let template = generate_init_template();
-	std::fs::write(&config_path, template)
+	if let Some(parent) = config_path.parent()
+		&& !parent.as_os_str().is_empty()
+	{
+		std::fs::create_dir_all(parent)
+			.with_context(|| format!("Failed to create {}", parent.display()))?;
+	}
+	std::fs::write(&config_path, content)
 		.with_context(|| format!("Failed to write {}", config_path.display()))?;
 
-	println!("Created .tramp.toml");
+	if preset.name == DEFAULT_PRESET {
+		println!("Created {}", config_path.display());
+	} else {
+		println!(
+			"Created {} from the {} preset",
+			config_path.display(),
+			preset.name
+		);
+	}
+
+	if let InitTarget::Local { gitignore } = target {
+		let cwd = std::env::current_dir().context("Failed to get current directory")?;
+		if find_config(&cwd).is_none() {
+			println!(
+				"note: {} is only read next to a repository config (.tramp.toml, or one in Cargo.toml or package.json)",
+				LOCAL_CONFIG_FILE_NAME
+			);
+		}
+		offer_gitignore(gitignore)?;
+	}
 	Ok(ExitCode::SUCCESS)
 }
 
-fn handle_config_show() -> Result<ExitCode> {
+/// Add `.tramp.local.toml` to `.gitignore` if the user agrees.
+///
+/// Asks when `decision` is `None` and stdin is a terminal; otherwise only
+/// prints a reminder.
+fn offer_gitignore(decision: Option<bool>) -> Result<()> {
+	let gitignore = Path::new(".gitignore");
+	let existing = std::fs::read_to_string(gitignore).unwrap_or_default();
+	let ignored = existing
+		.lines()
+		.any(|line| line.trim().trim_start_matches('/') == LOCAL_CONFIG_FILE_NAME);
+	if ignored {
+		return Ok(());
+	}
+
+	let add = match decision {
+		Some(add) => add,
+		None if std::io::stdin().is_terminal() => {
+			print!("Add {} to .gitignore? [Y/n] ", LOCAL_CONFIG_FILE_NAME);
+			std::io::stdout().flush()?;
+			let mut answer = String::new();
+			std::io::stdin().read_line(&mut answer)?;
+			!matches!(answer.trim().to_lowercase().as_str(), "n" | "no")
+		}
+		None => {
+			println!(
+				"Remember to keep {} out of version control, e.g. with --gitignore",
+				LOCAL_CONFIG_FILE_NAME
+			);
+			return Ok(());
+		}
+	};
+
+	if add {
+		let separator = if existing.is_empty() || existing.ends_with('\n') {
+			""
+		} else {
+			"\n"
+		};
+		let mut file = std::fs::OpenOptions::new()
+			.create(true)
+			.append(true)
+			.open(gitignore)
+			.context("Failed to open .gitignore")?;
+		writeln!(file, "{}{}", separator, LOCAL_CONFIG_FILE_NAME)
+			.context("Failed to write .gitignore")?;
+		println!("Added {} to .gitignore", LOCAL_CONFIG_FILE_NAME);
+	}
+	Ok(())
+}
+
+fn handle_config_show(format: OutputFormat) -> Result<ExitCode> {
 	let cwd = std::env::current_dir().context("Failed to get current directory")?;
-	let configs =
+	let discovery =
 		tramp_cli::config::discover_configs(&cwd).context("Failed to discover config files")?;
 
-	if configs.is_empty() {
+	if format != OutputFormat::Text {
+		let effective = merge_configs(&discovery.configs);
+		let report = ConfigShowReport {
+			schema_version: 1,
+			discovery: &discovery,
+			effective: &effective,
+		};
+		print_structured(&report, format)?;
+		return Ok(ExitCode::SUCCESS);
+	}
+
+	print_discovery_tree(&discovery);
+	println!();
+
+	if discovery.configs.is_empty() {
 		println!("No configuration files found.");
 		return Ok(ExitCode::SUCCESS);
 	}
 
 	println!("Configuration files (in cascade order):\n");
+	let muted = MutedRules::load();
 
-	for loaded in &configs {
+	for loaded in &discovery.configs {
 		println!("# Source: {}", loaded.path.display());
 		println!("# root: {}", loaded.config.root);
 		println!("# no-external-lookup: {}", loaded.config.no_external_lookup);
 		if let Some(ref env_var) = loaded.config.root_config_lookup_disable_env_var {
 			println!("# root-config-lookup-disable-env-var: {}", env_var);
 		}
+		if loaded.config.no_env_rules {
+			println!("# no-env-rules: true");
+		}
+		if let Some(max_depth) = loaded.config.max_depth {
+			println!("# max-depth: {}", max_depth);
+		}
+		if !loaded.config.templates.is_empty() {
+			let names: Vec<&str> = loaded.config.templates.keys().map(String::as_str).collect();
+			println!("# templates: {}", names.join(", "));
+		}
+		if !loaded.config.vars.is_empty() {
+			let names: Vec<&str> = loaded.config.vars.keys().map(String::as_str).collect();
+			println!("# vars: {}", names.join(", "));
+		}
+		for outcome in &loaded.config.conditions {
+			println!(
+				"# when '{}': {}",
+				outcome.condition,
+				if outcome.applied {
+					"applied"
+				} else {
+					"not applied"
+				}
+			);
+		}
 		println!("# rules: {}", loaded.config.rules.len());
+		if !loaded.config.groups.is_empty() {
+			println!("# groups: {}", loaded.config.groups.len());
+		}
 		println!();
 
 		for (i, rule) in loaded.config.rules.iter().enumerate() {
 			println!("  Rule {}:", i + 1);
-			if let Some(ref pattern) = rule.binary_pattern {
-				println!("    binary_pattern: {}", pattern);
+			print_rule_fields(rule, "    ", &muted);
+			println!();
+		}
+
+		for (i, group) in loaded.config.groups.iter().enumerate() {
+			match group.id {
+				Some(ref id) => println!("  Group {} ({}):", i + 1, id),
+				None => println!("  Group {}:", i + 1),
 			}
-			if let Some(ref pattern) = rule.cwd_pattern {
-				println!("    cwd_pattern: {}", pattern);
+			print_rule_fields(&group.matchers(), "    ", &MutedRules::default());
+			println!();
+
+			for (j, rule) in group.rules.iter().enumerate() {
+				println!("    Rule {}:", j + 1);
+				print_rule_fields(rule, "      ", &muted);
+				println!();
 			}
-			if let Some(ref rewrite) = rule.arg_rewrite {
-				println!("    arg_rewrite: {}", rewrite);
+		}
+	}
+
+	Ok(ExitCode::SUCCESS)
+}
+
+/// Print the fields a rule sets, one per line, noting whether it is muted.
+fn print_rule_fields(rule: &Rule, indent: &str, muted: &MutedRules) {
+	if let Some(ref id) = rule.id {
+		println!("{}id: {}", indent, id);
+	}
+	if !rule.is_enabled() {
+		println!("{}enabled: false (skipped)", indent);
+	}
+	let now = jiff::Zoned::now();
+	if let Some(ref id) = rule.id
+		&& let Some(mute) = muted.active(id, now.timestamp())
+	{
+		println!(
+			"{}muted: {} on this machine (skipped; `tramp enable {}` unmutes)",
+			indent,
+			describe_mute_end(mute.until),
+			id
+		);
+	}
+	if let Some(expires) = rule.expires {
+		let note = if rule.is_expired(now.date()) {
+			" (expired, skipped)"
+		} else {
+			""
+		};
+		println!("{}expires: {}{}", indent, expires, note);
+	}
+	if let Some(window) = rule.active_between {
+		let note = if rule.is_active_at(now.time()) {
+			""
+		} else {
+			" (inactive now)"
+		};
+		println!("{}active_between: {}{}", indent, window, note);
+	}
+	if !rule.uses.is_empty() {
+		println!(
+			"{}use: {} (fields below include them)",
+			indent,
+			rule.uses.join(", ")
+		);
+	}
+	for (field, pattern) in rule.matchers() {
+		println!("{}{}: {}", indent, field, pattern);
+	}
+	if let Some(depth) = rule.ancestor_depth {
+		println!("{}ancestor_depth: {}", indent, depth);
+	}
+	if let Some(ref rewrite) = rule.arg_rewrite {
+		println!("{}arg_rewrite: {}", indent, rewrite);
+	}
+	if let Some(ref rewrite) = rule.command_rewrite {
+		println!("{}command_rewrite: {}", indent, rewrite);
+	}
+	if let Some(ref cmd) = rule.alternate_command {
+		println!("{}alternate_command: {}", indent, cmd);
+	}
+	for (field, hook) in [
+		("pre_hook", &rule.pre_hook),
+		("post_hook", &rule.post_hook),
+		("intercept_hook", &rule.intercept_hook),
+	] {
+		if let Some(hook) = hook {
+			println!("{}{}: {}", indent, field, hook.display());
+		}
+	}
+}
+
+/// Print a rule left out of the merged config, and why.
+fn print_skipped_rule(skipped: &SkippedRule, indent: &str) {
+	let rule = &skipped.rule;
+	println!(
+		"{}{} {}{}: {}",
+		indent,
+		rule.source.display(),
+		rule_position(rule.rule.group.as_ref(), rule.source_index),
+		rule.rule
+			.id
+			.as_ref()
+			.map_or(String::new(), |id| format!(" ({})", id)),
+		skipped.skipped
+	);
+}
+
+/// Print the config discovery walk as a tree, explaining skipped layers.
+fn print_discovery_tree(discovery: &ConfigDiscovery) {
+	// Each entry is a line plus an optional note rendered beneath it
+	let mut entries: Vec<(String, Option<String>)> = Vec::new();
+
+	let env_entry = match discovery.env_rules {
+		EnvRulesDecision::Loaded => {
+			Some(format!("{} (loaded: ${})", ENV_RULES_SOURCE, RULES_ENV_VAR))
+		}
+		EnvRulesDecision::NotSet => None,
+		EnvRulesDecision::DisabledByUserConfig { ref path } => Some(format!(
+			"{} skipped: no-env-rules = true in {}",
+			ENV_RULES_SOURCE,
+			path.display()
+		)),
+		EnvRulesDecision::SkippedByNoExternalLookup { ref set_by } => Some(format!(
+			"{} skipped: no-external-lookup = true in {}",
+			ENV_RULES_SOURCE,
+			set_by.display()
+		)),
+	};
+	if let Some(entry) = env_entry {
+		entries.push((entry, None));
+	}
+
+	for location in &discovery.searched {
+		let status = if location.found {
+			"loaded".to_string()
+		} else if let Some(ref set_by) = location.ignored_by {
+			format!("skipped: no-external-lookup = true in {}", set_by.display())
+		} else {
+			"not found".to_string()
+		};
+		let note = match discovery.cascade_end {
+			CascadeEnd::Root { ref path } if *path == location.path => {
+				Some("root = true: stopped the directory walk".to_string())
 			}
-			if let Some(ref rewrite) = rule.command_rewrite {
-				println!("    command_rewrite: {}", rewrite);
+			CascadeEnd::NoExternalLookup { ref path } if *path == location.path => Some(
+				"no-external-lookup = true: ended discovery, no other configs apply".to_string(),
+			),
+			_ => None,
+		};
+		entries.push((format!("{} ({})", location.path.display(), status), note));
+	}
+
+	if discovery.cascade_end == CascadeEnd::FilesystemRoot {
+		entries.push(("reached the filesystem root".to_string(), None));
+	}
+
+	let user_entry = match discovery.user_config {
+		UserConfigDecision::Loaded { ref path } => {
+			format!("{} (user config: loaded)", path.display())
+		}
+		UserConfigDecision::NotFound { ref path } => {
+			format!("{} (user config: not found)", path.display())
+		}
+		UserConfigDecision::DisabledByEnvVar {
+			ref env_var,
+			ref set_by,
+		} => format!(
+			"user config skipped: ${} is truthy (root-config-lookup-disable-env-var in {})",
+			env_var,
+			set_by.display()
+		),
+		UserConfigDecision::SkippedByNoExternalLookup { ref set_by } => format!(
+			"user config skipped: no-external-lookup = true in {}",
+			set_by.display()
+		),
+	};
+	entries.push((user_entry, None));
+
+	println!("Config discovery from {}:", discovery.start_dir.display());
+	for (i, (line, note)) in entries.iter().enumerate() {
+		let last = i + 1 == entries.len();
+		println!("{} {}", if last { "└──" } else { "├──" }, line);
+		if let Some(note) = note {
+			println!("{}   └── {}", if last { " " } else { "│" }, note);
+		}
+	}
+}
+
+fn handle_config_set(target: &TargetFile, key: &str, value: &str) -> Result<ExitCode> {
+	let mut editor = target.open()?;
+	editor.set_key(key, value)?;
+	save_edit(&editor)?;
+
+	println!("Set {} in {}", key, editor.path().display());
+	Ok(ExitCode::SUCCESS)
+}
+
+fn handle_config_unset(target: &TargetFile, key: &str) -> Result<ExitCode> {
+	let mut editor = target.open()?;
+	if !editor.unset_key(key) {
+		anyhow::bail!("{} is not set in {}", key, editor.path().display());
+	}
+	save_edit(&editor)?;
+
+	println!("Removed {} from {}", key, editor.path().display());
+	Ok(ExitCode::SUCCESS)
+}
+
+fn handle_config_migrate(file: Option<PathBuf>, user: bool, check: bool) -> Result<ExitCode> {
+	let whole_cascade = file.is_none() && !user;
+	let paths = if let Some(file) = file {
+		vec![file]
+	} else if user {
+		vec![user_config_path()?]
+	} else {
+		let cwd = std::env::current_dir().context("Failed to get current directory")?;
+		discover_configs(&cwd)
+			.context("Failed to load configuration")?
+			.configs
+			.into_iter()
+			.map(|loaded| loaded.path)
+			.collect()
+	};
+
+	let mut pending = 0;
+	for path in &paths {
+		if whole_cascade && is_env_rules(path) {
+			continue;
+		}
+		if whole_cascade && is_embedded(path) {
+			println!(
+				"{} is embedded in a manifest; migrate it by hand",
+				path.display()
+			);
+			continue;
+		}
+
+		let report = migrate_file(path, check)
+			.with_context(|| format!("Failed to migrate {}", path.display()))?;
+
+		if !report.changed() {
+			println!("{} is up to date (version {})", path.display(), report.to);
+			continue;
+		}
+
+		pending += 1;
+		println!(
+			"{} {} from version {} to {}:",
+			if check { "Would migrate" } else { "Migrated" },
+			path.display(),
+			report.from,
+			report.to
+		);
+		for change in &report.changes {
+			println!("  {}", change);
+		}
+	}
+
+	if check && pending > 0 {
+		return Ok(ExitCode::FAILURE);
+	}
+	Ok(ExitCode::SUCCESS)
+}
+
+fn handle_rule(action: RuleAction) -> Result<ExitCode> {
+	match action {
+		RuleAction::Add {
+			target,
+			position,
+			rule,
+		} => {
+			let mut editor = target.open()?;
+			let rule = Rule::from(*rule);
+			let index = editor.add_rule(&rule, position.map(|p| p.saturating_sub(1)))?;
+			save_edit(&editor)?;
+
+			println!(
+				"Added rule {}{} to {}",
+				index + 1,
+				rule.id.map_or(String::new(), |id| format!(" ({})", id)),
+				editor.path().display()
+			);
+		}
+		RuleAction::Remove { target, rule } => {
+			let mut editor = target.open()?;
+			let index = editor.find_rule(&rule)?;
+			editor.remove_rule(index)?;
+			save_edit(&editor)?;
+
+			println!("Removed rule {} from {}", rule, editor.path().display());
+		}
+		RuleAction::Enable { target, rule } => set_rule_enabled(&target, &rule, true)?,
+		RuleAction::Disable { target, rule } => set_rule_enabled(&target, &rule, false)?,
+		RuleAction::Prune { target } => {
+			let mut editor = target.open()?;
+			let pruned = editor.prune_expired(local_now().date())?;
+			if pruned.is_empty() {
+				println!("No expired rules in {}", editor.path().display());
+				return Ok(ExitCode::SUCCESS);
+			}
+			save_edit(&editor)?;
+
+			for rule in &pruned {
+				let position = rule
+					.group
+					.as_ref()
+					.map_or(String::new(), |group| format!("{}, ", group));
+				let id = rule
+					.id
+					.as_ref()
+					.map_or(String::new(), |id| format!(" {}", id));
+				let expires = rule.expires.map_or(String::new(), |e| e.to_string());
+				println!("Removed {}rule{} (expired on {})", position, id, expires);
+			}
+			println!(
+				"Pruned {} expired rule(s) from {}",
+				pruned.len(),
+				editor.path().display()
+			);
+		}
+	}
+
+	Ok(ExitCode::SUCCESS)
+}
+
+/// Write an edited config, refusing edits that would leave it invalid.
+fn save_edit(editor: &ConfigEditor) -> Result<()> {
+	editor.validate().with_context(|| {
+		format!(
+			"Refusing to edit {}: the result would be invalid, so nothing was written",
+			editor.path().display()
+		)
+	})?;
+	editor.save()?;
+	Ok(())
+}
+
+fn set_rule_enabled(target: &TargetFile, rule: &str, enabled: bool) -> Result<()> {
+	let mut editor = target.open()?;
+	let index = editor.find_rule(rule)?;
+	editor.set_rule_enabled(index, enabled)?;
+	save_edit(&editor)?;
+
+	println!(
+		"{} rule {} in {}",
+		if enabled { "Enabled" } else { "Disabled" },
+		rule,
+		editor.path().display()
+	);
+	Ok(())
+}
+
+fn handle_disable(id: &str, duration: Option<jiff::Span>) -> Result<ExitCode> {
+	let until = match duration {
+		Some(span) if !span.is_positive() => anyhow::bail!("--for must be a positive duration"),
+		Some(span) => {
+			let end = jiff::Zoned::now()
+				.checked_add(span)
+				.context("--for is too long")?;
+			Some(end.timestamp())
+		}
+		None => None,
+	};
+
+	let path = muted_rules_path().context("Failed to locate the user data directory")?;
+	let mut muted = MutedRules::read(&path)?;
+	muted.prune(jiff::Timestamp::now());
+	muted.mute(id, until);
+	muted.write(&path)?;
+
+	let cwd = std::env::current_dir().context("Failed to get current directory")?;
+	let known = discover_configs(&cwd).is_ok_and(|discovery| {
+		discovery.configs.iter().any(|loaded| {
+			loaded
+				.config
+				.all_rules()
+				.any(|(_, _, rule)| rule.id.as_deref() == Some(id))
+		})
+	});
+	if !known {
+		eprintln!(
+			"Warning: no rule with id `{}` applies here; it is muted wherever it appears",
+			id
+		);
+	}
+
+	println!("Muted rule {} {}", id, describe_mute_end(until));
+	Ok(ExitCode::SUCCESS)
+}
+
+fn handle_enable(id: &str) -> Result<ExitCode> {
+	let path = muted_rules_path().context("Failed to locate the user data directory")?;
+	let mut muted = MutedRules::read(&path)?;
+	let now = jiff::Timestamp::now();
+	let was_muted = muted.active(id, now).is_some();
+
+	if muted.unmute(id) {
+		muted.prune(now);
+		muted.write(&path)?;
+	}
+	if was_muted {
+		println!("Unmuted rule {}", id);
+	} else {
+		println!("Rule {} is not muted", id);
+	}
+	Ok(ExitCode::SUCCESS)
+}
+
+fn handle_shim(action: ShimAction) -> Result<ExitCode> {
+	let dir = managed_shim_dir().context("Failed to locate the user data directory")?;
+	let path_dirs = path_dirs();
+
+	match action {
+		ShimAction::Install {
+			mut names,
+			from_config,
+			force,
+		} => {
+			if from_config {
+				let cwd = std::env::current_dir().context("Failed to get current directory")?;
+				let config = load_merged_config(&cwd).context("Failed to load configuration")?;
+				let rules = compile_rules(&config).context("Failed to compile rules")?;
+				let matched = binaries_matching_rules(&rules);
+				if matched.is_empty() {
+					println!("No command on PATH matches the binary_pattern of a rule");
+				}
+				for name in matched {
+					if !names.contains(&name) {
+						names.push(name);
+					}
+				}
 			}
-			if let Some(ref cmd) = rule.alternate_command {
-				println!("    alternate_command: {}", cmd);
+
+			for name in &names {
+				let target = resolve_command(name)
+					.with_context(|| format!("No `{}` on PATH to wrap", name))?;
+				if install_shim(&dir, name, force)? {
+					println!("Installed shim {} -> {}", name, target.display());
+				} else {
+					println!("Shim {} is already installed", name);
+				}
 			}
-			if let Some(ref hook) = rule.pre_hook {
-				println!("    pre_hook: {}", hook.display());
+
+			if !names.is_empty() && path_position(&dir, &path_dirs).is_none() {
+				println!(
+					"\n{} is not on PATH yet. Put it first, e.g. in your shell profile:",
+					dir.display()
+				);
+				println!("  export PATH=\"{}:$PATH\"", dir.display());
 			}
-			if let Some(ref hook) = rule.post_hook {
-				println!("    post_hook: {}", hook.display());
+		}
+		ShimAction::List => {
+			let shims = list_shims(&dir)?;
+			if shims.is_empty() {
+				println!("No shims in {}", dir.display());
+				return Ok(ExitCode::SUCCESS);
 			}
-			if let Some(ref hook) = rule.intercept_hook {
-				println!("    intercept_hook: {}", hook.display());
+
+			println!("Shims in {}:", dir.display());
+			for shim in &shims {
+				if !shim.is_tramp_link() {
+					println!("  {} ({})", shim.name, ShimProblem::NotALink);
+					continue;
+				}
+				let stale = shim
+					.problems(&path_dirs)
+					.into_iter()
+					.find(ShimProblem::is_stale);
+				match (stale, shim.target()) {
+					(Some(problem), _) => println!("  {} (stale: {})", shim.name, problem),
+					(None, Some(target)) => println!("  {} -> {}", shim.name, target.display()),
+					(None, None) => println!("  {}", shim.name),
+				}
 			}
-			println!();
 		}
+		ShimAction::Remove { mut names, stale } => {
+			if stale {
+				// Only shims tramp installed are stale; other files are left alone
+				for shim in list_shims(&dir)? {
+					if shim.is_tramp_link()
+						&& shim.problems(&path_dirs).iter().any(ShimProblem::is_stale)
+						&& !names.contains(&shim.name)
+					{
+						names.push(shim.name);
+					}
+				}
+				if names.is_empty() {
+					println!("No stale shims in {}", dir.display());
+				}
+			}
+
+			let mut failed = false;
+			for name in &names {
+				match remove_shim(&dir, name) {
+					Ok(()) => println!("Removed shim {}", name),
+					Err(e) => {
+						eprintln!("error: {}", e);
+						failed = true;
+					}
+				}
+			}
+			if failed {
+				return Ok(ExitCode::FAILURE);
+			}
+		}
+		ShimAction::Doctor => {
+			let mut problems = 0;
+
+			match path_position(&dir, &path_dirs) {
+				Some(position) => println!(
+					"Shim directory: {} (PATH entry {})",
+					dir.display(),
+					position + 1
+				),
+				None => {
+					problems += 1;
+					println!("Shim directory: {}", dir.display());
+					println!(
+						"  problem: not on PATH; add it first: export PATH=\"{}:$PATH\"",
+						dir.display()
+					);
+				}
+			}
+
+			let shims = list_shims(&dir)?;
+			if shims.is_empty() {
+				println!("  no shims installed");
+			}
+			for shim in &shims {
+				let shim_problems = shim.problems(&path_dirs);
+				if shim_problems.is_empty() {
+					let target = shim.target().unwrap_or_default();
+					println!("  {} -> {}: ok", shim.name, target.display());
+				}
+				for problem in &shim_problems {
+					println!("  {}: problem: {}", shim.name, problem);
+				}
+				problems += shim_problems.len();
+			}
+
+			if problems > 0 {
+				println!("\nFound {} problem(s)", problems);
+				return Ok(ExitCode::FAILURE);
+			}
+			println!("\nNo problems found");
+		}
+	}
+
+	Ok(ExitCode::SUCCESS)
+}
+
+/// Format a command and its arguments for display.
+fn command_line(binary: &Path, args: &[String]) -> String {
+	std::iter::once(binary.display().to_string())
+		.chain(args.iter().cloned())
+		.collect::<Vec<_>>()
+		.join(" ")
+}
+
+/// Exit code for `config validate --strict` when only warnings were found.
+const EXIT_WARNINGS: u8 = 2;
+
+fn handle_config_validate(format: OutputFormat, strict: bool) -> Result<ExitCode> {
+	let cwd = std::env::current_dir().context("Failed to get current directory")?;
+	let report = validate_cascade(&cwd).context("Failed to validate config files")?;
+
+	let warnings = report.count(Severity::Warning);
+	let code = if report.has_errors() {
+		ExitCode::FAILURE
+	} else if strict && warnings > 0 {
+		ExitCode::from(EXIT_WARNINGS)
+	} else {
+		ExitCode::SUCCESS
+	};
+
+	if format != OutputFormat::Text {
+		print_structured(&report, format)?;
+		return Ok(code);
+	}
+
+	for diagnostic in &report.diagnostics {
+		eprintln!("{}", format_diagnostic(diagnostic));
 	}
 
-	// Show user config path
-	if let Ok(user_path) = user_config_path() {
-		println!("User config path: {}", user_path.display());
-		if user_path.exists() {
-			println!("  (exists)");
+	if report.configs.is_empty() {
+		println!("No configuration files found.");
+	} else if !report.has_errors() {
+		if warnings > 0 {
+			println!(
+				"All configuration files are valid ({} warning(s)):",
+				warnings
+			);
 		} else {
-			println!("  (not found)");
+			println!("All configuration files are valid:");
+		}
+		for checked in &report.configs {
+			println!("  {} ({} rules)", checked.path.display(), checked.rules);
+		}
+	}
+
+	Ok(code)
+}
+
+/// Format a diagnostic as a single line: `error: <file>:<line>:<column>: rule N: <field>: <message>`.
+///
+/// For a rule in a group, `rule N` reads `group X, rule N`.
+fn format_diagnostic(diagnostic: &Diagnostic) -> String {
+	let mut line = format!(
+		"{}: {}",
+		diagnostic.severity.as_str(),
+		diagnostic.file.display()
+	);
+	if let (Some(number), Some(column)) = (diagnostic.line, diagnostic.column) {
+		line.push_str(&format!(":{}:{}", number, column));
+	}
+	match (&diagnostic.group, diagnostic.rule_index) {
+		(group, Some(index)) => {
+			line.push_str(&format!(": {}", rule_position(group.as_ref(), index)));
 		}
+		(Some(group), None) => line.push_str(&format!(": {}", group)),
+		(None, None) => {}
+	}
+	if let Some(ref field) = diagnostic.field {
+		line.push_str(&format!(": {}", field));
+	}
+	line.push_str(&format!(": {}", diagnostic.message));
+	line
+}
+
+/// Print a value as JSON or TOML.
+fn print_structured<T: Serialize>(value: &T, format: OutputFormat) -> Result<()> {
+	match format {
+		OutputFormat::Json => println!("{}", serde_json::to_string_pretty(value)?),
+		OutputFormat::Toml => print!("{}", toml::to_string(value)?),
+		OutputFormat::Text => unreachable!("text output is rendered by each command"),
+	}
+	Ok(())
+}
+
+fn handle_explain(args: &[String], format: OutputFormat) -> Result<ExitCode> {
+	let command_name = &args[0];
+	let command_args: Vec<String> = args[1..].to_vec();
+
+	let cwd = std::env::current_dir().context("Failed to get current directory")?;
+
+	let binary_path = resolve_command(command_name)
+		.ok_or_else(|| anyhow::anyhow!("Command not found: {}", command_name))?;
+
+	let configs = tramp_cli::config::discover_configs(&cwd)
+		.context("Failed to discover config files")?
+		.configs;
+	let merged = merge_configs(&configs);
+	let explanation = explain_command(
+		&configs,
+		merged,
+		&binary_path,
+		&command_args,
+		&cwd,
+		process_ancestry,
+	)
+	.with_context(|| format!("Failed to explain {}", command_name))?;
+
+	match format {
+		OutputFormat::Text => print_explanation(&explanation),
+		_ => print_structured(&explanation, format)?,
 	}
 
 	Ok(ExitCode::SUCCESS)
 }
 
-fn handle_config_validate() -> Result<ExitCode> {
+fn print_explanation(explanation: &Explanation) {
+	println!(
+		"Command: {} {}",
+		explanation.binary.display(),
+		explanation.args.join(" ")
+	);
+	println!("Working directory: {}", explanation.cwd.display());
+	println!();
+
+	if explanation.configs.is_empty() {
+		println!("No configuration files found.");
+	} else {
+		println!("Configuration files (in cascade order):");
+		for path in &explanation.configs {
+			println!("  {}", path.display());
+		}
+	}
+	println!();
+
+	println!("Rules:");
+	if explanation.rules.is_empty() {
+		println!("  (none)");
+	}
+	for trace in &explanation.rules {
+		let status = match explanation.chosen_rule {
+			Some(chosen) if chosen == trace.index => "CHOSEN",
+			Some(chosen) if trace.matched && chosen < trace.index => "matched (shadowed)",
+			_ if trace.matched => "matched",
+			_ => "no match",
+		};
+		let id = trace
+			.id
+			.as_ref()
+			.map_or(String::new(), |id| format!(" ({})", id));
+		println!(
+			"  [{}] {} {}{}: {}",
+			trace.index + 1,
+			trace.source.display(),
+			trace.position(),
+			id,
+			status
+		);
+		if trace.matchers.is_empty() {
+			println!("        (no matchers: matches every command)");
+		}
+		for outcome in &trace.matchers {
+			println!(
+				"        {} {}{} \"{}\" against \"{}\"",
+				if outcome.passed { "pass" } else { "FAIL" },
+				if outcome.from_group { "group " } else { "" },
+				outcome.matcher,
+				outcome.pattern,
+				outcome.subject
+			);
+		}
+	}
+	println!();
+
+	if !explanation.skipped.is_empty() {
+		println!("Skipped rules:");
+		for skipped in &explanation.skipped {
+			print_skipped_rule(skipped, "  ");
+		}
+		println!();
+	}
+
+	match explanation.chosen_rule {
+		Some(chosen) => {
+			let trace = &explanation.rules[chosen];
+			println!(
+				"Chosen rule: [{}] {} {}",
+				chosen + 1,
+				trace.source.display(),
+				trace.position()
+			);
+		}
+		None => println!("Chosen rule: none (command runs unchanged)"),
+	}
+
+	if let Some(ref error) = explanation.rewrite_error {
+		println!("Rewrite error: {}", error);
+	}
+	println!(
+		"Final command: {} {}",
+		explanation.final_binary.display(),
+		explanation.final_args.join(" ")
+	);
+	if !explanation.not_computed.is_empty() {
+		println!("{}", describe_not_computed(&explanation.not_computed));
+	}
+
+	if explanation.hooks.is_empty() {
+		println!("Hooks: none");
+	} else {
+		println!("Hooks:");
+		for hook in &explanation.hooks {
+			println!("  {}: {}", hook.hook_type.as_str(), hook.path.display());
+		}
+	}
+}
+
+/// Note the variables a preview left as `${vars.NAME}`.
+fn describe_not_computed(names: &[String]) -> String {
+	let references: Vec<_> = names
+		.iter()
+		.map(|name| format!("${{vars.{}}}", name))
+		.collect();
+	format!(
+		"Not computed: {} (only a real run executes `cmd` variables)",
+		references.join(", ")
+	)
+}
+
+fn handle_test(filter: Option<&str>, format: OutputFormat) -> Result<ExitCode> {
 	let cwd = std::env::current_dir().context("Failed to get current directory")?;
+	let report = run_config_tests(&cwd, filter).context("Failed to load configuration")?;
 
-	match tramp_cli::config::discover_configs(&cwd) {
-		Ok(configs) => {
-			if configs.is_empty() {
-				println!("No configuration files found.");
-			} else {
-				println!("All configuration files are valid:");
-				for loaded in &configs {
-					println!(
-						"  {} ({} rules)",
-						loaded.path.display(),
-						loaded.config.rules.len()
-					);
-				}
+	match format {
+		OutputFormat::Text => print_test_report(&report),
+		_ => print_structured(&report, format)?,
+	}
+
+	Ok(if report.failed() > 0 {
+		ExitCode::FAILURE
+	} else {
+		ExitCode::SUCCESS
+	})
+}
+
+/// Print test results in the style of `cargo test`.
+fn print_test_report(report: &TestReport) {
+	println!("running {} tests", report.results.len());
+	for result in &report.results {
+		println!(
+			"test {} ... {}",
+			result.name,
+			if result.passed() { "ok" } else { "FAILED" }
+		);
+	}
+	println!();
+
+	if report.failed() > 0 {
+		println!("failures:");
+		for result in report.results.iter().filter(|r| !r.passed()) {
+			println!();
+			println!(
+				"---- {} ({} test {}) ----",
+				result.name,
+				result.file.display(),
+				result.index + 1
+			);
+			for failure in &result.failures {
+				println!("  {}", failure);
 			}
-			Ok(ExitCode::SUCCESS)
-		}
-		Err(e) => {
-			eprintln!("Configuration error: {}", e);
-			Ok(ExitCode::FAILURE)
 		}
+		println!();
 	}
+
+	println!(
+		"test result: {}. {} passed; {} failed",
+		if report.failed() > 0 { "FAILED" } else { "ok" },
+		report.passed(),
+		report.failed()
+	);
 }
 
-fn handle_command(args: &[String]) -> Result<ExitCode> {
+fn handle_command(args: &[String], options: &RunOptions) -> Result<ExitCode> {
 	let command_name = &args[0];
 	let command_args: Vec<String> = args[1..].to_vec();
 
@@ -223,32 +1647,161 @@ fn handle_command(args: &[String]) -> Result<ExitCode> {
 	let binary_path = resolve_command(command_name)
 		.ok_or_else(|| anyhow::anyhow!("Command not found: {}", command_name))?;
 
-	// Load and merge config
-	let config = load_merged_config(&cwd).context("Failed to load configuration")?;
+	// Run the real command right away, without reading any config
+	if options.bypass || bypass_requested() {
+		if options.dry_run {
+			println!("Rule: none (bypassed)");
+			println!("Command: {}", command_line(&binary_path, &command_args));
+			return Ok(ExitCode::SUCCESS);
+		}
+		if options.verbose {
+			eprintln!(
+				"tramp: bypassed, running {}",
+				command_line(&binary_path, &command_args)
+			);
+		}
+		check_recursion_depth(&binary_path, DEFAULT_MAX_DEPTH)?;
+		let status = execute_command(&binary_path, &command_args, &cwd)
+			.with_context(|| format!("Failed to execute: {}", binary_path.display()))?;
+		return Ok(ExitCode::from(exit_code(&status)));
+	}
+	let skips = Skips::from_env()?;
+
+	// Load and merge config, leaving out rules skipped for this invocation
+	let mut config = match options.config {
+		Some(ref path) => load_single_config(&cwd.join(path)),
+		None => load_merged_config(&cwd),
+	}
+	.context("Failed to load configuration")?;
+	config.rules.retain(|rule| !skips.skips_rule(&rule.rule));
+
+	// Mention each expired rule once, so it gets cleaned up
+	for skipped in unwarned_expired_rules(&config.skipped) {
+		eprintln!(
+			"Warning: skipping {} in {}: {}; `tramp rule prune` deletes it",
+			rule_position(skipped.rule.rule.group.as_ref(), skipped.rule.source_index),
+			skipped.rule.source.display(),
+			skipped.skipped
+		);
+	}
+
+	// Refuse to recurse endlessly, e.g. when a trampoline resolves to itself
+	check_recursion_depth(&binary_path, config.max_depth.unwrap_or(DEFAULT_MAX_DEPTH))?;
 
 	// Compile rules
 	let rules = compile_rules(&config).context("Failed to compile rules")?;
 
+	// Inspect the invocation chain; the parent is always read so hooks can see it
+	let ancestry = process_ancestry(required_ancestry_depth(&rules).max(1));
+	let parent = ancestry.first();
+
 	// Create match context
 	let ctx = MatchContext {
 		binary_path: &binary_path,
 		cwd: &cwd,
 		args: &command_args,
+		ancestry: &ancestry,
 	};
 
 	// Find matching rule
 	let matched_rule = find_matching_rule(&rules, &ctx);
 
-	// Determine final binary and args
-	let (final_binary, final_args) = if let Some(rule) = matched_rule {
-		apply_rule(&binary_path, &command_args, &rule.rule)?
-	} else {
-		(binary_path.clone(), command_args.clone())
+	// Fill in the variables the rule uses, computing only those. A dry run
+	// doesn't run their commands either.
+	let mut not_computed = Vec::new();
+	let rule_fields = match matched_rule {
+		Some(rule) => {
+			let fields = if options.dry_run {
+				preview_rule(&rule.rule, &config.vars, &cwd).map(|(fields, uncomputed)| {
+					not_computed = uncomputed;
+					fields
+				})
+			} else {
+				interpolate_rule(&rule.rule, &config.vars, &cwd)
+			};
+			Some(
+				fields
+					.map_err(|e| rule.rule.locate_error(&rule.source, rule.source_index, e))
+					.with_context(|| {
+						format!("Failed to apply rule from {}", rule.source.display())
+					})?,
+			)
+		}
+		None => None,
 	};
 
+	// Determine final binary and args
+	let (final_binary, final_args) =
+		if let (Some(rule), Some(fields)) = (matched_rule, &rule_fields) {
+			apply_rule(&binary_path, &command_args, fields)
+				.map_err(|e| rule.rule.locate_error(&rule.source, rule.source_index, e))
+				.with_context(|| format!("Failed to apply rule from {}", rule.source.display()))?
+		} else {
+			(binary_path.clone(), command_args.clone())
+		};
+
+	if options.verbose || options.dry_run {
+		let rule = match matched_rule {
+			Some(rule) => format!(
+				"{} in {}{}",
+				rule.rule.position(rule.source_index),
+				rule.source.display(),
+				rule.rule
+					.id
+					.as_ref()
+					.map_or(String::new(), |id| format!(" ({})", id))
+			),
+			None => "none".to_string(),
+		};
+		let mut hooks: Vec<_> = rule_fields
+			.iter()
+			.flat_map(|fields| {
+				[
+					(HookType::Pre, &fields.pre_hook),
+					(HookType::Intercept, &fields.intercept_hook),
+					(HookType::Post, &fields.post_hook),
+				]
+			})
+			.filter(|(hook_type, _)| !skips.skips_hook(*hook_type))
+			.filter_map(|(hook_type, hook)| hook.as_ref().map(|hook| (hook_type, hook)))
+			.collect();
+		// An intercept hook replaces the command, so the post-hook never runs
+		if hooks
+			.iter()
+			.any(|(hook_type, _)| *hook_type == HookType::Intercept)
+		{
+			hooks.retain(|(hook_type, _)| *hook_type != HookType::Post);
+		}
+		let command = command_line(&final_binary, &final_args);
+
+		if options.dry_run {
+			println!("Rule: {}", rule);
+			println!("Command: {}", command);
+			if !not_computed.is_empty() {
+				println!("{}", describe_not_computed(&not_computed));
+			}
+			if hooks.is_empty() {
+				println!("Hooks: none");
+			} else {
+				println!("Hooks:");
+				for (hook_type, hook) in hooks {
+					println!("  {}: {}", hook_type.as_str(), hook.display());
+				}
+			}
+			return Ok(ExitCode::SUCCESS);
+		}
+		eprintln!("tramp: rule: {}", rule);
+		for (hook_type, hook) in hooks {
+			eprintln!("tramp: {} hook: {}", hook_type.as_str(), hook.display());
+		}
+		eprintln!("tramp: running {}", command);
+	}
+
 	// Execute pre-hook if present
-	if let Some(rule) = matched_rule {
-		if let Some(ref pre_hook) = rule.rule.pre_hook {
+	if let Some(ref rule) = rule_fields {
+		if let Some(ref pre_hook) = rule.pre_hook
+			&& !skips.skips_hook(HookType::Pre)
+		{
 			let hook_ctx = HookContext {
 				original_binary: &binary_path,
 				original_args: &command_args,
@@ -257,13 +1810,16 @@ fn handle_command(args: &[String]) -> Result<ExitCode> {
 				executed_binary: None,
 				executed_args: None,
 				exit_code: None,
+				parent,
 			};
 			execute_pre_hook(pre_hook, &hook_ctx)
 				.with_context(|| format!("Pre-hook failed: {}", pre_hook.display()))?;
 		}
 
 		// Check for intercept hook
-		if let Some(ref intercept_hook) = rule.rule.intercept_hook {
+		if let Some(ref intercept_hook) = rule.intercept_hook
+			&& !skips.skips_hook(HookType::Intercept)
+		{
 			let hook_ctx = HookContext {
 				original_binary: &binary_path,
 				original_args: &command_args,
@@ -272,6 +1828,7 @@ fn handle_command(args: &[String]) -> Result<ExitCode> {
 				executed_binary: Some(&final_binary),
 				executed_args: Some(&final_args),
 				exit_code: None,
+				parent,
 			};
 			let exit_code = execute_intercept_hook(intercept_hook, &hook_ctx)
 				.with_context(|| format!("Intercept hook failed: {}", intercept_hook.display()))?;
@@ -283,11 +1840,12 @@ fn handle_command(args: &[String]) -> Result<ExitCode> {
 	let status = execute_command(&final_binary, &final_args, &cwd)
 		.with_context(|| format!("Failed to execute: {}", final_binary.display()))?;
 
-	let exit_code = status.code().unwrap_or(1);
+	let code = exit_code(&status);
 
 	// Execute post-hook if present
-	if let Some(rule) = matched_rule
-		&& let Some(ref post_hook) = rule.rule.post_hook
+	if let Some(ref rule) = rule_fields
+		&& let Some(ref post_hook) = rule.post_hook
+		&& !skips.skips_hook(HookType::Post)
 	{
 		let hook_ctx = HookContext {
 			original_binary: &binary_path,
@@ -296,7 +1854,8 @@ fn handle_command(args: &[String]) -> Result<ExitCode> {
 			hook_type: HookType::Post,
 			executed_binary: Some(&final_binary),
 			executed_args: Some(&final_args),
-			exit_code: Some(exit_code),
+			exit_code: Some(code.into()),
+			parent,
 		};
 		// Post-hooks don't fail the command, just log if they error
 		if let Err(e) = execute_post_hook(post_hook, &hook_ctx) {
@@ -304,40 +1863,5 @@ fn handle_command(args: &[String]) -> Result<ExitCode> {
 		}
 	}
 
-	Ok(ExitCode::from(exit_code as u8))
-}
-
-fn apply_rule(
-	binary_path: &Path,
-	args: &[String],
-	rule: &tramp_cli::config::Rule,
-) -> Result<(PathBuf, Vec<String>)> {
-	// Check for alternate command
-	if let Some(ref alternate) = rule.alternate_command {
-		let alt_path = resolve_command(alternate)
-			.ok_or_else(|| anyhow::anyhow!("Alternate command not found: {}", alternate))?;
-		return Ok((alt_path, args.to_vec()));
-	}
-
-	// Check for arg_rewrite
-	if let Some(ref rewrite) = rule.arg_rewrite {
-		let sub = Substitution::parse(rewrite)
-			.with_context(|| format!("Invalid arg_rewrite pattern: {}", rewrite))?;
-		let new_args = rewrite_args(args, &sub);
-		return Ok((binary_path.to_path_buf(), new_args));
-	}
-
-	// Check for command_rewrite
-	if let Some(ref rewrite) = rule.command_rewrite {
-		let sub = Substitution::parse(rewrite)
-			.with_context(|| format!("Invalid command_rewrite pattern: {}", rewrite))?;
-		let binary_str = binary_path.to_string_lossy();
-		let (new_binary_str, new_args) = rewrite_command(&binary_str, args, &sub);
-		let new_binary = resolve_command(&new_binary_str)
-			.ok_or_else(|| anyhow::anyhow!("Rewritten command not found: {}", new_binary_str))?;
-		return Ok((new_binary, new_args));
-	}
-
-	// No rewrite, return as-is
-	Ok((binary_path.to_path_buf(), args.to_vec()))
+	Ok(ExitCode::from(code))
 }
@@ -0,0 +1,210 @@
+//! Process tree inspection for tramp.
+//!
+//! This module handles:
+//! - Reading information about the parent process and its ancestors
+//! - Matching process information against rule patterns
+//!
+//! Process details are read from `/proc` on Linux. On other platforms only the
+//! parent PID is available, so executable and command line are left empty.
+
+use regex::Regex;
+use std::path::PathBuf;
+
+/// Default number of ancestor levels walked by `ancestor_pattern` matchers.
+pub const DEFAULT_ANCESTOR_DEPTH: usize = 8;
+
+/// Information about a single process in the invocation chain.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcessInfo {
+	/// Process ID.
+	pub pid: u32,
+
+	/// Parent process ID, if known.
+	pub ppid: Option<u32>,
+
+	/// Resolved executable path, if readable.
+	pub exe: Option<PathBuf>,
+
+	/// Command line arguments (including argv[0]), if readable.
+	pub cmdline: Vec<String>,
+}
+
+impl ProcessInfo {
+	/// The command line joined with spaces, as exposed to hooks.
+	pub fn cmdline_string(&self) -> String {
+		self.cmdline.join(" ")
+	}
+
+	/// The short process name: the file name of the executable, or of argv[0].
+	pub fn name(&self) -> Option<String> {
+		self.exe
+			.as_deref()
+			.and_then(|exe| exe.file_name())
+			.map(|name| name.to_string_lossy().to_string())
+			.or_else(|| {
+				self.cmdline
+					.first()
+					.map(|arg0| arg0.rsplit('/').next().unwrap_or(arg0.as_str()).to_string())
+			})
+	}
+
+	/// Check if a pattern matches either the executable path or the command line.
+	pub fn matches(&self, regex: &Regex) -> bool {
+		if let Some(ref exe) = self.exe
+			&& regex.is_match(&exe.to_string_lossy())
+		{
+			return true;
+		}
+
+		!self.cmdline.is_empty() && regex.is_match(&self.cmdline_string())
+	}
+}
+
+/// Read information about the process with the given PID.
+#[cfg(target_os = "linux")]
+pub fn read_process(pid: u32) -> Option<ProcessInfo> {
+	let proc_dir = PathBuf::from(format!("/proc/{}", pid));
+
+	// The stat file is the only one we strictly need; without it the process is gone
+	let stat = std::fs::read_to_string(proc_dir.join("stat")).ok()?;
+	let ppid = parse_stat_ppid(&stat);
+
+	let exe = std::fs::read_link(proc_dir.join("exe")).ok();
+
+	let cmdline = std::fs::read(proc_dir.join("cmdline"))
+		.map(|bytes| parse_cmdline(&bytes))
+		.unwrap_or_default();
+
+	Some(ProcessInfo {
+		pid,
+		ppid,
+		exe,
+		cmdline,
+	})
+}
+
+/// Read information about the process with the given PID.
+#[cfg(not(target_os = "linux"))]
+pub fn read_process(_pid: u32) -> Option<ProcessInfo> {
+	None
+}
+
+/// Walk the process tree upwards from the current process's parent.
+///
+/// Returns at most `max_depth` entries, nearest ancestor first. The walk stops
+/// early at PID 1 or when a process can no longer be read.
+pub fn process_ancestry(max_depth: usize) -> Vec<ProcessInfo> {
+	let mut ancestry = Vec::new();
+	if max_depth == 0 {
+		return ancestry;
+	}
+
+	#[cfg(unix)]
+	let mut next_pid = Some(std::os::unix::process::parent_id());
+	#[cfg(not(unix))]
+	let mut next_pid: Option<u32> = None;
+
+	while let Some(pid) = next_pid {
+		if ancestry.len() >= max_depth || pid == 0 {
+			break;
+		}
+
+		match read_process(pid) {
+			Some(info) => {
+				next_pid = if pid == 1 { None } else { info.ppid };
+				ancestry.push(info);
+			}
+			None => {
+				// Still expose the PID when the platform can't tell us more
+				if ancestry.is_empty() {
+					ancestry.push(ProcessInfo {
+						pid,
+						..Default::default()
+					});
+				}
+				break;
+			}
+		}
+	}
+
+	ancestry
+}
+
+/// Extract the parent PID from the contents of `/proc/<pid>/stat`.
+///
+/// The command name (field 2) is wrapped in parentheses and may itself contain
+/// spaces or parentheses, so fields are counted from the last `)`.
+fn parse_stat_ppid(stat: &str) -> Option<u32> {
+	let after_comm = &stat[stat.rfind(')')? + 1..];
+	// Remaining fields: state, ppid, ...
+	after_comm.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Split the NUL-separated contents of `/proc/<pid>/cmdline`.
+fn parse_cmdline(bytes: &[u8]) -> Vec<String> {
+	bytes
+		.split(|b| *b == 0)
+		.filter(|part| !part.is_empty())
+		.map(|part| String::from_utf8_lossy(part).to_string())
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_parse_stat_ppid() {
+		let stat = "1234 (cargo) S 567 1234 1234 0 -1 4194560";
+		assert_eq!(parse_stat_ppid(stat), Some(567));
+	}
+
+	#[test]
+	fn test_parse_stat_ppid_with_parens_in_name() {
+		let stat = "1234 (weird) name) R 42 1234 1234 0 -1";
+		assert_eq!(parse_stat_ppid(stat), Some(42));
+	}
+
+	#[test]
+	fn test_parse_cmdline() {
+		let bytes = b"/usr/bin/code\0--wait\0file.rs\0";
+		assert_eq!(
+			parse_cmdline(bytes),
+			vec!["/usr/bin/code", "--wait", "file.rs"]
+		);
+	}
+
+	#[test]
+	fn test_process_info_matches_exe_or_cmdline() {
+		let info = ProcessInfo {
+			pid: 10,
+			ppid: Some(1),
+			exe: Some(PathBuf::from("/usr/bin/node")),
+			cmdline: vec!["node".to_string(), "/opt/ide/server.js".to_string()],
+		};
+
+		assert!(info.matches(&Regex::new(r"/node$").unwrap()));
+		assert!(info.matches(&Regex::new(r"ide/server\.js").unwrap()));
+		assert!(!info.matches(&Regex::new(r"tramp").unwrap()));
+		assert_eq!(info.name(), Some("node".to_string()));
+	}
+
+	#[test]
+	fn test_process_info_name_falls_back_to_argv0() {
+		let info = ProcessInfo {
+			pid: 10,
+			cmdline: vec!["/opt/bin/tramp".to_string(), "cargo".to_string()],
+			..Default::default()
+		};
+		assert_eq!(info.name(), Some("tramp".to_string()));
+	}
+
+	#[cfg(target_os = "linux")]
+	#[test]
+	fn test_process_ancestry_reads_parent() {
+		let ancestry = process_ancestry(2);
+		assert!(!ancestry.is_empty());
+		assert_eq!(ancestry[0].pid, std::os::unix::process::parent_id());
+		assert!(ancestry.len() <= 2);
+	}
+}
@@ -1,6 +1,8 @@
 use crate::config::types::{MergedConfig, Rule, RuleWithSource};
 use crate::error::{Result, TrampError};
+use crate::process::{DEFAULT_ANCESTOR_DEPTH, ProcessInfo};
 use regex::Regex;
+use serde::Serialize;
 use std::path::Path;
 
 /// Context for matching rules against a command invocation.
@@ -14,6 +16,9 @@ pub struct MatchContext<'a> {
 
 	/// The arguments passed to the command.
 	pub args: &'a [String],
+
+	/// The invocation chain, nearest ancestor (the parent process) first.
+	pub ancestry: &'a [ProcessInfo],
 }
 
 /// A compiled rule ready for matching.
@@ -28,13 +33,50 @@ pub struct CompiledRule {
 	/// Compiled cwd pattern regex.
 	pub cwd_regex: Option<Regex>,
 
+	/// Compiled parent process pattern regex.
+	pub parent_regex: Option<Regex>,
+
+	/// Compiled ancestor pattern regex.
+	pub ancestor_regex: Option<Regex>,
+
+	/// Compiled ancestor exclude pattern regex.
+	pub ancestor_exclude_regex: Option<Regex>,
+
+	/// The compiled matchers of the rule's group, which must match as well.
+	pub group_matchers: Option<Box<CompiledRule>>,
+
 	/// Source config path (for debugging).
 	pub source: std::path::PathBuf,
+
+	/// Position of the rule within its source config (0-based).
+	pub source_index: usize,
 }
 
 impl CompiledRule {
 	/// Compile a rule from a RuleWithSource.
+	///
+	/// Errors point at the rule's location in its source config, when known.
 	pub fn from_rule_with_source(rws: &RuleWithSource) -> Result<Self> {
+		let mut compiled = Self::compile(rws)
+			.map_err(|e| rws.rule.locate_error(&rws.source, rws.source_index, e))?;
+
+		if let Some(ref matchers) = rws.group_matchers {
+			let group = RuleWithSource {
+				rule: matchers.clone(),
+				group_matchers: None,
+				..rws.clone()
+			};
+			let compiled_group = Self::compile(&group).map_err(|e| match rws.rule.group {
+				Some(ref group) => matchers.locate_group_error(&rws.source, group, e),
+				None => e,
+			})?;
+			compiled.group_matchers = Some(Box::new(compiled_group));
+		}
+
+		Ok(compiled)
+	}
+
+	fn compile(rws: &RuleWithSource) -> Result<Self> {
 		let binary_regex = rws
 			.rule
 			.binary_pattern
@@ -49,33 +91,195 @@ impl CompiledRule {
 			.map(|p| compile_regex(p))
 			.transpose()?;
 
+		let parent_regex = rws
+			.rule
+			.parent_process_pattern
+			.as_ref()
+			.map(|p| compile_regex(p))
+			.transpose()?;
+
+		let ancestor_regex = rws
+			.rule
+			.ancestor_pattern
+			.as_ref()
+			.map(|p| compile_regex(p))
+			.transpose()?;
+
+		let ancestor_exclude_regex = rws
+			.rule
+			.ancestor_exclude_pattern
+			.as_ref()
+			.map(|p| compile_regex(p))
+			.transpose()?;
+
 		Ok(CompiledRule {
 			rule: rws.rule.clone(),
 			binary_regex,
 			cwd_regex,
+			parent_regex,
+			ancestor_regex,
+			ancestor_exclude_regex,
+			group_matchers: None,
 			source: rws.source.clone(),
+			source_index: rws.source_index,
 		})
 	}
 
-	/// Check if this rule matches the given context.
-	pub fn matches(&self, ctx: &MatchContext) -> bool {
-		// Check binary pattern if specified
-		if let Some(ref regex) = self.binary_regex {
-			let binary_str = ctx.binary_path.to_string_lossy();
-			if !regex.is_match(&binary_str) {
-				return false;
-			}
+	/// How many levels of the process tree this rule needs to inspect.
+	pub fn required_ancestry_depth(&self) -> usize {
+		let group_depth = self
+			.group_matchers
+			.as_ref()
+			.map_or(0, |group| group.required_ancestry_depth());
+		let own_depth = if self.ancestor_regex.is_some() || self.ancestor_exclude_regex.is_some() {
+			self.ancestor_depth()
+		} else if self.parent_regex.is_some() {
+			1
+		} else {
+			0
+		};
+		own_depth.max(group_depth)
+	}
+
+	/// Number of ancestor levels inspected by the ancestor patterns.
+	fn ancestor_depth(&self) -> usize {
+		self.rule.ancestor_depth.unwrap_or(DEFAULT_ANCESTOR_DEPTH)
+	}
+
+	/// The rule's own configured matchers with their regexes, in evaluation order.
+	fn matchers(&self) -> impl Iterator<Item = (Matcher, &Regex)> {
+		[
+			(Matcher::Binary, &self.binary_regex),
+			(Matcher::Cwd, &self.cwd_regex),
+			(Matcher::ParentProcess, &self.parent_regex),
+			(Matcher::Ancestor, &self.ancestor_regex),
+			(Matcher::AncestorExclude, &self.ancestor_exclude_regex),
+		]
+		.into_iter()
+		.filter_map(|(matcher, regex)| regex.as_ref().map(|regex| (matcher, regex)))
+	}
+
+	/// The ancestors the ancestor patterns are tested against.
+	fn ancestors<'c>(&self, ctx: &MatchContext<'c>) -> &'c [ProcessInfo] {
+		&ctx.ancestry[..self.ancestor_depth().min(ctx.ancestry.len())]
+	}
+
+	/// Whether one matcher of this rule passes in the given context.
+	fn passes(&self, matcher: Matcher, regex: &Regex, ctx: &MatchContext) -> bool {
+		match matcher {
+			Matcher::Binary => regex.is_match(&ctx.binary_path.to_string_lossy()),
+			Matcher::Cwd => regex.is_match(&ctx.cwd.to_string_lossy()),
+			Matcher::ParentProcess => ctx.ancestry.first().is_some_and(|p| p.matches(regex)),
+			Matcher::Ancestor => self.ancestors(ctx).iter().any(|p| p.matches(regex)),
+			Matcher::AncestorExclude => !self.ancestors(ctx).iter().any(|p| p.matches(regex)),
 		}
+	}
 
-		// Check cwd pattern if specified
-		if let Some(ref regex) = self.cwd_regex {
-			let cwd_str = ctx.cwd.to_string_lossy();
-			if !regex.is_match(&cwd_str) {
-				return false;
+	/// Describe what one matcher of this rule is tested against, for display.
+	fn subject(&self, matcher: Matcher, ctx: &MatchContext) -> String {
+		match matcher {
+			Matcher::Binary => ctx.binary_path.to_string_lossy().to_string(),
+			Matcher::Cwd => ctx.cwd.to_string_lossy().to_string(),
+			Matcher::ParentProcess => ctx
+				.ancestry
+				.first()
+				.map(describe_process)
+				.unwrap_or_else(|| "<unknown parent>".to_string()),
+			Matcher::Ancestor | Matcher::AncestorExclude => {
+				format!("{} ancestor process(es)", self.ancestors(ctx).len())
 			}
 		}
+	}
 
-		true
+	/// Check if this rule matches the given context.
+	///
+	/// Stops at the first matcher that fails; the rule's group comes first.
+	pub fn matches(&self, ctx: &MatchContext) -> bool {
+		// A rule in a group only matches if the group's matchers do
+		self.group_matchers
+			.as_ref()
+			.is_none_or(|group| group.matches(ctx))
+			&& self
+				.matchers()
+				.all(|(matcher, regex)| self.passes(matcher, regex, ctx))
+	}
+
+	/// Evaluate every matcher of this rule against the given context.
+	///
+	/// Unlike [`CompiledRule::matches`], this reports each configured matcher
+	/// individually and doesn't stop at the first failure, which is what
+	/// `tramp explain` displays. The matchers of the rule's group come first.
+	pub fn evaluate(&self, ctx: &MatchContext) -> Vec<MatcherOutcome> {
+		let group_outcomes = self
+			.group_matchers
+			.iter()
+			.flat_map(|group| group.evaluate(ctx))
+			.map(|outcome| MatcherOutcome {
+				from_group: true,
+				..outcome
+			});
+		let own_outcomes = self.matchers().map(|(matcher, regex)| MatcherOutcome {
+			matcher: matcher.field(),
+			pattern: regex.as_str().to_string(),
+			subject: self.subject(matcher, ctx),
+			passed: self.passes(matcher, regex, ctx),
+			from_group: false,
+		});
+		group_outcomes.chain(own_outcomes).collect()
+	}
+}
+
+/// A kind of matcher a rule can configure.
+#[derive(Debug, Clone, Copy)]
+enum Matcher {
+	Binary,
+	Cwd,
+	ParentProcess,
+	Ancestor,
+	AncestorExclude,
+}
+
+impl Matcher {
+	/// The config field of the matcher.
+	fn field(self) -> &'static str {
+		match self {
+			Matcher::Binary => "binary_pattern",
+			Matcher::Cwd => "cwd_pattern",
+			Matcher::ParentProcess => "parent_process_pattern",
+			Matcher::Ancestor => "ancestor_pattern",
+			Matcher::AncestorExclude => "ancestor_exclude_pattern",
+		}
+	}
+}
+
+/// The result of evaluating a single matcher of a rule.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MatcherOutcome {
+	/// The config field of the matcher, e.g. `binary_pattern`.
+	pub matcher: &'static str,
+
+	/// The regex pattern from the config.
+	pub pattern: String,
+
+	/// What the pattern was tested against.
+	pub subject: String,
+
+	/// Whether this matcher passed.
+	pub passed: bool,
+
+	/// Whether the matcher belongs to the rule's group rather than the rule.
+	#[serde(skip_serializing_if = "std::ops::Not::not")]
+	pub from_group: bool,
+}
+
+/// Describe a process for display: its command line, or its executable, or its PID.
+fn describe_process(process: &ProcessInfo) -> String {
+	if !process.cmdline.is_empty() {
+		process.cmdline_string()
+	} else if let Some(ref exe) = process.exe {
+		exe.to_string_lossy().to_string()
+	} else {
+		format!("pid {}", process.pid)
 	}
 }
 
@@ -96,6 +300,15 @@ pub fn compile_rules(config: &MergedConfig) -> Result<Vec<CompiledRule>> {
 		.collect()
 }
 
+/// How many levels of the process tree any of the given rules needs to inspect.
+pub fn required_ancestry_depth(rules: &[CompiledRule]) -> usize {
+	rules
+		.iter()
+		.map(CompiledRule::required_ancestry_depth)
+		.max()
+		.unwrap_or(0)
+}
+
 /// Find the first matching rule for a given context.
 pub fn find_matching_rule<'a>(
 	rules: &'a [CompiledRule],
@@ -111,8 +324,10 @@ mod tests {
 
 	fn make_rule_with_source(rule: Rule) -> RuleWithSource {
 		RuleWithSource {
-			rule,
 			source: PathBuf::from("test.toml"),
+			source_index: 0,
+			rule,
+			group_matchers: None,
 		}
 	}
 
@@ -147,6 +362,7 @@ mod tests {
 			binary_path: Path::new("/usr/local/bin/cargo"),
 			cwd: Path::new("/home/user/project"),
 			args: &[],
+			ancestry: &[],
 		};
 		assert!(compiled.matches(&ctx));
 
@@ -154,6 +370,7 @@ mod tests {
 			binary_path: Path::new("/usr/local/bin/rustc"),
 			cwd: Path::new("/home/user/project"),
 			args: &[],
+			ancestry: &[],
 		};
 		assert!(!compiled.matches(&ctx_no_match));
 	}
@@ -171,6 +388,7 @@ mod tests {
 			binary_path: Path::new("/usr/local/bin/cargo"),
 			cwd: Path::new("/home/user/my-project"),
 			args: &[],
+			ancestry: &[],
 		};
 		assert!(compiled.matches(&ctx));
 
@@ -178,6 +396,7 @@ mod tests {
 			binary_path: Path::new("/usr/local/bin/cargo"),
 			cwd: Path::new("/home/user/other-project"),
 			args: &[],
+			ancestry: &[],
 		};
 		assert!(!compiled.matches(&ctx_no_match));
 	}
@@ -197,6 +416,7 @@ mod tests {
 			binary_path: Path::new("/usr/local/bin/cargo"),
 			cwd: Path::new("/home/user/my-project"),
 			args: &[],
+			ancestry: &[],
 		};
 		assert!(compiled.matches(&ctx));
 
@@ -205,6 +425,7 @@ mod tests {
 			binary_path: Path::new("/usr/local/bin/cargo"),
 			cwd: Path::new("/home/user/other-project"),
 			args: &[],
+			ancestry: &[],
 		};
 		assert!(!compiled.matches(&ctx_cwd_mismatch));
 
@@ -213,10 +434,238 @@ mod tests {
 			binary_path: Path::new("/usr/local/bin/rustc"),
 			cwd: Path::new("/home/user/my-project"),
 			args: &[],
+			ancestry: &[],
 		};
 		assert!(!compiled.matches(&ctx_binary_mismatch));
 	}
 
+	fn make_process(pid: u32, exe: &str) -> ProcessInfo {
+		ProcessInfo {
+			pid,
+			ppid: Some(pid - 1),
+			exe: Some(PathBuf::from(exe)),
+			cmdline: vec![exe.to_string()],
+		}
+	}
+
+	#[test]
+	fn test_rule_matches_parent_process_pattern() {
+		let rule = Rule {
+			binary_pattern: Some(r".*/cargo$".to_string()),
+			parent_process_pattern: Some(r"/code$".to_string()),
+			..Default::default()
+		};
+		let compiled = CompiledRule::from_rule_with_source(&make_rule_with_source(rule)).unwrap();
+		assert_eq!(compiled.required_ancestry_depth(), 1);
+
+		let from_ide = [
+			make_process(20, "/usr/bin/code"),
+			make_process(10, "/bin/zsh"),
+		];
+		let ctx = MatchContext {
+			binary_path: Path::new("/usr/local/bin/cargo"),
+			cwd: Path::new("/home/user/project"),
+			args: &[],
+			ancestry: &from_ide,
+		};
+		assert!(compiled.matches(&ctx));
+
+		// Grandparent matching doesn't count for parent_process_pattern
+		let from_shell = [
+			make_process(20, "/bin/zsh"),
+			make_process(10, "/usr/bin/code"),
+		];
+		let ctx = MatchContext {
+			ancestry: &from_shell,
+			..ctx
+		};
+		assert!(!compiled.matches(&ctx));
+
+		// Unknown parent never matches
+		let ctx = MatchContext {
+			ancestry: &[],
+			..ctx
+		};
+		assert!(!compiled.matches(&ctx));
+	}
+
+	#[test]
+	fn test_rule_matches_ancestor_patterns_within_depth() {
+		let rule = Rule {
+			ancestor_pattern: Some(r"/code$".to_string()),
+			ancestor_depth: Some(2),
+			..Default::default()
+		};
+		let compiled = CompiledRule::from_rule_with_source(&make_rule_with_source(rule)).unwrap();
+		assert_eq!(compiled.required_ancestry_depth(), 2);
+
+		let chain = [
+			make_process(30, "/bin/sh"),
+			make_process(20, "/bin/zsh"),
+			make_process(10, "/usr/bin/code"),
+		];
+		let ctx = MatchContext {
+			binary_path: Path::new("/usr/local/bin/cargo"),
+			cwd: Path::new("/home/user/project"),
+			args: &[],
+			ancestry: &chain[..],
+		};
+		// The IDE is three levels up, beyond the configured depth
+		assert!(!compiled.matches(&ctx));
+
+		let ctx = MatchContext {
+			ancestry: &chain[1..],
+			..ctx
+		};
+		assert!(compiled.matches(&ctx));
+	}
+
+	#[test]
+	fn test_rule_ancestor_exclude_pattern() {
+		let rule = Rule {
+			ancestor_exclude_pattern: Some(r"/tramp$".to_string()),
+			..Default::default()
+		};
+		let compiled = CompiledRule::from_rule_with_source(&make_rule_with_source(rule)).unwrap();
+		assert_eq!(compiled.required_ancestry_depth(), DEFAULT_ANCESTOR_DEPTH);
+
+		let nested = [
+			make_process(20, "/bin/sh"),
+			make_process(10, "/usr/bin/tramp"),
+		];
+		let ctx = MatchContext {
+			binary_path: Path::new("/usr/local/bin/cargo"),
+			cwd: Path::new("/home/user/project"),
+			args: &[],
+			ancestry: &nested,
+		};
+		assert!(!compiled.matches(&ctx));
+
+		let direct = [make_process(20, "/bin/zsh")];
+		let ctx = MatchContext {
+			ancestry: &direct,
+			..ctx
+		};
+		assert!(compiled.matches(&ctx));
+	}
+
+	#[test]
+	fn test_evaluate_reports_each_matcher() {
+		let rule = Rule {
+			binary_pattern: Some(r".*/cargo$".to_string()),
+			cwd_pattern: Some(r".*/my-project$".to_string()),
+			..Default::default()
+		};
+		let compiled = CompiledRule::from_rule_with_source(&make_rule_with_source(rule)).unwrap();
+
+		let ctx = MatchContext {
+			binary_path: Path::new("/usr/local/bin/cargo"),
+			cwd: Path::new("/home/user/other-project"),
+			args: &[],
+			ancestry: &[],
+		};
+		let outcomes = compiled.evaluate(&ctx);
+
+		assert_eq!(outcomes.len(), 2);
+		assert_eq!(outcomes[0].matcher, "binary_pattern");
+		assert!(outcomes[0].passed);
+		assert_eq!(outcomes[1].matcher, "cwd_pattern");
+		assert_eq!(outcomes[1].subject, "/home/user/other-project");
+		assert!(!outcomes[1].passed);
+		assert!(!compiled.matches(&ctx));
+	}
+
+	#[test]
+	fn test_group_matchers_are_anded_with_the_rules() {
+		let rws = RuleWithSource {
+			group_matchers: Some(Rule {
+				binary_pattern: Some(r".*/cargo$".to_string()),
+				cwd_pattern: Some(r"^/repo".to_string()),
+				..Default::default()
+			}),
+			..make_rule_with_source(Rule {
+				cwd_pattern: Some(r"/crates/".to_string()),
+				..Default::default()
+			})
+		};
+		let compiled = CompiledRule::from_rule_with_source(&rws).unwrap();
+
+		let ctx = |binary, cwd| MatchContext {
+			binary_path: Path::new(binary),
+			cwd: Path::new(cwd),
+			args: &[],
+			ancestry: &[],
+		};
+		assert!(compiled.matches(&ctx("/bin/cargo", "/repo/crates/a")));
+		// Both the group's and the rule's cwd_pattern must match
+		assert!(!compiled.matches(&ctx("/bin/cargo", "/repo/docs")));
+		assert!(!compiled.matches(&ctx("/bin/cargo", "/other/crates/a")));
+		assert!(!compiled.matches(&ctx("/bin/make", "/repo/crates/a")));
+
+		let outcomes = compiled.evaluate(&ctx("/bin/cargo", "/repo/crates/a"));
+		let from_group: Vec<bool> = outcomes.iter().map(|o| o.from_group).collect();
+		assert_eq!(from_group, [true, true, false]);
+	}
+
+	#[test]
+	fn test_matches_agrees_with_evaluate() {
+		let rws = RuleWithSource {
+			group_matchers: Some(Rule {
+				cwd_pattern: Some(r"^/repo".to_string()),
+				..Default::default()
+			}),
+			..make_rule_with_source(Rule {
+				binary_pattern: Some(r".*/cargo$".to_string()),
+				parent_process_pattern: Some(r"/zsh$".to_string()),
+				ancestor_pattern: Some(r"/code$".to_string()),
+				ancestor_exclude_pattern: Some(r"/tramp$".to_string()),
+				ancestor_depth: Some(2),
+				..Default::default()
+			})
+		};
+		let compiled = CompiledRule::from_rule_with_source(&rws).unwrap();
+
+		let chains = [
+			vec![
+				make_process(20, "/bin/zsh"),
+				make_process(10, "/usr/bin/code"),
+			],
+			vec![
+				make_process(20, "/bin/sh"),
+				make_process(10, "/usr/bin/code"),
+			],
+			vec![
+				make_process(20, "/bin/zsh"),
+				make_process(10, "/usr/bin/tramp"),
+			],
+			vec![
+				make_process(30, "/bin/zsh"),
+				make_process(20, "/bin/sh"),
+				make_process(10, "/usr/bin/code"),
+			],
+			vec![],
+		];
+		for binary in ["/bin/cargo", "/bin/make"] {
+			for cwd in ["/repo", "/other"] {
+				for chain in &chains {
+					let ctx = MatchContext {
+						binary_path: Path::new(binary),
+						cwd: Path::new(cwd),
+						args: &[],
+						ancestry: chain,
+					};
+					let outcomes = compiled.evaluate(&ctx);
+					assert_eq!(outcomes.len(), 5);
+					assert_eq!(
+						compiled.matches(&ctx),
+						outcomes.iter().all(|o| o.passed),
+						"{binary} in {cwd} under {chain:?}"
+					);
+				}
+			}
+		}
+	}
+
 	#[test]
 	fn test_rule_with_no_patterns_matches_everything() {
 		let rule = Rule::default();
@@ -227,6 +676,7 @@ mod tests {
 			binary_path: Path::new("/any/path"),
 			cwd: Path::new("/any/dir"),
 			args: &[],
+			ancestry: &[],
 		};
 		assert!(compiled.matches(&ctx));
 	}
@@ -256,6 +706,7 @@ mod tests {
 			binary_path: Path::new("/usr/local/bin/cargo"),
 			cwd: Path::new("/home/user/project"),
 			args: &[],
+			ancestry: &[],
 		};
 
 		let matched = find_matching_rule(&compiled, &ctx);
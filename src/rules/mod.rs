@@ -1,11 +1,14 @@
 //! Rule matching and rewriting for tramp.
 //!
 //! This module handles:
-//! - Pattern matching for binary paths and working directories
+//! - Pattern matching for binary paths, working directories, and parent processes
 //! - Argument and command rewriting using sed-like substitutions
 
 pub mod matcher;
 pub mod rewriter;
 
-pub use matcher::{CompiledRule, MatchContext, compile_rules, find_matching_rule};
-pub use rewriter::{Substitution, rewrite_args, rewrite_command};
+pub use matcher::{
+	CompiledRule, MatchContext, MatcherOutcome, compile_rules, find_matching_rule,
+	required_ancestry_depth,
+};
+pub use rewriter::{Substitution, apply_rule, rewrite_args, rewrite_command};
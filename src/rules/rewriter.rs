@@ -1,5 +1,8 @@
+use crate::config::types::Rule;
 use crate::error::{Result, TrampError};
+use crate::exec::resolve_command;
 use regex::Regex;
+use std::path::{Path, PathBuf};
 
 /// Parsed substitution command (sed-like syntax).
 #[derive(Debug)]
@@ -152,6 +155,45 @@ pub fn rewrite_command(
 	}
 }
 
+/// Apply a rule's rewrite to a command, returning the final binary and arguments.
+///
+/// At most one of `alternate_command`, `arg_rewrite`, or `command_rewrite` is set
+/// (see [`Rule::validate`]). Rules without a rewrite return the command unchanged.
+pub fn apply_rule(
+	binary_path: &Path,
+	args: &[String],
+	rule: &Rule,
+) -> Result<(PathBuf, Vec<String>)> {
+	// Check for alternate command
+	if let Some(ref alternate) = rule.alternate_command {
+		let alt_path = resolve_command(alternate).ok_or_else(|| TrampError::CommandNotFound {
+			command: alternate.clone(),
+		})?;
+		return Ok((alt_path, args.to_vec()));
+	}
+
+	// Check for arg_rewrite
+	if let Some(ref rewrite) = rule.arg_rewrite {
+		let sub = Substitution::parse(rewrite)?;
+		let new_args = rewrite_args(args, &sub);
+		return Ok((binary_path.to_path_buf(), new_args));
+	}
+
+	// Check for command_rewrite
+	if let Some(ref rewrite) = rule.command_rewrite {
+		let sub = Substitution::parse(rewrite)?;
+		let binary_str = binary_path.to_string_lossy();
+		let (new_binary_str, new_args) = rewrite_command(&binary_str, args, &sub);
+		let new_binary = resolve_command(&new_binary_str).ok_or(TrampError::CommandNotFound {
+			command: new_binary_str,
+		})?;
+		return Ok((new_binary, new_args));
+	}
+
+	// No rewrite, return as-is
+	Ok((binary_path.to_path_buf(), args.to_vec()))
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -241,4 +283,40 @@ mod tests {
 		let result = Substitution::parse("s");
 		assert!(result.is_err());
 	}
+
+	#[test]
+	fn test_apply_rule_arg_rewrite() {
+		let rule = Rule {
+			arg_rewrite: Some("s/^build$/build --release/".to_string()),
+			..Default::default()
+		};
+		let (binary, args) =
+			apply_rule(Path::new("/usr/bin/cargo"), &["build".to_string()], &rule).unwrap();
+		assert_eq!(binary, Path::new("/usr/bin/cargo"));
+		assert_eq!(args, vec!["build", "--release"]);
+	}
+
+	#[test]
+	fn test_apply_rule_without_rewrite_is_unchanged() {
+		let args = vec!["test".to_string()];
+		let (binary, new_args) =
+			apply_rule(Path::new("/usr/bin/cargo"), &args, &Rule::default()).unwrap();
+		assert_eq!(binary, Path::new("/usr/bin/cargo"));
+		assert_eq!(new_args, args);
+	}
+
+	#[test]
+	fn test_apply_rule_missing_alternate_command() {
+		let rule = Rule {
+			alternate_command: Some("nonexistent_command_12345".to_string()),
+			..Default::default()
+		};
+		let result = apply_rule(Path::new("/usr/bin/npm"), &[], &rule);
+		match result.unwrap_err() {
+			TrampError::CommandNotFound { command } => {
+				assert_eq!(command, "nonexistent_command_12345");
+			}
+			_ => panic!("Expected CommandNotFound error"),
+		}
+	}
 }
@@ -0,0 +1,355 @@
+//! Config self-tests for `tramp test`.
+//!
+//! `[[tests]]` tables in a config describe how a command is expected to be
+//! handled. Each test is evaluated through the real matcher and rewriter, using
+//! the cascade seen from the test's working directory, without executing
+//! anything. Rules muted on this machine still apply, and `expires` and
+//! `active_between` only count for tests that set `at`, so results don't
+//! depend on where or when the tests run.
+
+use crate::config::cascade::{discover_configs, merge_configs_at};
+use crate::config::mutes::MutedRules;
+use crate::config::types::{LoadedConfig, RuleTest};
+use crate::error::Result;
+use crate::exec::resolve_command;
+use crate::explain::{Explanation, explain_command};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Value of `expect_rule` meaning no rule should match.
+pub const NO_RULE: &str = "none";
+
+/// The outcome of a single config test.
+#[derive(Debug, Clone, Serialize)]
+pub struct TestResult {
+	/// The config file the test is defined in.
+	pub file: PathBuf,
+
+	/// Position of the test within its config file (0-based).
+	pub index: usize,
+
+	/// The test's display name.
+	pub name: String,
+
+	/// Why the test failed. Empty if it passed.
+	pub failures: Vec<String>,
+}
+
+impl TestResult {
+	/// Whether every expectation held.
+	pub fn passed(&self) -> bool {
+		self.failures.is_empty()
+	}
+}
+
+/// The outcome of every config test in the cascade.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TestReport {
+	/// Results in cascade order, then in file order.
+	pub results: Vec<TestResult>,
+}
+
+impl TestReport {
+	/// Number of passing tests.
+	pub fn passed(&self) -> usize {
+		self.results.iter().filter(|r| r.passed()).count()
+	}
+
+	/// Number of failing tests.
+	pub fn failed(&self) -> usize {
+		self.results.len() - self.passed()
+	}
+}
+
+/// Run the tests defined in every config of the cascade starting at `start_dir`.
+///
+/// Only tests whose name contains `filter` are run, if given.
+pub fn run_config_tests(start_dir: &Path, filter: Option<&str>) -> Result<TestReport> {
+	let discovery = discover_configs(start_dir)?;
+	let mut report = TestReport::default();
+
+	for loaded in &discovery.configs {
+		for (index, test) in loaded.config.tests.iter().enumerate() {
+			let name = test.display_name();
+			if filter.is_some_and(|filter| !name.contains(filter)) {
+				continue;
+			}
+
+			report.results.push(TestResult {
+				file: loaded.path.clone(),
+				index,
+				name,
+				failures: run_test(test, &loaded.path),
+			});
+		}
+	}
+
+	Ok(report)
+}
+
+/// Run a single test defined in the config at `config_path`, returning its failures.
+pub fn run_test(test: &RuleTest, config_path: &Path) -> Vec<String> {
+	let config_dir = config_path.parent().unwrap_or(Path::new("."));
+	let cwd = match test.cwd {
+		Some(ref cwd) => config_dir.join(cwd),
+		None => config_dir.to_path_buf(),
+	};
+
+	match discover_configs(&cwd) {
+		Ok(discovery) => check_test(test, &discovery.configs, &cwd),
+		Err(e) => vec![format!(
+			"Failed to load configuration from {}: {}",
+			cwd.display(),
+			e
+		)],
+	}
+}
+
+/// Evaluate a test against an already-loaded cascade.
+pub fn check_test(test: &RuleTest, configs: &[LoadedConfig], cwd: &Path) -> Vec<String> {
+	let Some((program, args)) = test.command.split_first() else {
+		return vec!["Test has an empty command".to_string()];
+	};
+
+	// A program that isn't installed is taken to be in /usr/bin, so tests
+	// don't depend on what is installed on this machine
+	let binary = if program.contains('/') {
+		PathBuf::from(program)
+	} else {
+		resolve_command(program).unwrap_or_else(|| Path::new("/usr/bin").join(program))
+	};
+
+	let now = test.at.map(|at| at.0);
+	let merged = merge_configs_at(configs, now, &MutedRules::default());
+	match explain_command(configs, merged, &binary, args, cwd, |_| Vec::new()) {
+		Ok(explanation) => check_expectations(test, &explanation),
+		Err(e) => vec![e.to_string()],
+	}
+}
+
+/// Compare an explanation against a test's expectations.
+fn check_expectations(test: &RuleTest, explanation: &Explanation) -> Vec<String> {
+	let mut failures = Vec::new();
+	let chosen = explanation.chosen_rule.map(|i| &explanation.rules[i]);
+
+	if let Some(ref expected) = test.expect_rule {
+		let actual = match chosen {
+			Some(trace) => match trace.id {
+				Some(ref id) => format!("`{}`", id),
+				None => format!("{} in {} (no id)", trace.position(), trace.source.display()),
+			},
+			None => NO_RULE.to_string(),
+		};
+		let matches = match chosen {
+			Some(trace) => trace.id.as_deref() == Some(expected.as_str()),
+			None => expected == NO_RULE,
+		};
+		if !matches {
+			// Say why the expected rule was left out, if it was
+			let skipped = explanation
+				.skipped
+				.iter()
+				.find(|skipped| skipped.rule.rule.id.as_deref() == Some(expected.as_str()))
+				.map_or(String::new(), |skipped| {
+					format!(" (`{}` is {})", expected, skipped.skipped)
+				});
+			failures.push(format!(
+				"expected rule `{}`, but {} was chosen{}",
+				expected, actual, skipped
+			));
+		}
+	}
+
+	if let Some(ref error) = explanation.rewrite_error {
+		failures.push(format!("rewrite failed: {}", error));
+	} else if let Some(ref expected) = test.expect_argv {
+		let mut actual = vec![explanation.final_binary.to_string_lossy().to_string()];
+		actual.extend(explanation.final_args.iter().cloned());
+
+		if !argv_matches(expected, &explanation.final_binary, &explanation.final_args) {
+			let note = if explanation.not_computed.is_empty() {
+				String::new()
+			} else {
+				format!(
+					" (not computed, since tests don't run `cmd` variables: {})",
+					explanation.not_computed.join(", ")
+				)
+			};
+			failures.push(format!(
+				"expected argv {:?}, got {:?}{}",
+				expected, actual, note
+			));
+		}
+	}
+
+	if let Some(ref expected) = test.expect_hooks {
+		let actual: Vec<&str> = explanation
+			.hooks
+			.iter()
+			.map(|hook| hook.hook_type.as_str())
+			.collect();
+		if *expected != actual {
+			failures.push(format!("expected hooks {:?}, got {:?}", expected, actual));
+		}
+	}
+
+	failures
+}
+
+/// Whether the final command matches `expected`, whose program may be a full
+/// path or a bare name.
+fn argv_matches(expected: &[String], binary: &Path, args: &[String]) -> bool {
+	let Some((program, expected_args)) = expected.split_first() else {
+		return false;
+	};
+
+	let program_matches = if program.contains('/') {
+		Path::new(program) == binary
+	} else {
+		binary
+			.file_name()
+			.is_some_and(|name| name == program.as_str())
+	};
+
+	program_matches && expected_args == args
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::config::parse_config_str;
+
+	fn load(content: &str) -> Vec<LoadedConfig> {
+		vec![LoadedConfig {
+			config: parse_config_str(content, Path::new("/repo/.tramp.toml")).unwrap(),
+			path: PathBuf::from("/repo/.tramp.toml"),
+		}]
+	}
+
+	fn test_case(content: &str) -> RuleTest {
+		toml::from_str(content).unwrap()
+	}
+
+	const CONFIG: &str = r#"
+[[rules]]
+id = "release-builds"
+binary_pattern = ".*/cargo$"
+arg_rewrite = "s/^build$/build --release/"
+pre_hook = "/hooks/pre.sh"
+post_hook = "/hooks/post.sh"
+"#;
+
+	#[test]
+	fn test_passing_expectations() {
+		let configs = load(CONFIG);
+		let test = test_case(
+			r#"
+command = ["/usr/bin/cargo", "build"]
+expect_rule = "release-builds"
+expect_argv = ["cargo", "build", "--release"]
+expect_hooks = ["pre", "post"]
+"#,
+		);
+
+		assert!(check_test(&test, &configs, Path::new("/repo")).is_empty());
+	}
+
+	#[test]
+	fn test_failing_expectations_are_all_reported() {
+		let configs = load(CONFIG);
+		let test = test_case(
+			r#"
+command = ["/usr/bin/cargo", "build"]
+expect_rule = "something-else"
+expect_argv = ["/usr/bin/cargo", "build"]
+expect_hooks = ["pre"]
+"#,
+		);
+
+		let failures = check_test(&test, &configs, Path::new("/repo"));
+		assert_eq!(failures.len(), 3);
+		assert!(failures[0].contains("expected rule `something-else`"));
+		assert!(failures[0].contains("`release-builds` was chosen"));
+		assert!(failures[1].contains("expected argv"));
+		assert!(failures[2].contains("expected hooks"));
+	}
+
+	#[test]
+	fn test_expect_no_rule() {
+		let configs = load(CONFIG);
+		let test = test_case(
+			r#"
+command = ["/usr/bin/npm", "install"]
+expect_rule = "none"
+expect_argv = ["/usr/bin/npm", "install"]
+expect_hooks = []
+"#,
+		);
+
+		assert!(check_test(&test, &configs, Path::new("/repo")).is_empty());
+	}
+
+	#[test]
+	fn test_uninstalled_program_is_taken_to_be_in_usr_bin() {
+		let configs = load(CONFIG);
+		let test = test_case(
+			r#"
+command = ["tramp-test-no-such-program", "build"]
+expect_rule = "none"
+expect_argv = ["/usr/bin/tramp-test-no-such-program", "build"]
+"#,
+		);
+
+		assert!(check_test(&test, &configs, Path::new("/repo")).is_empty());
+	}
+
+	#[test]
+	fn test_time_limits_only_count_at_a_given_time() {
+		let configs = load(
+			r#"
+[[rules]]
+id = "daytime"
+binary_pattern = ".*/cargo$"
+active_between = ["09:00", "18:00"]
+expires = 2026-12-01
+"#,
+		);
+		let test = test_case(
+			r#"
+command = ["/usr/bin/cargo", "build"]
+expect_rule = "daytime"
+"#,
+		);
+		assert!(check_test(&test, &configs, Path::new("/repo")).is_empty());
+
+		let test = test_case(
+			r#"
+command = ["/usr/bin/cargo", "build"]
+at = 2026-10-18T20:00:00
+expect_rule = "daytime"
+"#,
+		);
+		assert_eq!(
+			check_test(&test, &configs, Path::new("/repo")),
+			[
+				"expected rule `daytime`, but none was chosen (`daytime` is outside active_between 09:00-18:00)"
+			]
+		);
+
+		let test = test_case(
+			r#"
+command = ["/usr/bin/cargo", "build"]
+at = "2026-12-01 10:00"
+expect_rule = "daytime"
+"#,
+		);
+		let failures = check_test(&test, &configs, Path::new("/repo"));
+		assert!(failures[0].ends_with("(`daytime` is expired on 2026-12-01)"));
+	}
+
+	#[test]
+	fn test_empty_command_fails() {
+		let failures = check_test(&RuleTest::default(), &load(CONFIG), Path::new("/repo"));
+		assert_eq!(failures, vec!["Test has an empty command"]);
+	}
+}
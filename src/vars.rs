@@ -0,0 +1,524 @@
+//! Computed variables: `[vars]` entries whose value comes from a command's
+//! output or a file, used in rules as `${vars.NAME}`.
+//!
+//! Values are computed lazily: only the variables referenced by the rule that
+//! was chosen are computed, once each per invocation. A command that doesn't
+//! finish within its `timeout` is killed. Variables with a `cache-ttl` keep
+//! their value on disk per working directory, so slow commands don't run on
+//! every invocation.
+
+use crate::config::split_config_path;
+use crate::config::types::{ComputedVar, Rule, VarWithSource};
+use crate::error::{Result, TrampError};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How long a `cmd` variable may run when it sets no `timeout`.
+pub const DEFAULT_VAR_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How often a running `cmd` is checked for completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// What starts a variable reference in a rule.
+const REFERENCE_START: &str = "${vars.";
+
+/// The names of the variables referenced in `text`, in order of first use.
+pub fn var_references(text: &str) -> Vec<&str> {
+	let mut names = Vec::new();
+	let mut rest = text;
+	while let Some(start) = rest.find(REFERENCE_START) {
+		let after = &rest[start + REFERENCE_START.len()..];
+		let Some(end) = after.find('}') else {
+			break;
+		};
+		if !names.contains(&&after[..end]) {
+			names.push(&after[..end]);
+		}
+		rest = &after[end + 1..];
+	}
+	names
+}
+
+/// Replace each `${vars.NAME}` in `text` that has a value in `values`.
+pub fn interpolate(text: &str, values: &HashMap<String, String>) -> String {
+	let mut result = String::with_capacity(text.len());
+	let mut rest = text;
+	while let Some(start) = rest.find(REFERENCE_START) {
+		let after = &rest[start + REFERENCE_START.len()..];
+		let Some(end) = after.find('}') else {
+			break;
+		};
+		result.push_str(&rest[..start]);
+		match values.get(&after[..end]) {
+			Some(value) => result.push_str(value),
+			None => result.push_str(&rest[start..start + REFERENCE_START.len() + end + 1]),
+		}
+		rest = &after[end + 1..];
+	}
+	result.push_str(rest);
+	result
+}
+
+/// Compute the variables a rule refers to and fill them into its fields.
+///
+/// A rule that refers to no variables is returned as-is without computing
+/// anything.
+pub fn interpolate_rule(
+	rule: &Rule,
+	vars: &BTreeMap<String, VarWithSource>,
+	cwd: &Path,
+) -> Result<Rule> {
+	fill_rule(rule, vars, cwd, true).map(|(rule, _)| rule)
+}
+
+/// Fill the variables a rule refers to into its fields without running
+/// anything, for `tramp explain`, `--dry-run`, and `tramp test`.
+///
+/// `file` variables are read and `cmd` variables use a cached value that is
+/// still valid. The references to other variables are left in place; their
+/// names are returned as not computed.
+pub fn preview_rule(
+	rule: &Rule,
+	vars: &BTreeMap<String, VarWithSource>,
+	cwd: &Path,
+) -> Result<(Rule, Vec<String>)> {
+	fill_rule(rule, vars, cwd, false)
+}
+
+/// Fill in a rule's variables, running `cmd` variables only if `run_commands`
+/// is set. Returns the rule and the names of the variables left uncomputed.
+fn fill_rule(
+	rule: &Rule,
+	vars: &BTreeMap<String, VarWithSource>,
+	cwd: &Path,
+	run_commands: bool,
+) -> Result<(Rule, Vec<String>)> {
+	let fields = rule.interpolated_fields();
+	let mut names: Vec<&str> = Vec::new();
+	for name in fields.iter().flat_map(|(_, value)| var_references(value)) {
+		if !names.contains(&name) {
+			names.push(name);
+		}
+	}
+	if names.is_empty() {
+		return Ok((rule.clone(), Vec::new()));
+	}
+
+	let mut cache = VarCache::default();
+	let mut values = HashMap::new();
+	let mut not_computed = Vec::new();
+	for name in names {
+		let var = vars.get(name).ok_or_else(|| TrampError::UnknownVar {
+			name: name.to_string(),
+		})?;
+		match compute_var(name, var, cwd, &mut cache, run_commands)? {
+			Some(value) => {
+				values.insert(name.to_string(), value);
+			}
+			None => not_computed.push(name.to_string()),
+		}
+	}
+	cache.save();
+
+	let mut rule = rule.clone();
+	for text in [
+		&mut rule.arg_rewrite,
+		&mut rule.command_rewrite,
+		&mut rule.alternate_command,
+	]
+	.into_iter()
+	.flatten()
+	{
+		*text = interpolate(text, &values);
+	}
+	for hook in [
+		&mut rule.pre_hook,
+		&mut rule.post_hook,
+		&mut rule.intercept_hook,
+	]
+	.into_iter()
+	.flatten()
+	{
+		*hook = PathBuf::from(interpolate(&hook.to_string_lossy(), &values));
+	}
+	Ok((rule, not_computed))
+}
+
+/// Get a variable's value from the cache, or compute it. Returns `None` for
+/// a `cmd` variable with no cached value when `run_commands` isn't set.
+fn compute_var(
+	name: &str,
+	var: &VarWithSource,
+	cwd: &Path,
+	cache: &mut VarCache,
+	run_commands: bool,
+) -> Result<Option<String>> {
+	let key = cache_key(name, var, cwd);
+	if var.var.cache_ttl.is_some()
+		&& let Some(value) = cache.get(&key)
+	{
+		return Ok(Some(value));
+	}
+	if !run_commands && var.var.cmd.is_some() {
+		return Ok(None);
+	}
+
+	match evaluate_var(&var.var, &var.source, cwd) {
+		Ok(value) => {
+			// Previews leave the cache as they found it
+			if let Some(ttl) = var.var.cache_ttl
+				&& run_commands
+			{
+				cache.put(key, value.clone(), ttl);
+			}
+			Ok(Some(value))
+		}
+		Err(reason) => var
+			.var
+			.default
+			.clone()
+			.map(Some)
+			.ok_or_else(|| TrampError::VarFailed {
+				name: name.to_string(),
+				reason,
+			}),
+	}
+}
+
+/// Compute a variable defined in the config at `source`, returning why it
+/// failed if it did.
+pub fn evaluate_var(
+	var: &ComputedVar,
+	source: &Path,
+	cwd: &Path,
+) -> std::result::Result<String, String> {
+	var.validate()?;
+
+	if let Some(ref cmd) = var.cmd {
+		let timeout = var
+			.timeout
+			.map_or(DEFAULT_VAR_TIMEOUT, Duration::from_secs_f64);
+		return run_with_timeout(cmd, cwd, timeout).map(|output| output.trim().to_string());
+	}
+
+	let file = var.file.as_deref().unwrap_or(Path::new(""));
+	let (config_file, _) = split_config_path(source);
+	let path = config_file.parent().unwrap_or(Path::new("")).join(file);
+	std::fs::read_to_string(&path)
+		.map(|content| content.trim().to_string())
+		.map_err(|e| format!("can't read {}: {}", path.display(), e))
+}
+
+/// Run a command and return its standard output, killing it after `timeout`.
+fn run_with_timeout(
+	cmd: &[String],
+	cwd: &Path,
+	timeout: Duration,
+) -> std::result::Result<String, String> {
+	let command_line = cmd.join(" ");
+	let (program, args) = cmd.split_first().ok_or("`cmd` is empty")?;
+
+	let mut child = Command::new(program)
+		.args(args)
+		.current_dir(cwd)
+		.stdin(Stdio::null())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::null())
+		.spawn()
+		.map_err(|e| format!("can't run `{}`: {}", command_line, e))?;
+
+	// Read output on another thread so a chatty command can't fill the pipe and stall
+	let mut stdout = child.stdout.take().expect("stdout is piped");
+	let reader = std::thread::spawn(move || {
+		let mut output = String::new();
+		stdout.read_to_string(&mut output).map(|_| output)
+	});
+
+	let deadline = Instant::now() + timeout;
+	let status = loop {
+		match child.try_wait() {
+			Ok(Some(status)) => break status,
+			Ok(None) if Instant::now() < deadline => std::thread::sleep(POLL_INTERVAL),
+			Ok(None) => {
+				let _ = child.kill();
+				let _ = child.wait();
+				return Err(format!("`{}` timed out after {:?}", command_line, timeout));
+			}
+			Err(e) => return Err(format!("can't wait for `{}`: {}", command_line, e)),
+		}
+	};
+
+	let output = reader
+		.join()
+		.map_err(|_| format!("can't read the output of `{}`", command_line))?
+		.map_err(|e| format!("can't read the output of `{}`: {}", command_line, e))?;
+
+	if !status.success() {
+		return Err(format!("`{}` exited with {}", command_line, status));
+	}
+	Ok(output)
+}
+
+/// Where computed values are cached: `tramp/vars.json` in the user cache directory.
+pub fn var_cache_path() -> Option<PathBuf> {
+	dirs::cache_dir().map(|dir| dir.join("tramp").join("vars.json"))
+}
+
+/// The cache key of a variable's value: where it's computed and how.
+///
+/// The definition is part of the key, so editing a variable discards its
+/// cached value.
+fn cache_key(name: &str, var: &VarWithSource, cwd: &Path) -> String {
+	let definition = serde_json::to_string(&var.var).unwrap_or_default();
+	format!(
+		"{}\n{}\n{}\n{}",
+		cwd.display(),
+		var.source.display(),
+		name,
+		definition
+	)
+}
+
+/// Computed values kept on disk between invocations.
+///
+/// The cache is best-effort: it is read on first use, and failures to read
+/// or write it are ignored.
+#[derive(Default)]
+struct VarCache {
+	entries: Option<BTreeMap<String, CacheEntry>>,
+	changed: bool,
+}
+
+/// A cached value and when it expires.
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+	value: String,
+
+	/// Seconds since the Unix epoch.
+	expires_at: u64,
+}
+
+impl VarCache {
+	fn entries(&mut self) -> &mut BTreeMap<String, CacheEntry> {
+		self.entries.get_or_insert_with(|| {
+			var_cache_path()
+				.and_then(|path| std::fs::read_to_string(path).ok())
+				.and_then(|content| serde_json::from_str(&content).ok())
+				.unwrap_or_default()
+		})
+	}
+
+	fn get(&mut self, key: &str) -> Option<String> {
+		let now = now();
+		self.entries()
+			.get(key)
+			.filter(|entry| entry.expires_at > now)
+			.map(|entry| entry.value.clone())
+	}
+
+	fn put(&mut self, key: String, value: String, ttl: u64) {
+		let expires_at = now().saturating_add(ttl);
+		self.entries().insert(key, CacheEntry { value, expires_at });
+		self.changed = true;
+	}
+
+	/// Write the cache back if anything was added, dropping expired values.
+	fn save(&mut self) {
+		if !self.changed {
+			return;
+		}
+		let (Some(path), Some(entries)) = (var_cache_path(), self.entries.as_mut()) else {
+			return;
+		};
+
+		let now = now();
+		entries.retain(|_, entry| entry.expires_at > now);
+
+		let Ok(content) = serde_json::to_string(entries) else {
+			return;
+		};
+		if let Some(dir) = path.parent() {
+			let _ = std::fs::create_dir_all(dir);
+		}
+		// Write then rename, so a concurrent invocation never reads half a file
+		let temp = path.with_extension(format!("json.{}", std::process::id()));
+		if std::fs::write(&temp, content).is_ok() && std::fs::rename(&temp, &path).is_err() {
+			let _ = std::fs::remove_file(&temp);
+		}
+	}
+}
+
+/// The current time in seconds since the Unix epoch.
+fn now() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |elapsed| elapsed.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn var(var: ComputedVar) -> VarWithSource {
+		VarWithSource {
+			source: PathBuf::from("/repo/.tramp.toml"),
+			var,
+		}
+	}
+
+	#[test]
+	fn test_var_references_and_interpolate() {
+		let text = "s/^deploy$/deploy --branch ${vars.branch} --tag ${vars.branch}-${vars.sha}/";
+		assert_eq!(var_references(text), ["branch", "sha"]);
+
+		let values = HashMap::from([("branch".to_string(), "main".to_string())]);
+		assert_eq!(
+			interpolate(text, &values),
+			"s/^deploy$/deploy --branch main --tag main-${vars.sha}/"
+		);
+		assert_eq!(interpolate("${HOME}/${vars.x", &values), "${HOME}/${vars.x");
+	}
+
+	#[test]
+	fn test_interpolate_rule_computes_only_referenced_vars() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let vars = BTreeMap::from([
+			(
+				"greeting".to_string(),
+				var(ComputedVar {
+					cmd: Some(vec!["echo".to_string(), " hello ".to_string()]),
+					..Default::default()
+				}),
+			),
+			(
+				"broken".to_string(),
+				var(ComputedVar {
+					cmd: Some(vec!["false".to_string()]),
+					..Default::default()
+				}),
+			),
+		]);
+		let rule = Rule {
+			arg_rewrite: Some("s/^hi$/${vars.greeting}/".to_string()),
+			pre_hook: Some(PathBuf::from("echo ${vars.greeting}")),
+			..Default::default()
+		};
+
+		// `broken` is never computed, since the rule doesn't use it
+		let rule = interpolate_rule(&rule, &vars, temp_dir.path()).unwrap();
+		assert_eq!(rule.arg_rewrite.as_deref(), Some("s/^hi$/hello/"));
+		assert_eq!(rule.pre_hook, Some(PathBuf::from("echo hello")));
+
+		let uses_broken = Rule {
+			alternate_command: Some("${vars.broken}".to_string()),
+			..Default::default()
+		};
+		let err = interpolate_rule(&uses_broken, &vars, temp_dir.path()).unwrap_err();
+		assert!(matches!(err, TrampError::VarFailed { ref name, .. } if name == "broken"));
+		assert_eq!(
+			uses_broken.locate_error(Path::new("x"), 0, err).to_string(),
+			"Failed to compute variable `broken`: `false` exited with exit status: 1"
+		);
+
+		let unknown = Rule {
+			alternate_command: Some("${vars.missing}".to_string()),
+			..Default::default()
+		};
+		assert!(matches!(
+			interpolate_rule(&unknown, &vars, temp_dir.path()),
+			Err(TrampError::UnknownVar { .. })
+		));
+	}
+
+	#[test]
+	fn test_preview_rule_runs_nothing() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		std::fs::write(temp_dir.path().join("rust-toolchain"), "1.85.0\n").unwrap();
+		let marker = temp_dir.path().join("ran");
+		let vars = BTreeMap::from([
+			(
+				"branch".to_string(),
+				var(ComputedVar {
+					cmd: Some(vec![
+						"touch".to_string(),
+						marker.to_string_lossy().to_string(),
+					]),
+					default: Some("main".to_string()),
+					..Default::default()
+				}),
+			),
+			(
+				"toolchain".to_string(),
+				VarWithSource {
+					source: temp_dir.path().join(".tramp.toml"),
+					var: ComputedVar {
+						file: Some(PathBuf::from("rust-toolchain")),
+						..Default::default()
+					},
+				},
+			),
+		]);
+		let rule = Rule {
+			arg_rewrite: Some("s/^x$/${vars.branch} ${vars.toolchain}/".to_string()),
+			..Default::default()
+		};
+
+		let (rule, not_computed) = preview_rule(&rule, &vars, temp_dir.path()).unwrap();
+		assert_eq!(
+			rule.arg_rewrite.as_deref(),
+			Some("s/^x$/${vars.branch} 1.85.0/")
+		);
+		assert_eq!(not_computed, ["branch"]);
+		assert!(!marker.exists());
+	}
+
+	#[test]
+	fn test_evaluate_var_timeout_and_default() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let slow = ComputedVar {
+			cmd: Some(vec!["sleep".to_string(), "5".to_string()]),
+			timeout: Some(0.05),
+			..Default::default()
+		};
+
+		let started = Instant::now();
+		let reason =
+			evaluate_var(&slow, Path::new("/repo/.tramp.toml"), temp_dir.path()).unwrap_err();
+		assert!(started.elapsed() < Duration::from_secs(2));
+		assert_eq!(reason, "`sleep 5` timed out after 50ms");
+
+		let vars = BTreeMap::from([(
+			"slow".to_string(),
+			var(ComputedVar {
+				default: Some("unknown".to_string()),
+				..slow
+			}),
+		)]);
+		let rule = Rule {
+			alternate_command: Some("echo ${vars.slow}".to_string()),
+			..Default::default()
+		};
+		let rule = interpolate_rule(&rule, &vars, temp_dir.path()).unwrap();
+		assert_eq!(rule.alternate_command.as_deref(), Some("echo unknown"));
+	}
+
+	#[test]
+	fn test_evaluate_file_var_relative_to_config() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		std::fs::write(temp_dir.path().join("rust-toolchain"), "1.85.0\n").unwrap();
+		let file = ComputedVar {
+			file: Some(PathBuf::from("rust-toolchain")),
+			..Default::default()
+		};
+
+		let source = temp_dir.path().join("Cargo.toml#package.metadata.tramp");
+		assert_eq!(
+			evaluate_var(&file, &source, Path::new("/")).as_deref(),
+			Ok("1.85.0")
+		);
+	}
+}
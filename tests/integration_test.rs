@@ -95,49 +95,1568 @@ fn test_init_force_overwrites() {
 	assert!(content.contains("root = true"));
 }
 
+#[test]
+fn test_init_preset_creates_valid_config() {
+	let temp_dir = tempfile::tempdir().unwrap();
+
+	tramp_cmd()
+		.args(["init", "--preset", "ci-safe"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.success()
+		.stdout(predicate::str::contains(
+			"Created .tramp.toml from the ci-safe preset",
+		));
+
+	let content = fs::read_to_string(temp_dir.path().join(".tramp.toml")).unwrap();
+	assert!(content.contains("root-config-lookup-disable-env-var = \"CI\""));
+
+	tramp_cmd()
+		.args(["config", "validate"])
+		.current_dir(temp_dir.path())
+		.env("CI", "1")
+		.assert()
+		.success();
+
+	tramp_cmd()
+		.args(["init", "--preset", "nope"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.failure()
+		.stderr(predicate::str::contains("guardrails"));
+}
+
+#[test]
+fn test_preset_tests_pass_without_the_tools_installed() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	let empty_path = tempfile::tempdir().unwrap();
+
+	tramp_cmd()
+		.args(["init", "--preset", "guardrails"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.success();
+
+	tramp_cmd()
+		.arg("test")
+		.current_dir(temp_dir.path())
+		.env("HOME", temp_dir.path())
+		.env("PATH", empty_path.path())
+		.assert()
+		.success()
+		.stdout(predicate::str::contains(
+			"test terraform destroy asks first ... ok",
+		));
+}
+
+#[test]
+fn test_init_user_config() {
+	let home = tempfile::tempdir().unwrap();
+	let work = tempfile::tempdir().unwrap();
+
+	tramp_cmd()
+		.args(["init", "--user", "--preset", "build-logger"])
+		.current_dir(work.path())
+		.env("HOME", home.path())
+		.env_remove("XDG_CONFIG_HOME")
+		.assert()
+		.success();
+	assert!(home.path().join(".tramp.toml").exists());
+	assert!(!work.path().join(".tramp.toml").exists());
+
+	tramp_cmd()
+		.args(["init", "--user"])
+		.current_dir(work.path())
+		.env("HOME", home.path())
+		.env_remove("XDG_CONFIG_HOME")
+		.assert()
+		.failure()
+		.stderr(predicate::str::contains("already exists"));
+
+	// With XDG_CONFIG_HOME set and no ~/.tramp.toml, the XDG location is used
+	let xdg_home = tempfile::tempdir().unwrap();
+	let xdg = xdg_home.path().join("xdg");
+	tramp_cmd()
+		.args(["init", "--user"])
+		.current_dir(work.path())
+		.env("HOME", xdg_home.path())
+		.env("XDG_CONFIG_HOME", &xdg)
+		.assert()
+		.success();
+	assert!(xdg.join("tramp/config.toml").exists());
+
+	tramp_cmd()
+		.args(["config", "show"])
+		.current_dir(work.path())
+		.env("HOME", xdg_home.path())
+		.env("XDG_CONFIG_HOME", &xdg)
+		.assert()
+		.success()
+		.stdout(predicate::str::contains(
+			"tramp/config.toml (user config: loaded)",
+		));
+}
+
 // ============================================================================
 // --setup tests
 // ============================================================================
 
-#[test]
-fn test_setup_generates_script() {
+#[test]
+fn test_setup_generates_script() {
+	tramp_cmd()
+		.args(["--setup", "/usr/local/bin/cargo"])
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("#!/bin/sh"))
+		.stdout(predicate::str::contains("tramp"))
+		.stdout(predicate::str::contains("/usr/local/bin/cargo"));
+}
+
+// ============================================================================
+// config subcommand tests
+// ============================================================================
+
+#[test]
+fn test_config_validate_no_config() {
+	let temp_dir = tempfile::tempdir().unwrap();
+
+	tramp_cmd()
+		.args(["config", "validate"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("No configuration files found"));
+}
+
+#[test]
+fn test_config_validate_valid_config() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	let config_path = temp_dir.path().join(".tramp.toml");
+
+	fs::write(
+		&config_path,
+		r#"
+root = true
+
+[[rules]]
+binary_pattern = ".*/echo$"
+"#,
+	)
+	.unwrap();
+
+	tramp_cmd()
+		.args(["config", "validate"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("valid"));
+}
+
+#[test]
+fn test_config_validate_invalid_config() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	let config_path = temp_dir.path().join(".tramp.toml");
+
+	fs::write(&config_path, "invalid toml [[[").unwrap();
+
+	tramp_cmd()
+		.args(["config", "validate"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.failure();
+}
+
+#[test]
+fn test_unknown_key_is_rejected_with_suggestion() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	fs::write(
+		temp_dir.path().join(".tramp.toml"),
+		r#"
+no-external-lookup = true
+
+[[rules]]
+binary_patern = ".*/echo$"
+pre_hook = "true"
+"#,
+	)
+	.unwrap();
+
+	tramp_cmd()
+		.args(["echo", "hi"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.failure()
+		.stderr(predicate::str::contains(
+			"Unknown key `binary_patern`; did you mean `binary_pattern`?",
+		))
+		.stderr(predicate::str::contains(".tramp.toml:5:1"))
+		.stderr(predicate::str::contains("5 | binary_patern"));
+
+	tramp_cmd()
+		.args(["config", "validate"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.failure()
+		.stderr(predicate::str::contains(".tramp.toml:5:1: Unknown key"));
+}
+
+#[test]
+fn test_invalid_rule_error_points_to_location() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	fs::write(
+		temp_dir.path().join(".tramp.toml"),
+		r#"
+no-external-lookup = true
+
+[[rules]]
+binary_pattern = ".*/echo$"
+pre_hook = "true"
+
+[[rules]]
+binary_pattern = "[unclosed"
+pre_hook = "true"
+"#,
+	)
+	.unwrap();
+
+	tramp_cmd()
+		.args(["echo", "hi"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.failure()
+		.stderr(predicate::str::contains("Invalid rule 2"))
+		.stderr(predicate::str::contains(".tramp.toml:9:1"))
+		.stderr(predicate::str::contains(
+			"9 | binary_pattern = \"[unclosed\"",
+		))
+		.stderr(predicate::str::contains("unclosed character class"));
+}
+
+#[test]
+fn test_tramp_test_reports_pass_and_fail() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	fs::create_dir(temp_dir.path().join("sub")).unwrap();
+	fs::write(
+		temp_dir.path().join(".tramp.toml"),
+		r#"
+no-external-lookup = true
+
+[[rules]]
+id = "loud-echo"
+binary_pattern = ".*/echo$"
+cwd_pattern = "/sub$"
+arg_rewrite = "s/^hi$/HI/"
+pre_hook = "true"
+
+[[tests]]
+name = "echo in sub is rewritten"
+command = ["echo", "hi"]
+cwd = "sub"
+expect_rule = "loud-echo"
+expect_argv = ["echo", "HI"]
+expect_hooks = ["pre"]
+
+[[tests]]
+name = "echo at the root is untouched"
+command = ["echo", "hi"]
+expect_rule = "loud-echo"
+"#,
+	)
+	.unwrap();
+
+	tramp_cmd()
+		.arg("test")
+		.current_dir(temp_dir.path())
+		.assert()
+		.failure()
+		.stdout(predicate::str::contains("running 2 tests"))
+		.stdout(predicate::str::contains(
+			"test echo in sub is rewritten ... ok",
+		))
+		.stdout(predicate::str::contains(
+			"test echo at the root is untouched ... FAILED",
+		))
+		.stdout(predicate::str::contains(
+			"expected rule `loud-echo`, but none was chosen",
+		))
+		.stdout(predicate::str::contains(
+			"test result: FAILED. 1 passed; 1 failed",
+		));
+
+	tramp_cmd()
+		.args(["test", "rewritten"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.success()
+		.stdout(predicate::str::contains(
+			"test result: ok. 1 passed; 0 failed",
+		));
+}
+
+#[test]
+fn test_rule_editing_commands_preserve_comments() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	let config_path = temp_dir.path().join(".tramp.toml");
+	fs::write(
+		&config_path,
+		r#"# Team settings
+no-external-lookup = true # keep it local
+
+# Loud echo
+[[rules]]
+id = "loud"
+binary_pattern = ".*/echo$"
+arg_rewrite = "s/^hi$/HI/"
+"#,
+	)
+	.unwrap();
+
+	tramp_cmd()
+		.args([
+			"rule",
+			"add",
+			"--id",
+			"quiet",
+			"--binary-pattern",
+			".*/echo$",
+			"--arg-rewrite",
+			"s/^hi$/shh/",
+			"--position",
+			"1",
+		])
+		.current_dir(temp_dir.path())
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("Added rule 1 (quiet)"));
+
+	tramp_cmd()
+		.args(["echo", "hi"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.success()
+		.stdout("shh\n");
+
+	tramp_cmd()
+		.args(["rule", "disable", "quiet"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.success();
+
+	tramp_cmd()
+		.args(["echo", "hi"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.success()
+		.stdout("HI\n");
+
+	tramp_cmd()
+		.args(["config", "set", "max-depth", "3"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.success();
+
+	let content = fs::read_to_string(&config_path).unwrap();
+	assert!(content.starts_with("# Team settings\nno-external-lookup = true # keep it local\n"));
+	assert!(content.contains("# Loud echo\n[[rules]]\nid = \"loud\""));
+	assert!(content.contains("enabled = false"));
+	assert!(content.contains("max-depth = 3"));
+
+	tramp_cmd()
+		.args(["rule", "remove", "2"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.success();
+	assert!(!fs::read_to_string(&config_path).unwrap().contains("loud"));
+}
+
+#[test]
+fn test_config_migrate_leaves_current_configs_alone() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	let project = temp_dir.path().join("project");
+	fs::create_dir(&project).unwrap();
+	let config_path = project.join(".tramp.toml");
+	let content = "# Team settings\n\n[[rules]] # loud\nbinary_pattern = \".*/echo$\"\narg_rewrite = \"s/^hi$/HI/\"\n";
+	fs::write(&config_path, content).unwrap();
+
+	// Without a `version` key a config is read as version 1, which is current
+	for args in [
+		&["config", "migrate", "--check"][..],
+		&["config", "migrate"][..],
+	] {
+		tramp_cmd()
+			.args(args)
+			.current_dir(&project)
+			.env("HOME", temp_dir.path())
+			.env_remove("XDG_CONFIG_HOME")
+			.assert()
+			.success()
+			.stdout(predicate::str::contains("up to date (version 1)"));
+	}
+	assert_eq!(fs::read_to_string(&config_path).unwrap(), content);
+
+	fs::write(&config_path, "version = 99\n").unwrap();
+	tramp_cmd()
+		.args(["config", "migrate", "--check"])
+		.current_dir(&project)
+		.env("HOME", temp_dir.path())
+		.env_remove("XDG_CONFIG_HOME")
+		.assert()
+		.failure()
+		.stderr(predicate::str::contains("99"));
+}
+
+#[test]
+fn test_config_schema_matches_checked_in_copy() {
+	let output = tramp_cmd().args(["config", "schema"]).output().unwrap();
+	assert!(output.status.success());
+
+	let schema: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+	assert_eq!(schema["title"], ".tramp.toml");
+	assert_eq!(
+		String::from_utf8(output.stdout).unwrap(),
+		fs::read_to_string("schema/tramp.schema.json").unwrap()
+	);
+}
+
+#[test]
+fn test_init_local_creates_ignored_override() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	fs::write(temp_dir.path().join(".gitignore"), "target").unwrap();
+	fs::write(
+		temp_dir.path().join(".tramp.toml"),
+		"root = true\n\n[[rules]]\nbinary_pattern = \".*/echo$\"\narg_rewrite = \"s/^hi$/team/\"\n",
+	)
+	.unwrap();
+
+	tramp_cmd()
+		.args(["init", "--local", "--gitignore"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.success()
+		.stdout(predicate::str::contains(
+			"Added .tramp.local.toml to .gitignore",
+		));
+	assert_eq!(
+		fs::read_to_string(temp_dir.path().join(".gitignore")).unwrap(),
+		"target\n.tramp.local.toml\n"
+	);
+
+	tramp_cmd()
+		.args([
+			"rule",
+			"add",
+			"--local",
+			"--binary-pattern",
+			".*/echo$",
+			"--arg-rewrite",
+			"s/^hi$/mine/",
+		])
+		.current_dir(temp_dir.path())
+		.assert()
+		.success();
+
+	tramp_cmd()
+		.args(["echo", "hi"])
+		.current_dir(temp_dir.path())
+		.env("HOME", temp_dir.path().join("home"))
+		.env_remove("XDG_CONFIG_HOME")
+		.assert()
+		.success()
+		.stdout("mine\n");
+
+	// Already ignored: nothing more is added
+	tramp_cmd()
+		.args(["init", "--local", "--force", "--gitignore"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.success();
+	assert_eq!(
+		fs::read_to_string(temp_dir.path().join(".gitignore"))
+			.unwrap()
+			.matches(".tramp.local.toml")
+			.count(),
+		1
+	);
+}
+
+#[test]
+fn test_rule_uses_template_from_user_config() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	let home = temp_dir.path().join("home");
+	let repo = temp_dir.path().join("repo");
+	fs::create_dir_all(&home).unwrap();
+	fs::create_dir_all(&repo).unwrap();
+	fs::write(
+		home.join(".tramp.toml"),
+		"[templates.shout]\narg_rewrite = \"s/^hi$/HI/\"\n",
+	)
+	.unwrap();
+	fs::write(
+		repo.join(".tramp.toml"),
+		"[[rules]]\nuse = [\"shout\"]\nbinary_pattern = \".*/echo$\"\n",
+	)
+	.unwrap();
+
+	tramp_cmd()
+		.args(["echo", "hi"])
+		.current_dir(&repo)
+		.env("HOME", &home)
+		.env_remove("XDG_CONFIG_HOME")
+		.assert()
+		.success()
+		.stdout("HI\n");
+
+	fs::write(
+		repo.join(".tramp.toml"),
+		"[[rules]]\nuse = [\"whisper\"]\nbinary_pattern = \".*/echo$\"\n",
+	)
+	.unwrap();
+
+	tramp_cmd()
+		.args(["config", "validate"])
+		.current_dir(&repo)
+		.env("HOME", &home)
+		.env_remove("XDG_CONFIG_HOME")
+		.assert()
+		.failure()
+		.stderr(predicate::str::contains(
+			".tramp.toml:2:1: rule 1: use: Unknown template `whisper`",
+		));
+}
+
+#[test]
+fn test_group_rules_and_their_matchers() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	let sub = temp_dir.path().join("sub");
+	fs::create_dir_all(&sub).unwrap();
+	fs::write(
+		temp_dir.path().join(".tramp.toml"),
+		r#"
+root = true
+
+[[groups]]
+id = "echo"
+binary_pattern = ".*/echo$"
+
+[[groups.rules]]
+cwd_pattern = "/sub$"
+arg_rewrite = "s/^hi$/from sub/"
+
+[[groups.rules]]
+arg_rewrite = "s/^hi$/anywhere/"
+"#,
+	)
+	.unwrap();
+
+	tramp_cmd()
+		.args(["echo", "hi"])
+		.current_dir(&sub)
+		.assert()
+		.success()
+		.stdout("from sub\n");
+
+	tramp_cmd()
+		.args(["echo", "hi"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.success()
+		.stdout("anywhere\n");
+
+	tramp_cmd()
+		.args(["explain", "echo", "hi"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("group echo, rule 1: no match"))
+		.stdout(predicate::str::contains("pass group binary_pattern"))
+		.stdout(predicate::str::contains("group echo, rule 2: CHOSEN"));
+}
+
+#[test]
+fn test_when_sections_apply_at_load() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	fs::write(
+		temp_dir.path().join(".tramp.toml"),
+		r#"
+root = true
+
+[[rules]]
+id = "echo"
+binary_pattern = ".*/echo$"
+arg_rewrite = "s/^hi$/local/"
+
+[when.'env.TRAMP_TEST_CI']
+max-depth = 4
+
+[[when.'env.TRAMP_TEST_CI'.rules]]
+id = "echo"
+binary_pattern = ".*/echo$"
+arg_rewrite = "s/^hi$/ci/"
+"#,
+	)
+	.unwrap();
+
+	tramp_cmd()
+		.args(["echo", "hi"])
+		.current_dir(temp_dir.path())
+		.env_remove("TRAMP_TEST_CI")
+		.assert()
+		.success()
+		.stdout("local\n");
+
+	tramp_cmd()
+		.args(["echo", "hi"])
+		.current_dir(temp_dir.path())
+		.env("TRAMP_TEST_CI", "1")
+		.assert()
+		.success()
+		.stdout("ci\n");
+
+	tramp_cmd()
+		.args(["config", "show"])
+		.current_dir(temp_dir.path())
+		.env("TRAMP_TEST_CI", "1")
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("# max-depth: 4"))
+		.stdout(predicate::str::contains(
+			"# when 'env.TRAMP_TEST_CI': applied",
+		))
+		.stdout(predicate::str::contains("arg_rewrite: s/^hi$/ci/"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_computed_vars_are_lazy_and_cached() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	let cache_dir = temp_dir.path().join("cache");
+	fs::write(temp_dir.path().join("rust-toolchain"), "1.85.0\n").unwrap();
+	fs::write(
+		temp_dir.path().join(".tramp.toml"),
+		r#"
+root = true
+
+[vars.toolchain]
+file = "rust-toolchain"
+
+[vars.branch]
+cmd = ["sh", "-c", "echo ran >> runs; echo main"]
+cache-ttl = 60
+
+[vars.never]
+cmd = ["sh", "-c", "echo ran >> never-ran"]
+
+[[rules]]
+binary_pattern = ".*/echo$"
+arg_rewrite = "s/^build$/build ${vars.branch} ${vars.toolchain}/"
+"#,
+	)
+	.unwrap();
+
+	for _ in 0..2 {
+		tramp_cmd()
+			.args(["echo", "build"])
+			.current_dir(temp_dir.path())
+			.env("XDG_CACHE_HOME", &cache_dir)
+			.assert()
+			.success()
+			.stdout("build main 1.85.0\n");
+	}
+
+	// The second run used the cached value, and unused variables never run
+	assert_eq!(
+		fs::read_to_string(temp_dir.path().join("runs")).unwrap(),
+		"ran\n"
+	);
+	assert!(!temp_dir.path().join("never-ran").exists());
+	assert!(cache_dir.join("tramp/vars.json").exists());
+
+	tramp_cmd()
+		.args(["explain", "echo", "build"])
+		.current_dir(temp_dir.path())
+		.env("XDG_CACHE_HOME", &cache_dir)
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("build main 1.85.0"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_previews_do_not_run_cmd_vars() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	let cache_dir = temp_dir.path().join("cache");
+	fs::write(
+		temp_dir.path().join(".tramp.toml"),
+		r#"
+root = true
+
+[vars.branch]
+cmd = ["sh", "-c", "touch marker; echo main"]
+cache-ttl = 60
+
+[[rules]]
+id = "branch"
+binary_pattern = ".*/echo$"
+arg_rewrite = "s/^build$/build ${vars.branch}/"
+
+[[tests]]
+command = ["echo", "build"]
+expect_rule = "branch"
+"#,
+	)
+	.unwrap();
+	let marker = temp_dir.path().join("marker");
+
+	tramp_cmd()
+		.args(["explain", "echo", "build"])
+		.current_dir(temp_dir.path())
+		.env("XDG_CACHE_HOME", &cache_dir)
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("Not computed: ${vars.branch}"));
+	tramp_cmd()
+		.args(["--dry-run", "echo", "build"])
+		.current_dir(temp_dir.path())
+		.env("XDG_CACHE_HOME", &cache_dir)
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("Not computed: ${vars.branch}"));
+	tramp_cmd()
+		.arg("test")
+		.current_dir(temp_dir.path())
+		.env("XDG_CACHE_HOME", &cache_dir)
+		.assert()
+		.success();
+
+	assert!(!marker.exists(), "a preview ran a `cmd` variable");
+	assert!(!cache_dir.join("tramp/vars.json").exists());
+
+	// Once a real run has cached the value, previews use it
+	tramp_cmd()
+		.args(["echo", "build"])
+		.current_dir(temp_dir.path())
+		.env("XDG_CACHE_HOME", &cache_dir)
+		.assert()
+		.success()
+		.stdout("build main\n");
+	tramp_cmd()
+		.args(["--dry-run", "echo", "build"])
+		.current_dir(temp_dir.path())
+		.env("XDG_CACHE_HOME", &cache_dir)
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("build main"))
+		.stdout(predicate::str::contains("Not computed").not());
+}
+
+#[test]
+fn test_env_rules_come_first_unless_disabled() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	let home_dir = temp_dir.path().join("home");
+	let project_dir = temp_dir.path().join("project");
+	fs::create_dir_all(&home_dir).unwrap();
+	fs::create_dir_all(&project_dir).unwrap();
+	fs::write(
+		project_dir.join(".tramp.toml"),
+		"root = true\n\n[[rules]]\nbinary_pattern = \".*/echo$\"\narg_rewrite = \"s/^hi$/file/\"\n",
+	)
+	.unwrap();
+
+	let env_rules = r#"[{"binary_pattern": ".*/echo$", "arg_rewrite": "s/^hi$/env/"}]"#;
+	let run = |args: &[&str]| {
+		let mut cmd = tramp_cmd();
+		cmd.args(args)
+			.current_dir(&project_dir)
+			.env("HOME", &home_dir)
+			.env_remove("XDG_CONFIG_HOME")
+			.env("TRAMP_RULES", env_rules);
+		cmd
+	};
+
+	run(&["echo", "hi"]).assert().success().stdout("env\n");
+
+	run(&["explain", "echo", "hi"])
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("<env:TRAMP_RULES>"));
+
+	run(&["config", "show"])
+		.assert()
+		.success()
+		.stdout(predicate::str::contains(
+			"<env:TRAMP_RULES> (loaded: $TRAMP_RULES)",
+		));
+
+	// The user config can turn the variable off
+	fs::write(home_dir.join(".tramp.toml"), "no-env-rules = true\n").unwrap();
+	run(&["echo", "hi"]).assert().success().stdout("file\n");
+
+	run(&["config", "show"])
+		.assert()
+		.success()
+		.stdout(predicate::str::contains(
+			"<env:TRAMP_RULES> skipped: no-env-rules = true in",
+		));
+}
+
+#[test]
+fn test_expired_rules_are_skipped_and_pruned() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	let home_dir = temp_dir.path().join("home");
+	let cache_dir = temp_dir.path().join("cache");
+	let project_dir = temp_dir.path().join("project");
+	fs::create_dir_all(&home_dir).unwrap();
+	fs::create_dir_all(&project_dir).unwrap();
+
+	let soon = jiff::Zoned::now().date().tomorrow().unwrap();
+	fs::write(
+		project_dir.join(".tramp.toml"),
+		format!(
+			r#"root = true
+
+[[rules]]
+id = "old"
+binary_pattern = ".*/echo$"
+arg_rewrite = "s/^hi$/expired/"
+expires = 2020-01-01
+
+[[rules]]
+id = "soon"
+binary_pattern = ".*/echo$"
+arg_rewrite = "s/^hi$/current/"
+expires = {soon}
+"#
+		),
+	)
+	.unwrap();
+
+	let run = |args: &[&str]| {
+		let mut cmd = tramp_cmd();
+		cmd.args(args)
+			.current_dir(&project_dir)
+			.env("HOME", &home_dir)
+			.env("XDG_CACHE_HOME", &cache_dir)
+			.env_remove("XDG_CONFIG_HOME")
+			.env_remove("TRAMP_RULES");
+		cmd
+	};
+
+	// Warned about once, then skipped quietly
+	run(&["echo", "hi"])
+		.assert()
+		.success()
+		.stdout("current\n")
+		.stderr(predicate::str::contains(
+			".tramp.toml: expired on 2020-01-01; `tramp rule prune` deletes it",
+		));
+	run(&["echo", "hi"])
+		.assert()
+		.success()
+		.stdout("current\n")
+		.stderr(predicate::str::contains("Warning").not());
+
+	run(&["config", "validate"])
+		.assert()
+		.stderr(predicate::str::contains("Rule expired on 2020-01-01"))
+		.stderr(predicate::str::contains(format!(
+			"Rule expires on {soon}, in 1 day(s)"
+		)));
+
+	run(&["rule", "prune"])
+		.assert()
+		.success()
+		.stdout(predicate::str::contains(
+			"Removed rule old (expired on 2020-01-01)",
+		))
+		.stdout(predicate::str::contains("Pruned 1 expired rule(s)"));
+
+	let content = fs::read_to_string(project_dir.join(".tramp.toml")).unwrap();
+	assert!(!content.contains("id = \"old\""));
+	assert!(content.contains("id = \"soon\""));
+
+	run(&["rule", "prune"])
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("No expired rules"));
+}
+
+#[test]
+fn test_disable_mutes_a_rule_on_this_machine() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	let home_dir = temp_dir.path().join("home");
+	let data_dir = temp_dir.path().join("data");
+	let project_dir = temp_dir.path().join("project");
+	fs::create_dir_all(&home_dir).unwrap();
+	fs::create_dir_all(&project_dir).unwrap();
+	fs::write(
+		project_dir.join(".tramp.toml"),
+		"root = true\n\n[[rules]]\nid = \"noisy\"\nbinary_pattern = \".*/echo$\"\narg_rewrite = \"s/^hi$/rewritten/\"\n",
+	)
+	.unwrap();
+
+	let run = |args: &[&str]| {
+		let mut cmd = tramp_cmd();
+		cmd.args(args)
+			.current_dir(&project_dir)
+			.env("HOME", &home_dir)
+			.env("XDG_DATA_HOME", &data_dir)
+			.env_remove("XDG_CONFIG_HOME")
+			.env_remove("TRAMP_RULES");
+		cmd
+	};
+
+	run(&["disable", "noisy", "--for", "2h"])
+		.assert()
+		.success()
+		.stdout(predicate::str::starts_with("Muted rule noisy until "));
+	assert!(data_dir.join("tramp/muted.json").exists());
+
+	run(&["echo", "hi"]).assert().success().stdout("hi\n");
+	run(&["config", "show"])
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("muted: until "))
+		.stdout(predicate::str::contains("`tramp enable noisy` unmutes"));
+	run(&["explain", "echo", "hi"])
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("rule 1 (noisy): muted until "));
+
+	// The config file itself is untouched
+	let content = fs::read_to_string(project_dir.join(".tramp.toml")).unwrap();
+	assert!(!content.contains("enabled"));
+
+	run(&["enable", "noisy"])
+		.assert()
+		.success()
+		.stdout("Unmuted rule noisy\n");
+	run(&["echo", "hi"])
+		.assert()
+		.success()
+		.stdout("rewritten\n");
+
+	run(&["disable", "noisy", "--for", "0m"])
+		.assert()
+		.failure()
+		.stderr(predicate::str::contains("positive duration"));
+	run(&["disable", "missing"])
+		.assert()
+		.success()
+		.stderr(predicate::str::contains(
+			"no rule with id `missing` applies here",
+		))
+		.stdout("Muted rule missing until `tramp enable`\n");
+}
+
+#[test]
+fn test_bypass_and_skips() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	fs::write(
+		temp_dir.path().join(".tramp.toml"),
+		r#"no-external-lookup = true
+
+[[rules]]
+id = "first"
+binary_pattern = ".*/sh$"
+post_hook = "/bin/echo"
+
+[[rules]]
+id = "second"
+binary_pattern = ".*/sh$"
+arg_rewrite = "s/^-c true$/-c false/"
+"#,
+	)
+	.unwrap();
+
+	let run = |args: &[&str]| {
+		let mut cmd = tramp_cmd();
+		cmd.args(args)
+			.current_dir(temp_dir.path())
+			.env_remove("TRAMP_BYPASS")
+			.env_remove("TRAMP_SKIP_RULES")
+			.env_remove("TRAMP_SKIP_HOOKS");
+		cmd
+	};
+
+	// The post-hook (echo) prints a blank line
+	run(&["sh", "-c", "exit 3"]).assert().code(3).stdout("\n");
+	run(&["sh", "-c", "exit 3"])
+		.env("TRAMP_SKIP_HOOKS", "post")
+		.assert()
+		.code(3)
+		.stdout("");
+	run(&["sh", "-c", "true"]).assert().code(0);
+	run(&["sh", "-c", "true"])
+		.env("TRAMP_SKIP_RULES", "first")
+		.assert()
+		.code(1);
+	run(&["sh", "-c", "exit 3"])
+		.env("TRAMP_SKIP_HOOKS", "later")
+		.assert()
+		.failure()
+		.stderr(predicate::str::contains("Invalid $TRAMP_SKIP_HOOKS"));
+
+	// Bypass doesn't even read the config
+	fs::write(temp_dir.path().join(".tramp.toml"), "invalid toml [[[").unwrap();
+	run(&["sh", "-c", "exit 3"]).assert().failure().code(1);
+	run(&["--bypass", "sh", "-c", "exit 3"])
+		.assert()
+		.code(3)
+		.stdout("");
+	run(&["sh", "-c", "exit 5"])
+		.env("TRAMP_BYPASS", "1")
+		.assert()
+		.code(5);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_run_wraps_commands_named_like_subcommands() {
+	use std::os::unix::fs::PermissionsExt;
+
+	let temp_dir = tempfile::tempdir().unwrap();
+	let bin_dir = temp_dir.path().join("bin");
+	fs::create_dir_all(&bin_dir).unwrap();
+	let tool = bin_dir.join("config");
+	fs::write(&tool, "#!/bin/sh\necho \"config: $*\"\n").unwrap();
+	fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+
+	fs::write(
+		temp_dir.path().join(".tramp.toml"),
+		r#"no-external-lookup = true
+
+[[rules]]
+id = "log"
+binary_pattern = ".*/config$"
+post_hook = "/bin/echo"
+"#,
+	)
+	.unwrap();
+	fs::write(
+		temp_dir.path().join("other.toml"),
+		"[[rules]]\nbinary_pattern = \".*/config$\"\narg_rewrite = \"s/^a$/b/\"\n",
+	)
+	.unwrap();
+
+	let path = format!(
+		"{}:{}",
+		bin_dir.display(),
+		std::env::var("PATH").unwrap_or_default()
+	);
+	let run = |args: &[&str]| {
+		let mut cmd = tramp_cmd();
+		cmd.args(args)
+			.current_dir(temp_dir.path())
+			.env("PATH", &path)
+			.env_remove("TRAMP_BYPASS")
+			.env_remove("TRAMP_SKIP_RULES")
+			.env_remove("TRAMP_SKIP_HOOKS");
+		cmd
+	};
+
+	// Flags after the command belong to the command
+	run(&["run", "config", "--verbose"])
+		.assert()
+		.success()
+		.stdout("config: --verbose\n\n")
+		.stderr("");
+	run(&["exec", "--verbose", "--", "config", "-v"])
+		.assert()
+		.success()
+		.stdout("config: -v\n\n")
+		.stderr(predicate::str::contains("tramp: rule: rule 1 in"))
+		.stderr(predicate::str::contains("tramp: running "));
+
+	run(&["run", "--dry-run", "config", "a"])
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("(log)"))
+		.stdout(predicate::str::contains("config a\n"))
+		.stdout(predicate::str::contains("  post: /bin/echo"))
+		.stdout(predicate::str::contains("config: ").not());
+
+	// --config replaces the whole cascade
+	run(&["run", "--config", "other.toml", "config", "a"])
+		.assert()
+		.success()
+		.stdout("config: b\n");
+
+	// The bare form keeps working for other commands
+	run(&["--dry-run", "echo", "hi"])
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("Rule: none"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_subcommand_names_need_tramp_run() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	fs::write(temp_dir.path().join(".tramp.toml"), "root = true\n").unwrap();
+
+	// `-f` isn't a flag of `tramp test`: that's an error, not `/usr/bin/test -f`
+	tramp_cmd()
+		.args(["--dry-run", "test", "-f", ".tramp.toml"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.code(2)
+		.stdout("")
+		.stderr(predicate::str::contains("unexpected argument '-f'"))
+		.stderr(predicate::str::contains(
+			"use `tramp run --dry-run -- test -f .tramp.toml`",
+		));
+	tramp_cmd()
+		.args(["run", "--", "test", "-f", ".tramp.toml"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.success()
+		.stdout("")
+		.stderr("");
+
+	// Arguments tramp's subcommands accept still go to them
+	tramp_cmd()
+		.arg("test")
+		.current_dir(temp_dir.path())
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("running 0 tests"));
+
+	// Mistakes in a subcommand without a real command of that name get no tip
+	tramp_cmd()
+		.args(["config", "shw"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.failure()
+		.stderr(predicate::str::contains("unrecognized subcommand 'shw'"))
+		.stderr(predicate::str::contains("tramp run").not());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_symlink_named_after_tool_wraps_it() {
+	use std::os::unix::fs::PermissionsExt;
+
+	let temp_dir = tempfile::tempdir().unwrap();
+	let shim_dir = temp_dir.path().join("shims");
+	let real_dir = temp_dir.path().join("real");
+	fs::create_dir_all(&shim_dir).unwrap();
+	fs::create_dir_all(&real_dir).unwrap();
+
+	let real = real_dir.join("tool");
+	fs::write(&real, "#!/bin/sh\necho \"real: $*\"\nexit 3\n").unwrap();
+	fs::set_permissions(&real, fs::Permissions::from_mode(0o755)).unwrap();
+	let shim = shim_dir.join("tool");
+	std::os::unix::fs::symlink(assert_cmd::cargo::cargo_bin("tramp"), &shim).unwrap();
+
+	fs::write(
+		temp_dir.path().join(".tramp.toml"),
+		"no-external-lookup = true\n\n[[rules]]\nbinary_pattern = \".*/real/tool$\"\narg_rewrite = \"s/^a$/b/\"\n",
+	)
+	.unwrap();
+
+	let path = format!(
+		"{}:{}:{}",
+		shim_dir.display(),
+		real_dir.display(),
+		std::env::var("PATH").unwrap_or_default()
+	);
+	let run = |path: &str, args: &[&str]| {
+		let mut cmd = assert_cmd::Command::new(&shim);
+		cmd.args(args)
+			.current_dir(temp_dir.path())
+			.env("PATH", path)
+			.env_remove("TRAMP_BYPASS")
+			.env_remove("TRAMP_SKIP_RULES");
+		cmd
+	};
+
+	// All arguments go to the tool, tramp's own flags included
+	run(&path, &["a"]).assert().code(3).stdout("real: b\n");
+	run(&path, &["--help"])
+		.assert()
+		.code(3)
+		.stdout("real: --help\n");
+
+	// Without a real tool further down PATH, the shim never runs itself
+	let shims_only = format!("{}:/usr/bin:/bin", shim_dir.display());
+	run(&shims_only, &["a"])
+		.assert()
+		.failure()
+		.stderr(predicate::str::contains("Command not found: tool"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_shim_install_list_remove_doctor() {
+	use std::os::unix::fs::PermissionsExt;
+
+	let temp_dir = tempfile::tempdir().unwrap();
+	let data_dir = temp_dir.path().join("data");
+	let real_dir = temp_dir.path().join("real");
+	let shim_dir = data_dir.join("tramp/shims");
+	fs::create_dir_all(&real_dir).unwrap();
+	for tool in ["mytool", "othertool"] {
+		let path = real_dir.join(tool);
+		fs::write(&path, "#!/bin/sh\necho \"real: $*\"\n").unwrap();
+		fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+	}
+	fs::write(
+		temp_dir.path().join(".tramp.toml"),
+		"no-external-lookup = true\n\n[[rules]]\nbinary_pattern = \".*/real/mytool$\"\narg_rewrite = \"s/^a$/b/\"\n",
+	)
+	.unwrap();
+
+	let path_without_shims = format!("{}:/usr/bin:/bin", real_dir.display());
+	let path_with_shims = format!("{}:{}", shim_dir.display(), path_without_shims);
+	let run = |path: &str, args: &[&str]| {
+		let mut cmd = tramp_cmd();
+		cmd.args(args)
+			.current_dir(temp_dir.path())
+			.env("PATH", path)
+			.env("XDG_DATA_HOME", &data_dir)
+			.env_remove("TRAMP_SHIM_DIRS");
+		cmd
+	};
+
+	run(&path_without_shims, &["shim", "install", "--from-config"])
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("Installed shim mytool -> "))
+		.stdout(predicate::str::contains("is not on PATH yet"))
+		.stdout(predicate::str::contains("othertool").not());
+	run(&path_without_shims, &["shim", "install", "othertool"])
+		.assert()
+		.success();
+	run(&path_without_shims, &["shim", "install", "missing"])
+		.assert()
+		.failure()
+		.stderr(predicate::str::contains("No `missing` on PATH to wrap"));
+
+	run(&path_without_shims, &["shim", "doctor"])
+		.assert()
+		.code(1)
+		.stdout(predicate::str::contains("problem: not on PATH"));
+	run(&path_with_shims, &["shim", "doctor"])
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("mytool -> "))
+		.stdout(predicate::str::contains("No problems found"));
+
+	// The installed shim wraps the real tool
+	assert_cmd::Command::new(shim_dir.join("mytool"))
+		.arg("a")
+		.current_dir(temp_dir.path())
+		.env("PATH", &path_with_shims)
+		.env("XDG_DATA_HOME", &data_dir)
+		.assert()
+		.success()
+		.stdout("real: b\n");
+
+	// A shim whose command is gone is stale; links to other programs and
+	// other files are not tramp's to remove
+	fs::remove_file(real_dir.join("othertool")).unwrap();
+	std::os::unix::fs::symlink(real_dir.join("mytool"), shim_dir.join("python")).unwrap();
+	std::os::unix::fs::symlink("/nonexistent/other", shim_dir.join("gone")).unwrap();
+	fs::write(shim_dir.join("notes"), "").unwrap();
+	run(&path_with_shims, &["shim", "list"])
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("othertool (stale: "))
+		.stdout(predicate::str::contains("python (not a link to tramp)"))
+		.stdout(predicate::str::contains("gone (not a link to tramp)"));
+	run(&path_with_shims, &["shim", "doctor"])
+		.assert()
+		.code(1)
+		.stdout(predicate::str::contains(
+			"python: problem: not a link to tramp",
+		));
+	run(&path_with_shims, &["shim", "remove", "--stale"])
+		.assert()
+		.success()
+		.stdout("Removed shim othertool\n");
+
+	// A failure doesn't stop the other removals
+	run(&path_with_shims, &["shim", "remove", "python", "mytool"])
+		.assert()
+		.code(1)
+		.stdout("Removed shim mytool\n")
+		.stderr(predicate::str::contains("not a tramp shim"));
+	assert!(shim_dir.join("python").symlink_metadata().is_ok());
+	for name in ["python", "gone", "notes"] {
+		fs::remove_file(shim_dir.join(name)).unwrap();
+	}
+	run(&path_with_shims, &["shim", "list"])
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("No shims in"));
+}
+
+#[test]
+fn test_config_embedded_in_cargo_toml() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	fs::write(
+		temp_dir.path().join("Cargo.toml"),
+		r#"[package]
+name = "demo"
+version = "0.1.0"
+
+[package.metadata.tramp]
+root = true
+
+[[package.metadata.tramp.rules]]
+binary_pattern = ".*/echo$"
+arg_rewrite = "s/^hi$/HI/"
+"#,
+	)
+	.unwrap();
+
+	tramp_cmd()
+		.args(["echo", "hi"])
+		.current_dir(temp_dir.path())
+		.env("HOME", temp_dir.path())
+		.env_remove("XDG_CONFIG_HOME")
+		.assert()
+		.success()
+		.stdout("HI\n");
+
+	tramp_cmd()
+		.args(["config", "show"])
+		.current_dir(temp_dir.path())
+		.env("HOME", temp_dir.path())
+		.env_remove("XDG_CONFIG_HOME")
+		.assert()
+		.success()
+		.stdout(predicate::str::contains(
+			"Cargo.toml#package.metadata.tramp (loaded)",
+		));
+
+	tramp_cmd()
+		.args(["rule", "add", "--file", "Cargo.toml#package.metadata.tramp"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.failure()
+		.stderr(predicate::str::contains("edit it directly"));
+}
+
+#[test]
+fn test_config_newer_than_binary_is_rejected() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	fs::write(
+		temp_dir.path().join(".tramp.toml"),
+		"version = 99\nroot = true\nfuture_feature = true\n",
+	)
+	.unwrap();
+
+	tramp_cmd()
+		.args(["echo", "hi"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.failure()
+		.stderr(predicate::str::contains("uses config version 99"))
+		.stderr(predicate::str::contains("Upgrade tramp"));
+}
+
+#[test]
+fn test_rule_editing_rejects_invalid_results() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	let config_path = temp_dir.path().join(".tramp.toml");
+	let original = "root = true\n";
+	fs::write(&config_path, original).unwrap();
+
+	tramp_cmd()
+		.args(["rule", "add", "--binary-pattern", "[unclosed"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.failure()
+		.stderr(predicate::str::contains("unclosed character class"));
+
+	tramp_cmd()
+		.args(["rule", "add", "--binary-pattern", ".*/echo$"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.success();
+
+	tramp_cmd()
+		.args(["config", "set", "max_depth", "3"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.failure()
+		.stderr(predicate::str::contains("nothing was written"))
+		.stderr(predicate::str::contains("did you mean `max-depth`?"));
+
+	tramp_cmd()
+		.args([
+			"rule",
+			"add",
+			"--arg-rewrite",
+			"s/a/b/",
+			"--alternate-command",
+			"sh",
+		])
+		.current_dir(temp_dir.path())
+		.assert()
+		.failure()
+		.stderr(predicate::str::contains("Mutually exclusive"));
+
+	tramp_cmd()
+		.args(["rule", "enable", "missing"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.failure()
+		.stderr(predicate::str::contains(
+			"No rule with id or number `missing`",
+		));
+
+	assert_eq!(
+		fs::read_to_string(&config_path).unwrap(),
+		"root = true\n\n[[rules]]\nbinary_pattern = \".*/echo$\"\n"
+	);
+}
+
+#[test]
+fn test_config_show_displays_config() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	let config_path = temp_dir.path().join(".tramp.toml");
+
+	fs::write(
+		&config_path,
+		r#"
+root = true
+
+[[rules]]
+binary_pattern = ".*/cargo$"
+arg_rewrite = "s/build/build --release/"
+"#,
+	)
+	.unwrap();
+
 	tramp_cmd()
-		.args(["--setup", "/usr/local/bin/cargo"])
+		.args(["config", "show"])
+		.current_dir(temp_dir.path())
 		.assert()
 		.success()
-		.stdout(predicate::str::contains("#!/bin/sh"))
-		.stdout(predicate::str::contains("tramp"))
-		.stdout(predicate::str::contains("/usr/local/bin/cargo"));
+		.stdout(predicate::str::contains("binary_pattern"))
+		.stdout(predicate::str::contains("arg_rewrite"));
 }
 
-// ============================================================================
-// config subcommand tests
-// ============================================================================
-
 #[test]
-fn test_config_validate_no_config() {
+fn test_config_show_discovery_tree() {
 	let temp_dir = tempfile::tempdir().unwrap();
+	let sub_dir = temp_dir.path().join("sub");
+	fs::create_dir(&sub_dir).unwrap();
+	fs::write(
+		temp_dir.path().join(".tramp.toml"),
+		"root = true\nroot-config-lookup-disable-env-var = \"TRAMP_TEST_SKIP_USER\"\n",
+	)
+	.unwrap();
 
 	tramp_cmd()
-		.args(["config", "validate"])
-		.current_dir(temp_dir.path())
+		.args(["config", "show"])
+		.current_dir(&sub_dir)
+		.env("TRAMP_TEST_SKIP_USER", "1")
 		.assert()
 		.success()
-		.stdout(predicate::str::contains("No configuration files found"));
+		.stdout(predicate::str::contains("(not found)"))
+		.stdout(predicate::str::contains("(loaded)"))
+		.stdout(predicate::str::contains(
+			"root = true: stopped the directory walk",
+		))
+		.stdout(predicate::str::contains(
+			"user config skipped: $TRAMP_TEST_SKIP_USER is truthy",
+		));
 }
 
 #[test]
-fn test_config_validate_valid_config() {
+fn test_config_show_discovery_json() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	fs::write(
+		temp_dir.path().join(".tramp.toml"),
+		"no-external-lookup = true\n",
+	)
+	.unwrap();
+
+	let output = tramp_cmd()
+		.args(["config", "show", "--format", "json"])
+		.current_dir(temp_dir.path())
+		.output()
+		.unwrap();
+	assert!(output.status.success());
+
+	let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+	let discovery = &json["discovery"];
+	assert_eq!(discovery["searched"][0]["found"], true);
+	assert_eq!(discovery["cascade_end"]["reason"], "no-external-lookup");
+	assert_eq!(
+		discovery["user_config"]["status"],
+		"skipped-by-no-external-lookup"
+	);
+}
+
+#[test]
+fn test_config_show_effective_rules_with_provenance() {
 	let temp_dir = tempfile::tempdir().unwrap();
 	let config_path = temp_dir.path().join(".tramp.toml");
+	fs::write(
+		&config_path,
+		r#"
+no-external-lookup = true
+
+[[rules]]
+binary_pattern = ".*/npm$"
+
+[[rules]]
+binary_pattern = ".*/cargo$"
+arg_rewrite = "s/build/build --release/"
+"#,
+	)
+	.unwrap();
+
+	let output = tramp_cmd()
+		.args(["config", "show", "--format", "json"])
+		.current_dir(temp_dir.path())
+		.output()
+		.unwrap();
+	assert!(output.status.success());
+
+	let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+	assert_eq!(json["schema_version"], 1);
+	let rule = &json["effective"]["rules"][1];
+	assert_eq!(rule["source"], config_path.to_string_lossy().as_ref());
+	assert_eq!(rule["source_index"], 1);
+	assert_eq!(rule["rule"]["binary_pattern"], ".*/cargo$");
+	assert!(rule["rule"].get("alternate_command").is_none());
+
+	let output = tramp_cmd()
+		.args(["config", "show", "--format", "toml"])
+		.current_dir(temp_dir.path())
+		.output()
+		.unwrap();
+	assert!(output.status.success());
+
+	let toml_value: toml::Value = toml::from_str(&String::from_utf8_lossy(&output.stdout)).unwrap();
+	assert_eq!(toml_value["schema_version"].as_integer(), Some(1));
+	assert_eq!(
+		toml_value["effective"]["rules"][0]["rule"]["binary_pattern"].as_str(),
+		Some(".*/npm$")
+	);
+}
 
+#[test]
+fn test_config_validate_structured_diagnostics() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	let config_path = temp_dir.path().join(".tramp.toml");
 	fs::write(
 		&config_path,
 		r#"
-root = true
+no-external-lookup = true
 
 [[rules]]
-binary_pattern = ".*/echo$"
+binary_pattern = ".*/cargo$"
+arg_rewrite = "s/^build$/build --release/"
+
+[[rules]]
+arg_rewrite = "s/foo/bar/"
+command_rewrite = "s/baz/qux/"
 "#,
 	)
 	.unwrap();
@@ -146,48 +1665,164 @@ binary_pattern = ".*/echo$"
 		.args(["config", "validate"])
 		.current_dir(temp_dir.path())
 		.assert()
-		.success()
-		.stdout(predicate::str::contains("valid"));
+		.failure()
+		.stderr(predicate::str::contains("rule 2: command_rewrite"));
+
+	let output = tramp_cmd()
+		.args(["config", "validate", "--format", "json"])
+		.current_dir(temp_dir.path())
+		.output()
+		.unwrap();
+	assert!(!output.status.success());
+
+	let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+	let diagnostic = &json["diagnostics"][0];
+	assert_eq!(diagnostic["severity"], "error");
+	assert_eq!(diagnostic["file"], config_path.to_string_lossy().as_ref());
+	assert_eq!(diagnostic["rule_index"], 1);
+	assert_eq!(diagnostic["field"], "command_rewrite");
 }
 
 #[test]
-fn test_config_validate_invalid_config() {
+fn test_config_validate_lints_rules() {
 	let temp_dir = tempfile::tempdir().unwrap();
-	let config_path = temp_dir.path().join(".tramp.toml");
+	fs::write(
+		temp_dir.path().join(".tramp.toml"),
+		r#"
+no-external-lookup = true
 
-	fs::write(&config_path, "invalid toml [[[").unwrap();
+[[rules]]
+binary_pattern = ".*/cargo$"
+arg_rewrite = "s/(unclosed/x/"
+pre_hook = "./missing-hook.sh"
+"#,
+	)
+	.unwrap();
 
 	tramp_cmd()
 		.args(["config", "validate"])
 		.current_dir(temp_dir.path())
 		.assert()
-		.failure();
+		.code(1)
+		.stderr(predicate::str::contains(
+			"rule 1: arg_rewrite: Invalid substitution",
+		))
+		.stderr(predicate::str::contains("rule 1: pre_hook: Hook not found"));
 }
 
 #[test]
-fn test_config_show_displays_config() {
+fn test_config_validate_strict_warnings_exit_code() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	fs::write(
+		temp_dir.path().join(".tramp.toml"),
+		r#"
+no-external-lookup = true
+
+[[rules]]
+arg_rewrite = "s/a/b/"
+
+[[rules]]
+binary_pattern = ".*/cargo$"
+arg_rewrite = "s/c/d/"
+"#,
+	)
+	.unwrap();
+
+	// Warnings alone don't fail validation...
+	tramp_cmd()
+		.args(["config", "validate"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("(1 warning(s))"))
+		.stderr(predicate::str::contains("warning: "))
+		.stderr(predicate::str::contains(
+			"shadowed by earlier catch-all rule",
+		));
+
+	// ...unless --strict is set
+	tramp_cmd()
+		.args(["config", "validate", "--strict"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.code(2);
+}
+
+// ============================================================================
+// explain subcommand tests
+// ============================================================================
+
+#[cfg(unix)]
+#[test]
+fn test_explain_traces_rules_without_running() {
 	let temp_dir = tempfile::tempdir().unwrap();
 	let config_path = temp_dir.path().join(".tramp.toml");
+	let marker_path = temp_dir.path().join("intercepted");
 
 	fs::write(
 		&config_path,
-		r#"
+		format!(
+			r#"
 root = true
 
 [[rules]]
 binary_pattern = ".*/cargo$"
-arg_rewrite = "s/build/build --release/"
+
+[[rules]]
+binary_pattern = ".*/echo$"
+arg_rewrite = "s/hello/goodbye/"
+intercept_hook = "touch {}"
 "#,
+			marker_path.to_string_lossy()
+		),
 	)
 	.unwrap();
 
 	tramp_cmd()
-		.args(["config", "show"])
+		.args(["explain", "echo", "hello"])
 		.current_dir(temp_dir.path())
 		.assert()
 		.success()
-		.stdout(predicate::str::contains("binary_pattern"))
-		.stdout(predicate::str::contains("arg_rewrite"));
+		.stdout(predicate::str::contains("rule 1: no match"))
+		.stdout(predicate::str::contains("FAIL binary_pattern"))
+		.stdout(predicate::str::contains("rule 2: CHOSEN"))
+		.stdout(predicate::str::contains("goodbye"))
+		.stdout(predicate::str::contains("intercept: touch"));
+
+	assert!(!marker_path.exists(), "explain must not run hooks");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_explain_json_output() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	let config_path = temp_dir.path().join(".tramp.toml");
+
+	fs::write(
+		&config_path,
+		r#"
+root = true
+
+[[rules]]
+binary_pattern = ".*/echo$"
+arg_rewrite = "s/hello/goodbye/"
+"#,
+	)
+	.unwrap();
+
+	let output = tramp_cmd()
+		.args(["explain", "--format", "json", "echo", "hello", "--flag"])
+		.current_dir(temp_dir.path())
+		.output()
+		.unwrap();
+	assert!(output.status.success());
+
+	let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+	assert_eq!(json["chosen_rule"], 0);
+	assert_eq!(json["args"], serde_json::json!(["hello", "--flag"]));
+	assert_eq!(json["final_args"], serde_json::json!(["goodbye", "--flag"]));
+	assert_eq!(json["rules"][0]["matchers"][0]["matcher"], "binary_pattern");
+	assert_eq!(json["rules"][0]["matchers"][0]["passed"], true);
 }
 
 // ============================================================================
@@ -229,6 +1864,91 @@ fn test_command_with_args() {
 		.stdout(predicate::str::contains("foo bar"));
 }
 
+/// Build a PATH with `dir` in front of the current PATH.
+#[cfg(unix)]
+fn path_with(dir: &std::path::Path) -> std::ffi::OsString {
+	let mut paths = vec![dir.to_path_buf()];
+	paths.extend(std::env::split_paths(&std::env::var_os("PATH").unwrap()));
+	std::env::join_paths(paths).unwrap()
+}
+
+#[cfg(unix)]
+#[test]
+fn test_resolve_skips_tramp_shims_on_path() {
+	use std::os::unix::fs::PermissionsExt;
+
+	let temp_dir = tempfile::tempdir().unwrap();
+	let shim_path = temp_dir.path().join("echo");
+
+	// A trampoline for `echo` that sits on PATH before the real one
+	let setup = tramp_cmd().args(["--setup", "echo"]).output().unwrap();
+	fs::write(&shim_path, setup.stdout).unwrap();
+	fs::set_permissions(&shim_path, fs::Permissions::from_mode(0o755)).unwrap();
+
+	tramp_cmd()
+		.args(["echo", "hello"])
+		.current_dir(temp_dir.path())
+		.env("PATH", path_with(temp_dir.path()))
+		.timeout(std::time::Duration::from_secs(10))
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("hello"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_resolve_skips_non_executable_files() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	fs::write(temp_dir.path().join("echo"), "not a program").unwrap();
+
+	tramp_cmd()
+		.args(["echo", "hello"])
+		.current_dir(temp_dir.path())
+		.env("PATH", path_with(temp_dir.path()))
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("hello"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_recursion_limit_stops_loops() {
+	use std::os::unix::fs::PermissionsExt;
+
+	let temp_dir = tempfile::tempdir().unwrap();
+	let loop_path = temp_dir.path().join("loopy");
+	let tramp_path = assert_cmd::cargo::cargo_bin("tramp");
+
+	// A hand-written wrapper that calls back into tramp with its own name
+	fs::write(
+		&loop_path,
+		format!(
+			"#!/bin/sh\nexec \"{}\" loopy \"$@\"\n",
+			tramp_path.display()
+		),
+	)
+	.unwrap();
+	fs::set_permissions(&loop_path, fs::Permissions::from_mode(0o755)).unwrap();
+	fs::write(
+		temp_dir.path().join(".tramp.toml"),
+		"root = true\nmax-depth = 3\n",
+	)
+	.unwrap();
+
+	tramp_cmd()
+		.args(["loopy"])
+		.current_dir(temp_dir.path())
+		.env("PATH", path_with(temp_dir.path()))
+		.env_remove("TRAMP_DEPTH")
+		.env_remove("TRAMP_CHAIN")
+		.timeout(std::time::Duration::from_secs(10))
+		.assert()
+		.failure()
+		.stderr(predicate::str::contains("Recursion limit reached"))
+		.stderr(predicate::str::contains("max-depth = 3"))
+		.stderr(predicate::str::contains("loopy -> "));
+}
+
 // ============================================================================
 // Rule matching and rewriting tests (Unix only)
 // ============================================================================
@@ -314,6 +2034,67 @@ arg_rewrite = "s/build/test/"
 		.stdout(predicate::str::contains("build"));
 }
 
+#[cfg(target_os = "linux")]
+#[test]
+fn test_parent_process_pattern() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	let config_path = temp_dir.path().join(".tramp.toml");
+
+	// The first rule requires a parent that never exists; the second matches the
+	// test harness, which is tramp's parent process here
+	fs::write(
+		&config_path,
+		r#"
+root = true
+
+[[rules]]
+binary_pattern = ".*/echo$"
+parent_process_pattern = "no-such-parent-process"
+arg_rewrite = "s/hello/wrong/"
+
+[[rules]]
+binary_pattern = ".*/echo$"
+parent_process_pattern = "integration_test"
+arg_rewrite = "s/hello/goodbye/"
+"#,
+	)
+	.unwrap();
+
+	tramp_cmd()
+		.args(["echo", "hello"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("goodbye"));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_ancestor_exclude_pattern() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	let config_path = temp_dir.path().join(".tramp.toml");
+
+	fs::write(
+		&config_path,
+		r#"
+root = true
+
+[[rules]]
+binary_pattern = ".*/echo$"
+ancestor_exclude_pattern = "integration_test"
+arg_rewrite = "s/hello/goodbye/"
+"#,
+	)
+	.unwrap();
+
+	tramp_cmd()
+		.args(["echo", "hello"])
+		.current_dir(temp_dir.path())
+		.assert()
+		.success()
+		.stdout(predicate::str::contains("hello"));
+}
+
 // ============================================================================
 // Hook tests (Unix only - hooks use shell scripts)
 // ============================================================================
@@ -585,6 +2366,46 @@ intercept_hook = "{}"
 		.code(77);
 }
 
+#[test]
+fn test_dry_run_leaves_out_post_hook_replaced_by_intercept_hook() {
+	let temp_dir = tempfile::tempdir().unwrap();
+	fs::write(
+		temp_dir.path().join(".tramp.toml"),
+		r#"
+root = true
+
+[[rules]]
+binary_pattern = ".*/echo$"
+pre_hook = "/hooks/pre.sh"
+intercept_hook = "/hooks/intercept.sh"
+post_hook = "/hooks/post.sh"
+"#,
+	)
+	.unwrap();
+
+	tramp_cmd()
+		.args(["--dry-run", "echo", "hi"])
+		.current_dir(temp_dir.path())
+		.env_remove("TRAMP_SKIP_HOOKS")
+		.assert()
+		.success()
+		.stdout(predicate::str::contains(
+			"Hooks:\n  pre: /hooks/pre.sh\n  intercept: /hooks/intercept.sh\n",
+		))
+		.stdout(predicate::str::contains("post:").not());
+
+	// With the intercept hook skipped, the command runs and so does the post-hook
+	tramp_cmd()
+		.args(["--dry-run", "echo", "hi"])
+		.current_dir(temp_dir.path())
+		.env("TRAMP_SKIP_HOOKS", "intercept")
+		.assert()
+		.success()
+		.stdout(predicate::str::contains(
+			"Hooks:\n  pre: /hooks/pre.sh\n  post: /hooks/post.sh\n",
+		));
+}
+
 #[cfg(unix)]
 #[test]
 fn test_hook_receives_env_vars() {
@@ -605,7 +2426,9 @@ fn test_hook_receives_env_vars() {
 echo "TRAMP_ORIGINAL_ARGS=$TRAMP_ORIGINAL_ARGS" >> {}
 echo "TRAMP_CWD=$TRAMP_CWD" >> {}
 echo "TRAMP_HOOK_TYPE=$TRAMP_HOOK_TYPE" >> {}
+echo "TRAMP_PARENT_PID=$TRAMP_PARENT_PID" >> {}
 "#,
+			env_file.to_string_lossy(),
 			env_file.to_string_lossy(),
 			env_file.to_string_lossy(),
 			env_file.to_string_lossy()
@@ -639,4 +2462,5 @@ pre_hook = "{}"
 	assert!(env_content.contains("TRAMP_ORIGINAL_ARGS=arg1 arg2"));
 	assert!(env_content.contains(&format!("TRAMP_CWD={}", temp_path.to_string_lossy())));
 	assert!(env_content.contains("TRAMP_HOOK_TYPE=pre"));
+	assert!(env_content.contains(&format!("TRAMP_PARENT_PID={}", std::process::id())));
 }